@@ -0,0 +1,301 @@
+/// Making replacements without the interface (see `--replace`).
+use std::io::Write;
+use std::path::Path;
+
+use anyhow::Result;
+
+use crate::journal::Journal;
+use crate::model::{pattern_text, PatternTexts, ReplacementCriteria, ReplacementOutput};
+use crate::replace::{diff_files, diff_output, replace_files, FileReplacement, FileWrite};
+use crate::rg::de::RgMessage;
+use crate::ui::line::Item;
+
+/// The exit code used when some of the matches could not be replaced.
+pub const EXIT_CODE_SKIPPED: i32 = 2;
+
+/// Creates `ReplacementCriteria` which replaces every match found by `rg` with `text`.
+pub fn select_all(text: &str, rg_messages: Vec<RgMessage>) -> ReplacementCriteria {
+    let items = rg_messages
+        .into_iter()
+        .enumerate()
+        .filter(|(_, rg_message)| !matches!(rg_message, RgMessage::Summary { .. }))
+        .map(|(i, rg_message)| Item::new(i, rg_message))
+        .collect();
+
+    ReplacementCriteria::new(text, items)
+}
+
+/// Replaces the matches of each pattern in `pattern_texts` with the text for that pattern (see `--replace-pattern`).
+/// Unless `replace_others` is set, the matches of any other patterns are deselected.
+pub fn select_patterns(
+    criteria: &mut ReplacementCriteria,
+    pattern_texts: PatternTexts,
+    replace_others: bool,
+) {
+    for item in &mut criteria.items {
+        item.attribute_patterns(&criteria.matcher);
+        if replace_others {
+            continue;
+        }
+
+        for idx in 0..item.sub_items().len() {
+            let sub_item = &item.sub_items()[idx];
+            if pattern_text(sub_item, &criteria.matcher, &pattern_texts).is_none() {
+                item.set_should_replace(idx, false);
+            }
+        }
+    }
+
+    criteria.set_pattern_texts(pattern_texts);
+}
+
+/// Makes the replacements and prints a summary for each file. If a diff is being written, then it's written to
+/// the file at `diff_path` (or STDOUT) and the summary is printed to STDERR instead.
+/// If a `journal` is given, then the original contents of each file written are recorded in it.
+/// Returns whether every match was replaced.
+pub fn run(
+    criteria: &ReplacementCriteria,
+    dry_run: bool,
+    diff_path: Option<&Path>,
+    journal: Option<&mut Journal>,
+) -> Result<bool> {
+    let (summaries, dry_run) = match criteria.output {
+        ReplacementOutput::Files => (replace_files(criteria, dry_run, journal), dry_run),
+        ReplacementOutput::Diff => {
+            let mut out = diff_output(diff_path)?;
+            let summaries = diff_files(criteria, &mut out);
+            out.flush()?;
+            (summaries, true)
+        }
+    };
+
+    let to_stderr = criteria.output == ReplacementOutput::Diff;
+    for summary in &summaries {
+        if to_stderr || summary.error.is_some() {
+            eprintln!("{}", format_summary(summary, dry_run));
+        } else {
+            println!("{}", format_summary(summary, dry_run));
+        }
+    }
+
+    if to_stderr {
+        eprintln!("{}", format_total(&summaries, dry_run));
+    } else {
+        println!("{}", format_total(&summaries, dry_run));
+    }
+
+    Ok(summaries.iter().all(FileReplacement::is_complete))
+}
+
+fn format_summary(summary: &FileReplacement, dry_run: bool) -> String {
+    if let Some(e) = &summary.error {
+        return format!("{}: failed: {}", summary.path, e);
+    }
+
+    let mut line = format!(
+        "{}: {} {}",
+        summary.path,
+        summary.replaced,
+        if dry_run { "to replace" } else { "replaced" }
+    );
+    if summary.skipped > 0 {
+        line.push_str(&format!(", {} skipped", summary.skipped));
+    }
+    // How the replacements were made, and how the file was written.
+    let how = summary
+        .strategy
+        .map(|s| s.to_string())
+        .into_iter()
+        .chain(summary.written.as_ref().map(FileWrite::to_string))
+        .collect::<Vec<_>>();
+    if !how.is_empty() {
+        line.push_str(&format!(" ({})", how.join(", ")));
+    }
+
+    line
+}
+
+fn format_total(summaries: &[FileReplacement], dry_run: bool) -> String {
+    let replaced = summaries.iter().map(|s| s.replaced).sum::<usize>();
+    let skipped = summaries.iter().map(|s| s.skipped).sum::<usize>();
+    let failed = summaries.iter().filter(|s| s.error.is_some()).count();
+
+    let mut line = format!(
+        "{} {} in {} file(s)",
+        replaced,
+        if dry_run { "to replace" } else { "replaced" },
+        summaries.len() - failed
+    );
+    if skipped > 0 {
+        line.push_str(&format!(", {} skipped", skipped));
+    }
+    if failed > 0 {
+        line.push_str(&format!(", {} file(s) failed", failed));
+    }
+    if dry_run {
+        line.push_str(" (dry run, no files were written)");
+    }
+
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use anyhow::anyhow;
+    use pretty_assertions::assert_eq;
+
+    use crate::batch::{format_summary, format_total, select_all, select_patterns};
+    use crate::model::{Matcher, MatcherOptions, PatternTexts};
+    use crate::replace::{FileReplacement, FileWrite, ReplaceStrategy, WriteStrategy};
+    use crate::rg::de::test_utilities::*;
+    use crate::rg::de::{RgMessage, RgMessageKind, SubMatch};
+
+    fn summary(path: &str, replaced: usize, skipped: usize, failed: bool) -> FileReplacement {
+        FileReplacement {
+            path: path.to_owned(),
+            replaced,
+            skipped,
+            error: if failed { Some(anyhow!("oops")) } else { None },
+            strategy: None,
+            written: None,
+        }
+    }
+
+    #[test]
+    fn it_selects_all_matches() {
+        let criteria = select_all(
+            "foo",
+            vec![
+                RgMessage::from_str(RG_JSON_BEGIN),
+                RgMessage::from_str(RG_JSON_MATCH),
+                RgMessage::from_str(RG_JSON_CONTEXT),
+                RgMessage::from_str(RG_JSON_END),
+                RgMessage::from_str(RG_JSON_SUMMARY),
+            ],
+        );
+
+        assert_eq!(criteria.text, "foo");
+        assert_eq!(criteria.items.len(), 4);
+        assert!(criteria
+            .items
+            .iter()
+            .filter(|i| i.kind == RgMessageKind::Match)
+            .all(|i| i.get_should_replace_all()));
+    }
+
+    #[test]
+    fn it_selects_the_matches_of_patterns() {
+        let rg_messages = || {
+            vec![RgMessageBuilder::new(RgMessageKind::Match)
+                .with_path_text("a")
+                .with_offset(0)
+                .with_lines_text("foo bar baz\n")
+                .with_submatches(vec![
+                    SubMatch::new_text("foo", 0..3),
+                    SubMatch::new_text("bar", 4..7),
+                    SubMatch::new_text("baz", 8..11),
+                ])
+                .build()]
+        };
+        let pattern_texts = PatternTexts::from([(String::from("ba."), String::from("qux"))]);
+        let matcher = Matcher::new(&["foo", "ba."], MatcherOptions::default());
+
+        let mut criteria = select_all("", rg_messages());
+        criteria.set_matcher(matcher.clone());
+        select_patterns(&mut criteria, pattern_texts.clone(), false);
+        let item = &criteria.items[0];
+        assert_eq!(
+            item.sub_items()
+                .iter()
+                .map(|s| (s.pattern, s.should_replace))
+                .collect::<Vec<_>>(),
+            vec![(Some(0), false), (Some(1), true), (Some(1), true)]
+        );
+
+        let mut criteria = select_all("x", rg_messages());
+        criteria.set_matcher(matcher);
+        select_patterns(&mut criteria, pattern_texts, true);
+        let item = &criteria.items[0];
+        assert!(item.get_should_replace_all());
+        assert_eq!(
+            item.replacement_text(
+                &item.sub_items()[1],
+                &criteria.matcher,
+                &criteria.text,
+                &criteria.pattern_texts,
+                false
+            ),
+            "qux"
+        );
+    }
+
+    #[test]
+    fn it_formats_file_summaries() {
+        assert_eq!(
+            format_summary(&summary("a", 2, 0, false), false),
+            "a: 2 replaced"
+        );
+        assert_eq!(
+            format_summary(&summary("a", 2, 1, false), false),
+            "a: 2 replaced, 1 skipped"
+        );
+        assert_eq!(
+            format_summary(&summary("a", 2, 0, false), true),
+            "a: 2 to replace"
+        );
+        assert_eq!(
+            format_summary(&summary("a", 0, 0, true), false),
+            "a: failed: oops"
+        );
+
+        let mut written = summary("a", 2, 0, false);
+        written.written = Some(FileWrite {
+            strategy: WriteStrategy::TempFile,
+            symlink_target: None,
+        });
+        assert_eq!(
+            format_summary(&written, false),
+            "a: 2 replaced (via temporary file)"
+        );
+        written.written = Some(FileWrite {
+            strategy: WriteStrategy::InPlace(3),
+            symlink_target: Some(PathBuf::from("b")),
+        });
+        assert_eq!(
+            format_summary(&written, false),
+            "a: 2 replaced (in place, 3 hard links, through symlink to b)"
+        );
+
+        let mut spliced = summary("a", 2, 0, false);
+        spliced.strategy = Some(ReplaceStrategy::Bytes("utf-8"));
+        assert_eq!(
+            format_summary(&spliced, true),
+            "a: 2 to replace (spliced bytes, not valid utf-8)"
+        );
+        spliced.strategy = Some(ReplaceStrategy::Decoded("utf-16le"));
+        spliced.written = written.written;
+        assert_eq!(
+            format_summary(&spliced, false),
+            "a: 2 replaced (decoded as utf-16le, in place, 3 hard links, through symlink to b)"
+        );
+    }
+
+    #[test]
+    fn it_formats_totals() {
+        let summaries = vec![
+            summary("a", 2, 0, false),
+            summary("b", 1, 3, false),
+            summary("c", 0, 0, true),
+        ];
+        assert_eq!(
+            format_total(&summaries, false),
+            "3 replaced in 2 file(s), 3 skipped, 1 file(s) failed"
+        );
+        assert_eq!(
+            format_total(&summaries[..1], true),
+            "2 to replace in 1 file(s) (dry run, no files were written)"
+        );
+    }
+}
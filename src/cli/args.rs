@@ -5,17 +5,11 @@
 ///
 /// We do use some of this information, for instance the `encoding` is sniffed from the argument
 /// parsing we do here.
-use std::env;
 use std::ffi::OsString;
 use std::path::PathBuf;
 
 use clap::{crate_authors, crate_version};
-use clap::{ArgAction, Parser};
-
-// TODO: options to support in the future
-// -P/--pcre2
-// -F/--fixed-strings
-// -f/--file
+use clap::{ArgAction, ArgGroup, CommandFactory, Parser};
 
 /// See `rg --help` for more detailed information on each of the flags passed.
 ///
@@ -24,6 +18,10 @@ use clap::{ArgAction, Parser};
 #[clap(
   version = crate_version!(),
   author = crate_authors!(),
+  // Like `rg`, later flags override earlier ones (e.g.: those from a config file).
+  args_override_self = true,
+  // Either of these replaces the matches without starting the interface.
+  group = ArgGroup::new("replacement").args(["replace", "replace_patterns"]).multiple(true),
 )]
 pub struct Args {
     //
@@ -45,6 +43,15 @@ pub struct Args {
         number_of_values = 1
     )]
     pub patterns: Vec<String>,
+    /// Read patterns from a file, one per line. Can be used with -e/--regexp, and more than once.
+    #[clap(
+        short = 'f',
+        long = "file",
+        value_name = "PATTERNFILE",
+        num_args = 1..,
+        number_of_values = 1
+    )]
+    pub pattern_files: Vec<PathBuf>,
 
     // FLAGS
     /// How many lines of context should be shown after each match.
@@ -62,6 +69,9 @@ pub struct Args {
     /// Provide the encoding to use when searching files.
     #[clap(short = 'E', long = "encoding")]
     pub encoding: Option<String>,
+    /// Treat the patterns as literal strings rather than regular expressions.
+    #[clap(short = 'F', long = "fixed-strings")]
+    pub fixed_strings: bool,
     /// Follow symlinks.
     #[clap(short = 'L', long = "follow")]
     pub follow_symlinks: bool,
@@ -108,6 +118,12 @@ pub struct Args {
         number_of_values = 1
     )]
     pub type_not: Vec<String>,
+    /// Add a new glob for a file type (e.g.: "web:*.{html,css}").
+    #[clap(long = "type-add", value_name = "TYPESPEC", num_args = 1.., number_of_values = 1)]
+    pub type_add: Vec<String>,
+    /// Clear the file type globs for the given type.
+    #[clap(long = "type-clear", value_name = "TYPE", num_args = 1.., number_of_values = 1)]
+    pub type_clear: Vec<String>,
     /// Set the "unrestricted" searching options for ripgrep.
     /// Note that this is currently limited to only two occurrences `-uu` since
     /// binary searching is not supported in repgrep.
@@ -122,6 +138,9 @@ pub struct Args {
     /// When matching, use a word boundary search.
     #[clap(short = 'w', long = "word-regexp")]
     pub word_regexp: bool,
+    /// Use the PCRE2 regex engine, which supports look-around and backreferences.
+    #[clap(short = 'P', long = "pcre2")]
+    pub pcre2: bool,
 
     // FILES & IGNORES
     /// A list of globs to match files.
@@ -147,26 +166,306 @@ pub struct Args {
     /// Don't traverse filesystems for each path specified.
     #[clap(long = "one-file-system")]
     pub one_file_system: bool,
+
+    // CONFIG
+    /// Don't read the config file given by the RIPGREP_CONFIG_PATH environment variable. Otherwise, the
+    /// arguments in it are checked in the same way and used before the command line arguments.
+    #[clap(long = "no-config")]
+    pub no_config: bool,
+
+    //
+    // REPGREP ARGUMENTS
+    //
+    // NOTE: these are not passed to ripgrep, so each one must also be added to `RGR_ONLY_FLAGS`.
+    //
+    /// Preserve the case of each match when replacing it (e.g.: replacing "foo" with "bar" will
+    /// replace "Foo" with "Bar" and "FOO" with "BAR"). This can also be toggled in the replacement
+    /// view with <control+p>.
+    #[clap(long = "preserve-case")]
+    pub preserve_case: bool,
+    /// Replace every match with the given text without starting the interface.
+    /// This requires either --yes, --dry-run or --diff. A summary of the replacements made in each file is printed,
+    /// and if any matches could not be replaced then the exit code is 2.
+    #[clap(long = "replace", value_name = "TEXT")]
+    pub replace: Option<String>,
+    /// Replace the matches of PATTERN with TEXT (rather than the text given by --replace), where PATTERN is one of
+    /// the patterns searched for, e.g.: `-e foo -e bar --replace-pattern foo=baz --replace-pattern bar=qux`.
+    /// This can be used more than once, and like --replace the matches are replaced without starting the interface.
+    /// If --replace isn't given, then the matches of any other patterns are not replaced.
+    #[clap(long = "replace-pattern", value_name = "PATTERN=TEXT")]
+    pub replace_patterns: Vec<String>,
+    /// Write the replacements given by --replace without asking for confirmation.
+    #[clap(long = "yes", requires = "replacement")]
+    pub yes: bool,
+    /// Print a summary of the replacements given by --replace without writing any files.
+    #[clap(long = "dry-run", requires = "replacement")]
+    pub dry_run: bool,
+    /// Write either every file or none of them. All files are patched and staged before any are written, so if any
+    /// match can't be replaced no files are written, and if writing a file fails then the files already written are
+    /// restored.
+    #[clap(long = "all-or-nothing", conflicts_with_all = ["rename", "undo"])]
+    pub all_or_nothing: bool,
+    /// Write a unified diff of the replacements to FILE (or STDOUT if no FILE is given) rather than changing any
+    /// files. The diff can be applied later with `git apply` or `patch -p1`. In the interface, this can also be
+    /// toggled when confirming the replacements with <d>.
+    #[clap(
+        long = "diff",
+        value_name = "FILE",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "-"
+    )]
+    pub diff: Option<PathBuf>,
+    /// What to do with each line containing selected matches: substitute the matches with the replacement text (the
+    /// default), delete the line, or insert the replacement text as a new line above or below it. This can also be
+    /// changed in the replacement view with <control+o>.
+    #[clap(
+        long = "operation",
+        value_name = "OPERATION",
+        value_parser = ["substitute", "delete-line", "insert-above", "insert-below"],
+        conflicts_with_all = ["rename", "undo"]
+    )]
+    pub operation: Option<String>,
+    /// Rename the files whose paths match the pattern, rather than replacing the matches in their contents.
+    /// The files are listed with `rg --files`, and the matches in each path are replaced to make its new path.
+    /// Existing files are never overwritten, and any missing directories are created.
+    #[clap(long = "rename", conflicts_with = "undo")]
+    pub rename: bool,
+    /// Undo the last run which replaced matches, restoring the original contents of each file it changed.
+    /// Files which have been changed since they were replaced are not restored, and if there are any the exit
+    /// code is 2.
+    #[clap(long = "undo", conflicts_with = "replacement")]
+    pub undo: bool,
+    /// Don't highlight the syntax of the lines in the interface (which is done by the extension of each file).
+    /// Highlighting can be slow when the lines are very long, such as in minified files.
+    #[clap(long = "no-highlight")]
+    pub no_highlight: bool,
+
+    /// The arguments these were parsed from (including the program name), which are passed down to ripgrep.
+    #[clap(skip)]
+    pub raw_args: Vec<OsString>,
+    /// The path of the ripgrep config file which was used, if any.
+    #[clap(skip)]
+    pub config_path: Option<PathBuf>,
+    /// The arguments read from the ripgrep config file, which are passed down before `raw_args`.
+    #[clap(skip)]
+    pub config_args: Vec<OsString>,
+}
+
+/// Flags which are only understood by repgrep, and so must not be passed down to ripgrep.
+const RGR_ONLY_FLAGS: &[&str] = &[
+    "--preserve-case",
+    "--replace",
+    "--replace-pattern",
+    "--yes",
+    "--dry-run",
+    "--all-or-nothing",
+    "--diff",
+    "--operation",
+    "--rename",
+    "--undo",
+    "--no-highlight",
+];
+
+/// Removes any repgrep-only flags (and their values) from the given arguments.
+fn strip_rgr_args<I: IntoIterator<Item = OsString>>(args: I) -> Vec<OsString> {
+    partition_rgr_args(args).0
+}
+
+/// Returns the names of any repgrep-only flags in the given arguments.
+pub fn rgr_only_flags<I: IntoIterator<Item = OsString>>(args: I) -> Vec<String> {
+    partition_rgr_args(args).1
+}
+
+/// Separates the given arguments into those which are passed down to ripgrep, and the names of the repgrep-only
+/// flags (whose values are dropped).
+fn partition_rgr_args<I: IntoIterator<Item = OsString>>(args: I) -> (Vec<OsString>, Vec<String>) {
+    let value_flags = ValueFlags::new();
+    let mut rg_args = vec![];
+    let mut rgr_flags = vec![];
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        // Everything after `--` is a pattern or a path, so pass it all through.
+        if arg == "--" {
+            rg_args.push(arg);
+            rg_args.extend(args);
+            break;
+        }
+
+        let flag = arg.to_string_lossy().into_owned();
+        let name = flag.split_once('=').map_or(flag.as_str(), |(name, _)| name);
+        // NOTE: the value of a flag is never itself a flag (e.g. `-e --yes` searches for "--yes").
+        let value = if value_flags.value_follows(&flag) {
+            args.next()
+        } else {
+            None
+        };
+        if RGR_ONLY_FLAGS.contains(&name) {
+            rgr_flags.push(name.to_string());
+        } else {
+            rg_args.push(arg);
+            rg_args.extend(value);
+        }
+    }
+
+    (rg_args, rgr_flags)
+}
+
+/// The flags which take their value from the next argument (unless it's attached, such as `-C2` or `--context=2`),
+/// as declared to clap. These are needed to tell the values of flags apart from other arguments.
+struct ValueFlags {
+    longs: Vec<String>,
+    shorts: Vec<char>,
+}
+
+impl ValueFlags {
+    fn new() -> ValueFlags {
+        let mut command = Args::command();
+        command.build();
+        let value_args = command
+            .get_arguments()
+            .filter(|arg| {
+                arg.get_num_args().is_some_and(|n| n.takes_values()) && !arg.is_require_equals_set()
+            })
+            .collect::<Vec<_>>();
+
+        ValueFlags {
+            longs: value_args
+                .iter()
+                .filter_map(|arg| arg.get_long())
+                .map(String::from)
+                .collect(),
+            shorts: value_args
+                .iter()
+                .filter_map(|arg| arg.get_short())
+                .collect(),
+        }
+    }
+
+    /// Whether the argument after `arg` is the value of its flag.
+    fn value_follows(&self, arg: &str) -> bool {
+        if let Some(long) = arg.strip_prefix("--") {
+            !long.contains('=') && self.longs.iter().any(|name| name == long)
+        } else if let Some(shorts) = arg.strip_prefix('-') {
+            // The value of a short flag is either the rest of the argument, or the next argument.
+            match shorts.char_indices().find(|(_, c)| self.shorts.contains(c)) {
+                Some((i, c)) => i + c.len_utf8() == shorts.len(),
+                None => false,
+            }
+        } else {
+            false
+        }
+    }
+}
+
+/// Returns the indices of the positional arguments (the pattern and paths) in `args` (including the program name),
+/// along with the `--` separator if there is one.
+/// NOTE: clap's indices of the arguments don't line up with `args` if any values are attached to their flags (such
+/// as `-C2` or `--regexp=foo`), so the arguments are walked here instead.
+fn positional_indices(args: &[OsString]) -> Vec<usize> {
+    let value_flags = ValueFlags::new();
+    let mut positional = vec![];
+    let mut i = 1;
+    while i < args.len() {
+        let arg = args[i].to_string_lossy();
+        if arg == "--" {
+            positional.extend(i..args.len());
+            break;
+        }
+
+        if value_flags.value_follows(&arg) {
+            i += 1;
+        } else if arg == "-" || !arg.starts_with('-') {
+            positional.push(i);
+        }
+
+        i += 1;
+    }
+
+    positional
+}
+
+/// Replaces the positional arguments in `args` (including the program name) with `paths`, and removes any
+/// repgrep-only flags. A positional pattern is kept by passing it with `--regexp` instead.
+fn args_for_paths(args: Vec<OsString>, paths: &[PathBuf]) -> Vec<OsString> {
+    let matches = match Args::command().try_get_matches_from(args.clone()) {
+        Ok(matches) => matches,
+        // These arguments were already parsed successfully, so this shouldn't happen.
+        Err(_) => return strip_rgr_args(args.into_iter().skip(1)),
+    };
+
+    // If patterns were passed with -e/--regexp or -f/--file then the positional pattern is actually a path.
+    let pattern = match (
+        matches.indices_of("patterns"),
+        matches.indices_of("pattern_files"),
+    ) {
+        (None, None) => matches.get_one::<String>("PATTERN"),
+        _ => None,
+    };
+    let positional = positional_indices(&args);
+
+    let mut rg_args = pattern
+        .map(|p| OsString::from(format!("--regexp={}", p)))
+        .into_iter()
+        .collect::<Vec<_>>();
+    rg_args.extend(strip_rgr_args(
+        args.into_iter()
+            .enumerate()
+            .skip(1)
+            .filter(|(i, _)| !positional.contains(i))
+            .map(|(_, arg)| arg),
+    ));
+    rg_args.push(OsString::from("--"));
+    rg_args.extend(paths.iter().map(OsString::from));
+
+    rg_args
 }
 
 impl Args {
     /// Provides the command line arguments to pass down to ripgrep.
     /// At the moment this just proxies down _all_ command line arguments (excluding the program name)
-    /// directly to ripgrep. We assume that the arguments contain a supported set of flags and options
-    /// since we'll have used Parser to parse this struct and validate our program's arguments.
+    /// directly to ripgrep (except for repgrep-only flags). We assume that the arguments contain a
+    /// supported set of flags and options since we'll have used Parser to parse this struct and
+    /// validate our program's arguments.
     pub fn rg_args(&self) -> impl Iterator<Item = OsString> {
+        self.config_args
+            .clone()
+            .into_iter()
+            .chain(self.rg_cmdline_args())
+    }
+
+    /// Provides the command line arguments to pass down to ripgrep, without those from the config file.
+    pub fn rg_cmdline_args(&self) -> impl Iterator<Item = OsString> {
         // Skip the first argument, which _should_ be the binary name.
-        env::args_os().skip(1)
+        strip_rgr_args(self.raw_args.iter().skip(1).cloned()).into_iter()
+    }
+
+    /// Provides the command line arguments to pass down to ripgrep to search only the given `paths`, rather than the
+    /// paths which were originally searched.
+    pub fn rg_args_for_paths(&self, paths: &[PathBuf]) -> Vec<OsString> {
+        let mut rg_args = self.config_args.clone();
+        rg_args.extend(args_for_paths(self.raw_args.clone(), paths));
+        rg_args
+    }
+
+    /// Returns the patterns passed to `rg` on the command line (see also `Args::pattern_files`).
+    pub fn rg_patterns(&self) -> Vec<&str> {
+        if let Some(pattern) = &self.pattern {
+            vec![pattern]
+        } else {
+            self.patterns.iter().map(|p| p.as_ref()).collect()
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::ffi::OsString;
     use std::path::PathBuf;
 
     use clap::{CommandFactory, Parser};
 
-    use super::Args;
+    use super::{args_for_paths, strip_rgr_args, Args};
 
     #[test]
     fn verify_cli() {
@@ -175,13 +474,13 @@ mod tests {
 
     #[test]
     fn verify_pattern() {
-        let args = Args::parse_from(&["rgr", "foobar"]);
+        let args = Args::parse_from(["rgr", "foobar"]);
         assert_eq!(args.pattern, Some(String::from("foobar")));
     }
 
     #[test]
     fn verify_paths() {
-        let args = Args::parse_from(&["rgr", "foobar", "/tmp", "/dev"]);
+        let args = Args::parse_from(["rgr", "foobar", "/tmp", "/dev"]);
         assert_eq!(
             args.paths,
             vec![PathBuf::from("/tmp"), PathBuf::from("/dev")]
@@ -190,7 +489,7 @@ mod tests {
 
     #[test]
     fn verify_patterns() {
-        let args = Args::parse_from(&["rgr", "-e=foobar", "-e", "pattern"]);
+        let args = Args::parse_from(["rgr", "-e=foobar", "-e", "pattern"]);
         assert_eq!(
             args.patterns,
             vec![String::from("foobar"), String::from("pattern")]
@@ -199,162 +498,214 @@ mod tests {
 
     #[test]
     fn verify_after_context() {
-        let args = Args::parse_from(&["rgr", ".", "-A5"]);
+        let args = Args::parse_from(["rgr", ".", "-A5"]);
         assert_eq!(args.after_context, Some(5));
     }
 
     #[test]
     fn verify_before_context() {
-        let args = Args::parse_from(&["rgr", ".", "-B=10"]);
+        let args = Args::parse_from(["rgr", ".", "-B=10"]);
         assert_eq!(args.before_context, Some(10));
     }
 
     #[test]
     fn verify_context() {
-        let args = Args::parse_from(&["rgr", ".", "-C", "42"]);
+        let args = Args::parse_from(["rgr", ".", "-C", "42"]);
         assert_eq!(args.context, Some(42));
     }
 
     #[test]
     fn verify_crlf() {
-        let args = Args::parse_from(&["rgr", ".", "--crlf"]);
-        assert_eq!(args.crlf, true);
+        let args = Args::parse_from(["rgr", ".", "--crlf"]);
+        assert!(args.crlf);
     }
 
     #[test]
     fn verify_encoding() {
-        let args = Args::parse_from(&["rgr", ".", "-Eutf-8"]);
+        let args = Args::parse_from(["rgr", ".", "-Eutf-8"]);
         assert_eq!(args.encoding, Some(String::from("utf-8")));
-        let args = Args::parse_from(&["rgr", ".", "--encoding", "utf-16"]);
+        let args = Args::parse_from(["rgr", ".", "--encoding", "utf-16"]);
         assert_eq!(args.encoding, Some(String::from("utf-16")));
     }
 
+    #[test]
+    fn verify_fixed_strings() {
+        let args = Args::parse_from(["rgr", ".", "-F"]);
+        assert!(args.fixed_strings);
+        let args = Args::parse_from(["rgr", ".", "--fixed-strings"]);
+        assert!(args.fixed_strings);
+    }
+
+    #[test]
+    fn verify_pattern_files() {
+        let args = Args::parse_from(["rgr", "-f", "a", "--file", "b", "--file=c"]);
+        assert_eq!(
+            args.pattern_files,
+            vec![PathBuf::from("a"), PathBuf::from("b"), PathBuf::from("c")]
+        );
+    }
+
+    #[test]
+    fn verify_pcre2() {
+        let args = Args::parse_from(["rgr", ".", "-P"]);
+        assert!(args.pcre2);
+        let args = Args::parse_from(["rgr", ".", "--pcre2"]);
+        assert!(args.pcre2);
+    }
+
     #[test]
     fn verify_follow_symlinks() {
-        let args = Args::parse_from(&["rgr", ".", "-L"]);
-        assert_eq!(args.follow_symlinks, true);
-        let args = Args::parse_from(&["rgr", ".", "--follow"]);
-        assert_eq!(args.follow_symlinks, true);
+        let args = Args::parse_from(["rgr", ".", "-L"]);
+        assert!(args.follow_symlinks);
+        let args = Args::parse_from(["rgr", ".", "--follow"]);
+        assert!(args.follow_symlinks);
     }
 
     #[test]
     fn verify_ignore_case() {
-        let args = Args::parse_from(&["rgr", ".", "-i"]);
-        assert_eq!(args.ignore_case, true);
-        let args = Args::parse_from(&["rgr", ".", "--ignore-case"]);
-        assert_eq!(args.ignore_case, true);
+        let args = Args::parse_from(["rgr", ".", "-i"]);
+        assert!(args.ignore_case);
+        let args = Args::parse_from(["rgr", ".", "--ignore-case"]);
+        assert!(args.ignore_case);
     }
 
     #[test]
     fn verify_invert_match() {
-        let args = Args::parse_from(&["rgr", ".", "-v"]);
-        assert_eq!(args.invert_match, true);
-        let args = Args::parse_from(&["rgr", ".", "--invert-match"]);
-        assert_eq!(args.invert_match, true);
+        let args = Args::parse_from(["rgr", ".", "-v"]);
+        assert!(args.invert_match);
+        let args = Args::parse_from(["rgr", ".", "--invert-match"]);
+        assert!(args.invert_match);
     }
 
     #[test]
     fn verify_passthru() {
-        let args = Args::parse_from(&["rgr", ".", "--passthru"]);
-        assert_eq!(args.passthru, true);
+        let args = Args::parse_from(["rgr", ".", "--passthru"]);
+        assert!(args.passthru);
     }
 
     #[test]
     fn verify_smart_case() {
-        let args = Args::parse_from(&["rgr", ".", "-S"]);
-        assert_eq!(args.smart_case, true);
-        let args = Args::parse_from(&["rgr", ".", "--smart-case"]);
-        assert_eq!(args.smart_case, true);
+        let args = Args::parse_from(["rgr", ".", "-S"]);
+        assert!(args.smart_case);
+        let args = Args::parse_from(["rgr", ".", "--smart-case"]);
+        assert!(args.smart_case);
     }
 
     #[test]
     fn verify_case_sensitive() {
-        let args = Args::parse_from(&["rgr", ".", "-s"]);
-        assert_eq!(args.case_sensitive, true);
-        let args = Args::parse_from(&["rgr", ".", "--case-sensitive"]);
-        assert_eq!(args.case_sensitive, true);
+        let args = Args::parse_from(["rgr", ".", "-s"]);
+        assert!(args.case_sensitive);
+        let args = Args::parse_from(["rgr", ".", "--case-sensitive"]);
+        assert!(args.case_sensitive);
     }
 
     #[test]
     fn verify_sort() {
-        let args = Args::parse_from(&["rgr", ".", "--sort=path"]);
+        let args = Args::parse_from(["rgr", ".", "--sort=path"]);
         assert_eq!(args.sort, Some(String::from("path")));
     }
 
     #[test]
     fn verify_sortr() {
-        let args = Args::parse_from(&["rgr", ".", "--sortr=created"]);
+        let args = Args::parse_from(["rgr", ".", "--sortr=created"]);
         assert_eq!(args.sortr, Some(String::from("created")));
     }
 
     #[test]
     fn verify_threads() {
-        let args = Args::parse_from(&["rgr", ".", "-j12"]);
+        let args = Args::parse_from(["rgr", ".", "-j12"]);
         assert_eq!(args.threads, Some(12));
-        let args = Args::parse_from(&["rgr", ".", "--threads=4"]);
+        let args = Args::parse_from(["rgr", ".", "--threads=4"]);
         assert_eq!(args.threads, Some(4));
     }
 
     #[test]
     fn verify_trim() {
-        let args = Args::parse_from(&["rgr", ".", "--trim"]);
-        assert_eq!(args.trim, true);
+        let args = Args::parse_from(["rgr", ".", "--trim"]);
+        assert!(args.trim);
     }
 
     #[test]
     fn verify_type() {
-        let args = Args::parse_from(&["rgr", ".", "-tcss"]);
+        let args = Args::parse_from(["rgr", ".", "-tcss"]);
         assert_eq!(args.r#type, vec![String::from("css")]);
-        let args = Args::parse_from(&["rgr", ".", "-tcss", "--type=html"]);
+        let args = Args::parse_from(["rgr", ".", "-tcss", "--type=html"]);
         assert_eq!(args.r#type, vec![String::from("css"), String::from("html")]);
     }
 
     #[test]
     fn verify_type_not() {
-        let args = Args::parse_from(&["rgr", ".", "-Tcss"]);
+        let args = Args::parse_from(["rgr", ".", "-Tcss"]);
         assert_eq!(args.type_not, vec![String::from("css")]);
-        let args = Args::parse_from(&["rgr", ".", "-Tcss", "--type-not=html"]);
+        let args = Args::parse_from(["rgr", ".", "-Tcss", "--type-not=html"]);
         assert_eq!(
             args.type_not,
             vec![String::from("css"), String::from("html")]
         );
     }
 
+    #[test]
+    fn verify_type_add() {
+        let args = Args::parse_from([
+            "rgr",
+            ".",
+            "--type-add",
+            "web:*.html",
+            "--type-add=web:*.css",
+        ]);
+        assert_eq!(args.type_add, vec!["web:*.html", "web:*.css"]);
+        let args = Args::parse_from(["rgr", ".", "--type-clear", "web"]);
+        assert_eq!(args.type_clear, vec!["web"]);
+    }
+
+    #[test]
+    fn verify_no_config() {
+        let args = Args::parse_from(["rgr", ".", "--no-config"]);
+        assert!(args.no_config);
+    }
+
+    #[test]
+    fn verify_later_flags_override_earlier_ones() {
+        let args = Args::parse_from(["rgr", ".", "--sort", "path", "--sort=modified", "-i", "-i"]);
+        assert_eq!(args.sort, Some(String::from("modified")));
+        assert!(args.ignore_case);
+    }
+
     #[test]
     fn verify_unrestricted() {
-        let args = Args::parse_from(&["rgr", ".", "-u"]);
+        let args = Args::parse_from(["rgr", ".", "-u"]);
         assert_eq!(args.unrestricted, 1);
-        let args = Args::parse_from(&["rgr", ".", "-uu"]);
+        let args = Args::parse_from(["rgr", ".", "-uu"]);
         assert_eq!(args.unrestricted, 2);
-        let args = Args::parse_from(&["rgr", ".", "--unrestricted"]);
+        let args = Args::parse_from(["rgr", ".", "--unrestricted"]);
         assert_eq!(args.unrestricted, 1);
     }
 
     #[test]
     fn verify_multiline() {
-        let args = Args::parse_from(&["rgr", ".", "--multiline"]);
-        assert_eq!(args.multiline, true);
+        let args = Args::parse_from(["rgr", ".", "--multiline"]);
+        assert!(args.multiline);
     }
 
     #[test]
     fn verify_multiline_dotall() {
-        let args = Args::parse_from(&["rgr", ".", "--multiline-dotall"]);
-        assert_eq!(args.multiline_dotall, true);
+        let args = Args::parse_from(["rgr", ".", "--multiline-dotall"]);
+        assert!(args.multiline_dotall);
     }
 
     #[test]
     fn verify_word_regexp() {
-        let args = Args::parse_from(&["rgr", ".", "-w"]);
-        assert_eq!(args.word_regexp, true);
-        let args = Args::parse_from(&["rgr", ".", "--word-regexp"]);
-        assert_eq!(args.word_regexp, true);
+        let args = Args::parse_from(["rgr", ".", "-w"]);
+        assert!(args.word_regexp);
+        let args = Args::parse_from(["rgr", ".", "--word-regexp"]);
+        assert!(args.word_regexp);
     }
 
     #[test]
     fn verify_glob() {
-        let args = Args::parse_from(&["rgr", ".", "-g=asdf"]);
+        let args = Args::parse_from(["rgr", ".", "-g=asdf"]);
         assert_eq!(args.glob, vec![String::from("asdf")]);
-        let args = Args::parse_from(&["rgr", ".", "-g=asdf", "--glob", "qwerty"]);
+        let args = Args::parse_from(["rgr", ".", "-g=asdf", "--glob", "qwerty"]);
         assert_eq!(
             args.glob,
             vec![String::from("asdf"), String::from("qwerty")]
@@ -363,21 +714,21 @@ mod tests {
 
     #[test]
     fn verify_iglob() {
-        let args = Args::parse_from(&["rgr", ".", "--iglob=zxcv"]);
+        let args = Args::parse_from(["rgr", ".", "--iglob=zxcv"]);
         assert_eq!(args.iglob, vec![String::from("zxcv")]);
     }
 
     #[test]
     fn verify_hidden() {
-        let args = Args::parse_from(&["rgr", ".", "-."]);
-        assert_eq!(args.hidden, true);
-        let args = Args::parse_from(&["rgr", ".", "--hidden"]);
-        assert_eq!(args.hidden, true);
+        let args = Args::parse_from(["rgr", ".", "-."]);
+        assert!(args.hidden);
+        let args = Args::parse_from(["rgr", ".", "--hidden"]);
+        assert!(args.hidden);
     }
 
     #[test]
     fn verify_ignore_file() {
-        let args = Args::parse_from(&["rgr", ".", "--ignore-file=my/path/to/.gitignore"]);
+        let args = Args::parse_from(["rgr", ".", "--ignore-file=my/path/to/.gitignore"]);
         assert_eq!(
             args.ignore_file,
             Some(PathBuf::from("my/path/to/.gitignore"))
@@ -386,13 +737,177 @@ mod tests {
 
     #[test]
     fn verify_ignore_file_case_insensitive() {
-        let args = Args::parse_from(&["rgr", ".", "--ignore-file-case-insensitive"]);
-        assert_eq!(args.ignore_file_case_insensitive, true);
+        let args = Args::parse_from(["rgr", ".", "--ignore-file-case-insensitive"]);
+        assert!(args.ignore_file_case_insensitive);
     }
 
     #[test]
     fn verify_one_file_system() {
-        let args = Args::parse_from(&["rgr", ".", "--one-file-system"]);
-        assert_eq!(args.one_file_system, true);
+        let args = Args::parse_from(["rgr", ".", "--one-file-system"]);
+        assert!(args.one_file_system);
+    }
+
+    #[test]
+    fn verify_preserve_case() {
+        let args = Args::parse_from(["rgr", ".", "--preserve-case"]);
+        assert!(args.preserve_case);
+    }
+
+    #[test]
+    fn verify_replace() {
+        let args = Args::parse_from(["rgr", ".", "--replace", "foo", "--yes", "--dry-run"]);
+        assert_eq!(args.replace, Some(String::from("foo")));
+        assert!(args.yes);
+        assert!(args.dry_run);
+
+        assert!(Args::try_parse_from(["rgr", ".", "--yes"]).is_err());
+        assert!(Args::try_parse_from(["rgr", ".", "--dry-run"]).is_err());
+    }
+
+    #[test]
+    fn verify_replace_pattern() {
+        let args = Args::parse_from([
+            "rgr",
+            "-e=foo",
+            "-e=bar",
+            "--replace-pattern",
+            "foo=baz",
+            "--replace-pattern=bar=a=b",
+            "--yes",
+        ]);
+        assert_eq!(args.replace_patterns, vec!["foo=baz", "bar=a=b"]);
+        assert!(args.yes);
+
+        assert!(Args::try_parse_from(["rgr", "--undo", "--replace-pattern=foo=bar"]).is_err());
+    }
+
+    #[test]
+    fn verify_diff() {
+        let args = Args::parse_from(["rgr", ".", "--diff"]);
+        assert_eq!(args.diff, Some(PathBuf::from("-")));
+
+        let args = Args::parse_from(["rgr", ".", "--diff=out.patch"]);
+        assert_eq!(args.diff, Some(PathBuf::from("out.patch")));
+
+        let args = Args::parse_from(["rgr", ".", "--diff", "path"]);
+        assert_eq!(args.diff, Some(PathBuf::from("-")));
+        assert_eq!(args.paths, vec![PathBuf::from("path")]);
+    }
+
+    #[test]
+    fn verify_operation() {
+        let args = Args::parse_from(["rgr", "foo", "--operation", "delete-line"]);
+        assert_eq!(args.operation, Some(String::from("delete-line")));
+
+        assert!(Args::try_parse_from(["rgr", "foo", "--operation=delete"]).is_err());
+        assert!(
+            Args::try_parse_from(["rgr", "foo", "--operation=delete-line", "--rename"]).is_err()
+        );
+    }
+
+    #[test]
+    fn verify_all_or_nothing() {
+        let args = Args::parse_from(["rgr", "foo", "--all-or-nothing"]);
+        assert!(args.all_or_nothing);
+
+        assert!(Args::try_parse_from(["rgr", "foo", "--all-or-nothing", "--rename"]).is_err());
+    }
+
+    #[test]
+    fn verify_rename() {
+        let args = Args::parse_from(["rgr", "foo", "--rename"]);
+        assert!(args.rename);
+
+        assert!(Args::try_parse_from(["rgr", "--rename", "--undo"]).is_err());
+    }
+
+    #[test]
+    fn strips_rgr_only_flags() {
+        let args = [
+            "-i",
+            "--preserve-case",
+            "--replace",
+            "bar",
+            "--replace=baz",
+            "--replace-pattern",
+            "foo=bar",
+            "--replace-pattern=foo=baz",
+            "--yes",
+            "--all-or-nothing",
+            "--diff",
+            "--diff=out.patch",
+            "--operation",
+            "delete-line",
+            "--operation=insert-above",
+            "--rename",
+            "--no-highlight",
+            "foo",
+            "-e",
+            "--preserve-case",
+            "-g",
+            "--yes",
+            "--regexp",
+            "--replace",
+            "-ie",
+            "--rename",
+            "--",
+            "--preserve-case",
+        ]
+        .iter()
+        .map(OsString::from);
+        assert_eq!(
+            strip_rgr_args(args),
+            vec![
+                "-i",
+                "foo",
+                "-e",
+                "--preserve-case",
+                "-g",
+                "--yes",
+                "--regexp",
+                "--replace",
+                "-ie",
+                "--rename",
+                "--",
+                "--preserve-case"
+            ]
+        );
+    }
+
+    #[test]
+    fn replaces_paths_to_search_again() {
+        let search_again = |args: &[&str]| {
+            let args = args.iter().map(OsString::from).collect();
+            args_for_paths(args, &[PathBuf::from("c")])
+        };
+
+        assert_eq!(
+            search_again(&["rgr", "-i", "foo", "a", "b", "--replace", "x", "--yes"]),
+            vec!["--regexp=foo", "-i", "--", "c"]
+        );
+        assert_eq!(
+            search_again(&["rgr", "-e", "foo", "a", "-C", "2"]),
+            vec!["-e", "foo", "-C", "2", "--", "c"]
+        );
+        assert_eq!(
+            search_again(&["rgr", "-i", "--", "-foo", "a"]),
+            vec!["--regexp=-foo", "-i", "--", "c"]
+        );
+        assert_eq!(
+            search_again(&["rgr", "-e", "foo", "-i", "--", "a", "-b"]),
+            vec!["-e", "foo", "-i", "--", "c"]
+        );
+        assert_eq!(
+            search_again(&["rgr", "-F", "-f", "patterns", "a"]),
+            vec!["-F", "-f", "patterns", "--", "c"]
+        );
+        assert_eq!(
+            search_again(&["rgr", "-iC2", "--regexp=foo", "a", "-g=*.rs"]),
+            vec!["-iC2", "--regexp=foo", "-g=*.rs", "--", "c"]
+        );
+        assert_eq!(
+            search_again(&["rgr", "foo", "--diff", "a", "--diff=out.patch", "b"]),
+            vec!["--regexp=foo", "--", "c"]
+        );
     }
 }
@@ -0,0 +1,170 @@
+//! Support for ripgrep's config file, see:
+//! https://github.com/BurntSushi/ripgrep/blob/master/GUIDE.md#configuration-file
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+
+pub const ENV_CONFIG_PATH: &str = "RIPGREP_CONFIG_PATH";
+
+/// Flags which only change how `rg` prints its results, which don't matter since its JSON output is read instead.
+/// These are dropped from the config file rather than rejected, since they're common in it.
+/// Each entry is the flag name, and whether or not it takes a value.
+const DISPLAY_ONLY_FLAGS: &[(&str, bool)] = &[
+    ("--byte-offset", false),
+    ("-b", false),
+    ("--color", true),
+    ("--colors", true),
+    ("--column", false),
+    ("--no-column", false),
+    ("--context-separator", true),
+    ("--no-context-separator", false),
+    ("--field-context-separator", true),
+    ("--field-match-separator", true),
+    ("--heading", false),
+    ("--no-heading", false),
+    ("--hyperlink-format", true),
+    ("--line-number", false),
+    ("-n", false),
+    ("--no-line-number", false),
+    ("-N", false),
+    ("--max-columns", true),
+    ("-M", true),
+    ("--max-columns-preview", false),
+    ("--no-max-columns-preview", false),
+    ("--pretty", false),
+    ("-p", false),
+    // NOTE: rg's `--replace` only changes the text it prints, whereas rgr's writes files. So it's never taken from
+    // the config file, which is shared with rg.
+    ("--replace", true),
+    ("-r", true),
+    ("--with-filename", false),
+    ("-H", false),
+    ("--no-filename", false),
+    ("-I", false),
+];
+
+/// Returns the path of the config file set in the environment, if any.
+pub fn config_path() -> Option<PathBuf> {
+    env::var_os(ENV_CONFIG_PATH)
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from)
+}
+
+/// Parses the arguments in a config file. Each line is a single argument (with surrounding whitespace
+/// removed), and empty lines and lines starting with `#` are ignored.
+pub fn parse_config(contents: &str) -> Vec<OsString> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(OsString::from)
+        .collect()
+}
+
+/// Reads the arguments in the config file at `path`.
+pub fn read_config(path: &Path) -> Result<Vec<OsString>> {
+    let contents = fs::read(path)
+        .with_context(|| format!("Failed to read ripgrep config {}", path.display()))?;
+    let contents = String::from_utf8(contents)
+        .map_err(|_| anyhow!("The ripgrep config {} is not valid UTF-8", path.display()))?;
+
+    Ok(parse_config(&contents))
+}
+
+/// Removes the flags which only change how `rg` prints its results (and their values) from the arguments read from
+/// the config file at `path`.
+pub fn strip_display_args(args: Vec<OsString>, path: &Path) -> Vec<OsString> {
+    let mut kept = vec![];
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let flag = arg.to_string_lossy();
+        let (name, has_inline_value) = match flag.split_once('=') {
+            Some((name, _)) => (name, true),
+            None => (flag.as_ref(), false),
+        };
+        let display_flag = DISPLAY_ONLY_FLAGS
+            .iter()
+            .find(|(display_name, takes_value)| {
+                // The value of a short flag can also be attached to it, e.g. `-M150`.
+                *display_name == name
+                    || (*takes_value
+                        && !display_name.starts_with("--")
+                        && name.starts_with(display_name))
+            });
+
+        match display_flag {
+            Some((display_name, takes_value)) => {
+                let value = if *takes_value && !has_inline_value && name == *display_name {
+                    args.next()
+                } else {
+                    None
+                };
+                log::warn!(
+                    "Ignoring {}{} in ripgrep config {}, since it only changes how rg prints results",
+                    flag,
+                    value.map_or(String::new(), |v| format!(" {}", v.to_string_lossy())),
+                    path.display()
+                );
+            }
+            None => kept.push(arg),
+        }
+    }
+
+    kept
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsString;
+    use std::path::Path;
+
+    use pretty_assertions::assert_eq;
+
+    use super::{parse_config, strip_display_args};
+
+    #[test]
+    fn it_parses_one_argument_per_line() {
+        let config = "
+# Don't let ripgrep vomit really long lines to my terminal.
+--max-columns=150
+
+  --glob=!vendor/
+--type-add
+web:*.{html,css,js}
+";
+
+        assert_eq!(
+            parse_config(config),
+            vec![
+                "--max-columns=150",
+                "--glob=!vendor/",
+                "--type-add",
+                "web:*.{html,css,js}"
+            ]
+        );
+    }
+
+    #[test]
+    fn it_strips_flags_which_only_change_how_results_are_printed() {
+        let args = [
+            "--max-columns=150",
+            "--colors",
+            "match:fg:red",
+            "-M150",
+            "--heading",
+            "-p",
+            "--glob=!vendor/",
+            "-i",
+        ]
+        .map(OsString::from)
+        .to_vec();
+
+        assert_eq!(
+            strip_display_args(args, Path::new("config")),
+            vec!["--glob=!vendor/", "-i"]
+        );
+    }
+}
@@ -1,28 +1,52 @@
 mod args;
+mod config;
 
 use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::iter;
 use std::path::PathBuf;
 
 use anyhow::{anyhow, Result};
 use clap::{CommandFactory, Parser};
 
-use args::Args;
+use args::rgr_only_flags;
+pub use args::Args;
+
+use crate::model::{Matcher, MatcherOptions, Operation, PatternTexts, ReplacementOutput};
+use crate::rename;
+use crate::rg::search::Search;
 
 pub const ENV_JSON_FILE: &str = "RGR_JSON_FILE";
 
 /// This is where we perform our validation of the arguments.
 fn validate_arguments(mut args: Args) -> Result<Args> {
-    // Check we have a pattern.
-    if args.pattern.is_none() && args.patterns.is_empty() && env::var(ENV_JSON_FILE).is_err() {
+    // Check we have a pattern (we don't search when undoing).
+    if !args.undo
+        && args.pattern.is_none()
+        && args.patterns.is_empty()
+        && args.pattern_files.is_empty()
+        && env::var(ENV_JSON_FILE).is_err()
+    {
         return Err(anyhow!("No pattern was provided!"));
     }
 
-    // If a positional pattern was passed _and_ patterns via flags were passed, then
+    // If a positional pattern was passed _and_ patterns via flags (or files) were passed, then
     // assume that the positional pattern is a path.
-    if args.pattern.is_some() && !args.patterns.is_empty() {
+    if args.pattern.is_some() && !(args.patterns.is_empty() && args.pattern_files.is_empty()) {
         args.paths.push(PathBuf::from(args.pattern.take().unwrap()));
     }
 
+    // Replacing without the interface must be either confirmed up front, or not write any files.
+    if args.is_batch() && !args.yes && !args.dry_run && args.diff.is_none() {
+        return Err(anyhow!(
+            "--replace writes files without confirmation, pass --yes to continue (or --dry-run or --diff to preview)"
+        ));
+    }
+
+    // Check each --replace-pattern refers to one of the patterns.
+    args.pattern_texts()?;
+
     // We don't support binary searches.
     if args.unrestricted > 2 {
         log::warn!("Binary file searching is not supported. Changing -uuu to -uu");
@@ -32,26 +56,219 @@ fn validate_arguments(mut args: Args) -> Result<Args> {
     Ok(args)
 }
 
+// NOTE: this lives here rather than in `args.rs` since that file is also used by the build script.
+impl Args {
+    /// Returns all the patterns used by `rg`, including those read from any pattern files.
+    fn all_patterns(&self) -> Vec<String> {
+        let mut patterns = self
+            .rg_patterns()
+            .into_iter()
+            .map(str::to_owned)
+            .collect::<Vec<_>>();
+        for path in &self.pattern_files {
+            // NOTE: `rg` reads these from STDIN, so we can't read them as well.
+            if path.as_os_str() == "-" {
+                log::warn!("Patterns read from STDIN can't be used to expand capture groups");
+                continue;
+            }
+
+            match fs::read(path) {
+                Ok(contents) => patterns.extend(
+                    String::from_utf8_lossy(&contents)
+                        .lines()
+                        .map(str::to_owned),
+                ),
+                Err(e) => log::warn!("Failed to read patterns from {}: {}", path.display(), e),
+            }
+        }
+
+        patterns
+    }
+
+    /// Builds a `Matcher` which finds the same matches as `rg` did, so capture groups can be expanded.
+    pub fn matcher(&self) -> Matcher {
+        Matcher::new(
+            &self.all_patterns(),
+            MatcherOptions {
+                ignore_case: self.ignore_case && !self.case_sensitive,
+                smart_case: self.smart_case && !self.case_sensitive,
+                word_regexp: self.word_regexp,
+                multiline_dotall: self.multiline_dotall,
+                crlf: self.crlf,
+                fixed_strings: self.fixed_strings,
+                pcre2: self.pcre2,
+            },
+        )
+    }
+
+    /// Starts the search: either `rg` searching the contents of files, or listing the files whose paths match when
+    /// renaming them (see `--rename`).
+    pub fn search(&self) -> Result<Search> {
+        if self.rename {
+            rename::search(self)
+        } else {
+            Search::ripgrep(self.rg_args())
+        }
+    }
+
+    /// Whether the matches are replaced without the interface (see `--replace` and `--replace-pattern`).
+    pub fn is_batch(&self) -> bool {
+        self.replace.is_some() || !self.replace_patterns.is_empty()
+    }
+
+    /// Returns the replacement text for each pattern given with `--replace-pattern PATTERN=TEXT`.
+    /// Since both the pattern and the text may contain a `=`, the pattern is the first prefix before a `=` which is
+    /// one of the patterns searched for.
+    pub fn pattern_texts(&self) -> Result<PatternTexts> {
+        if self.replace_patterns.is_empty() {
+            return Ok(PatternTexts::new());
+        }
+
+        let patterns = self.all_patterns();
+        self.replace_patterns
+            .iter()
+            .map(|pair| {
+                pair.match_indices('=')
+                    .map(|(i, _)| (&pair[..i], &pair[i + 1..]))
+                    .find(|(pattern, _)| patterns.iter().any(|p| p == pattern))
+                    .map(|(pattern, text)| (pattern.to_owned(), text.to_owned()))
+                    .ok_or_else(|| {
+                        anyhow!(
+                            "--replace-pattern {}: expected PATTERN=TEXT, where PATTERN is one of the patterns searched for",
+                            pair
+                        )
+                    })
+            })
+            .collect()
+    }
+
+    /// What should be done with each line containing selected matches.
+    pub fn operation(&self) -> Operation {
+        match self.operation.as_deref() {
+            Some("delete-line") => Operation::DeleteLine,
+            Some("insert-above") => Operation::InsertAbove,
+            Some("insert-below") => Operation::InsertBelow,
+            _ => Operation::Substitute,
+        }
+    }
+
+    /// What should be done with the replacements by default.
+    pub fn replacement_output(&self) -> ReplacementOutput {
+        if self.diff.is_some() {
+            ReplacementOutput::Diff
+        } else {
+            ReplacementOutput::Files
+        }
+    }
+}
+
 /// Prints the help generated by clap.
 pub fn print_help() {
     Args::command().print_help().unwrap();
 }
 
+/// Only keeps the first line of clap's error, since it also includes the usage.
+fn clap_error(e: clap::Error) -> anyhow::Error {
+    let e = e.to_string();
+    let e = e.lines().next().unwrap_or_default();
+    anyhow!("{}", e.trim_start_matches("error: "))
+}
+
+/// If there's a ripgrep config file at `config_path` (and `--no-config` wasn't passed) then its arguments are
+/// parsed before `raw_args`, the same as `rg` would. Flags which only change how `rg` prints its results are
+/// ignored, and repgrep-only flags are rejected (since the config is shared with `rg`, it mustn't be able to make
+/// rgr write files).
+fn apply_config(args: Args, raw_args: &[OsString], config_path: Option<PathBuf>) -> Result<Args> {
+    let path = match config_path {
+        Some(path) if !args.no_config => path,
+        _ => return Ok(args),
+    };
+
+    let config_args = config::strip_display_args(config::read_config(&path)?, &path);
+    log::debug!("Read args from {}: {:?}", path.display(), config_args);
+
+    let rgr_flags = rgr_only_flags(config_args.iter().cloned());
+    if !rgr_flags.is_empty() {
+        return Err(anyhow!(
+            "Unsupported arguments in ripgrep config {}: {} can only be passed on the command line",
+            path.display(),
+            rgr_flags.join(", ")
+        ));
+    }
+
+    // Check the config file on its own first, so it's clear where any unsupported flags came from.
+    let program = OsString::from("rgr");
+    Args::try_parse_from(iter::once(&program).chain(&config_args)).map_err(|e| {
+        anyhow!(
+            "Unsupported arguments in ripgrep config {}: {}",
+            path.display(),
+            clap_error(e)
+        )
+    })?;
+
+    let mut args = Args::try_parse_from(
+        iter::once(&program)
+            .chain(&config_args)
+            .chain(raw_args.iter().skip(1)),
+    )
+    .map_err(clap_error)?;
+    args.config_path = Some(path);
+    args.config_args = config_args;
+
+    Ok(args)
+}
+
 // Parses arguments from the environment (argv, etc).
 pub fn parse_arguments() -> Result<Args> {
-    validate_arguments(Args::parse())
+    let raw_args = env::args_os().collect::<Vec<_>>();
+    let args = Args::parse_from(&raw_args);
+    let mut args = apply_config(args, &raw_args, config::config_path())?;
+    args.raw_args = raw_args;
+    validate_arguments(args)
+}
+
+/// Parses a search entered in the interface, which is written the same as the arguments to `rgr` (without the
+/// program name), and may quote arguments like a shell.
+pub fn parse_search(cmdline: &str) -> Result<Args> {
+    let words = shell_words::split(cmdline)?;
+    let raw_args = iter::once(OsString::from("rgr"))
+        .chain(words.into_iter().map(OsString::from))
+        .collect::<Vec<_>>();
+
+    let args = Args::try_parse_from(&raw_args).map_err(clap_error)?;
+    if args.is_batch() || args.operation.is_some() || args.rename || args.undo {
+        return Err(anyhow!(
+            "--replace, --replace-pattern, --operation, --rename and --undo can't be used in the interface"
+        ));
+    }
+
+    let mut args = apply_config(args, &raw_args, config::config_path())?;
+    args.raw_args = raw_args;
+    validate_arguments(args)
+}
+
+/// Formats the arguments which are passed down to `rg` so they can be edited with `parse_search`.
+/// Arguments from the config file aren't included, since they're added again when parsing.
+pub fn search_cmdline(args: &Args) -> String {
+    shell_words::join(
+        args.rg_cmdline_args()
+            .map(|s| s.to_string_lossy().into_owned()),
+    )
 }
 
 #[cfg(test)]
 mod tests {
     use std::ffi::OsString;
+    use std::io::Write;
     use std::path::PathBuf;
 
     use anyhow::Result;
     use clap::Parser;
     use pretty_assertions::assert_eq;
+    use tempfile::NamedTempFile;
 
-    use super::{validate_arguments, Args};
+    use super::{apply_config, parse_search, search_cmdline, validate_arguments, Args};
+    use crate::model::PatternTexts;
 
     /// Parses arguments from a list.
     fn parse_arguments_from<I, T>(itr: I) -> Result<Args>
@@ -62,18 +279,9 @@ mod tests {
         validate_arguments(Args::parse_from(itr))
     }
 
-    /// Returns the patterns used by `rg` in the search.
-    fn rg_patterns(args: &Args) -> Vec<&str> {
-        if let Some(pattern) = &args.pattern {
-            vec![pattern]
-        } else {
-            args.patterns.iter().map(|p| p.as_ref()).collect()
-        }
-    }
-
     #[test]
     fn checks_if_no_pattern_was_passed() {
-        let args = parse_arguments_from(&["rgr", "-E", "utf8", "-A1", "-B", "10"]);
+        let args = parse_arguments_from(["rgr", "-E", "utf8", "-A1", "-B", "10"]);
         assert!(args.is_err());
         assert_eq!(
             format!("{}", args.unwrap_err()),
@@ -83,8 +291,8 @@ mod tests {
 
     #[test]
     fn reads_pattern_as_path_if_pattern_flag_given() {
-        let args = parse_arguments_from(&["rgr", "-e", "pattern-flag", "pattern-pos", "path-pos"])
-            .unwrap();
+        let args =
+            parse_arguments_from(["rgr", "-e", "pattern-flag", "pattern-pos", "path-pos"]).unwrap();
 
         assert_eq!(args.pattern, None);
         assert_eq!(args.patterns, vec!["pattern-flag".to_owned()]);
@@ -94,15 +302,201 @@ mod tests {
         )
     }
 
+    #[test]
+    fn reads_pattern_as_path_if_pattern_file_given() {
+        let args = parse_arguments_from(["rgr", "-f", "patterns", "pattern-pos"]).unwrap();
+        assert_eq!(args.pattern, None);
+        assert_eq!(args.paths, vec![PathBuf::from("pattern-pos")]);
+    }
+
+    #[test]
+    fn reads_patterns_from_files() {
+        let mut file = NamedTempFile::new().unwrap();
+        write!(file, "a(\\d)\nb(\\d)\n").unwrap();
+
+        let args = parse_arguments_from([
+            OsString::from("rgr"),
+            OsString::from("-e"),
+            OsString::from("c(\\d)"),
+            OsString::from("-f"),
+            file.path().as_os_str().to_owned(),
+        ])
+        .unwrap();
+        assert_eq!(args.all_patterns(), vec![r"c(\d)", r"a(\d)", r"b(\d)"]);
+
+        let matcher = args.matcher();
+        assert_eq!(matcher.expand("$1", b"a1 b2 c3", 3..5), "2");
+        assert_eq!(matcher.expand("$1", b"a1 b2 c3", 6..8), "3");
+    }
+
+    #[test]
+    fn reads_arguments_from_the_config_file() {
+        let mut config = NamedTempFile::new().unwrap();
+        writeln!(
+            config,
+            "# comment\n--glob=!vendor\n--max-columns=150\n--encoding\nutf-16\n-i"
+        )
+        .unwrap();
+        let config_path = Some(config.path().to_owned());
+
+        let raw_args = ["rgr", "foo", "-E", "utf-8"].map(OsString::from).to_vec();
+        let args = Args::parse_from(&raw_args);
+        let mut args = apply_config(args, &raw_args, config_path.clone()).unwrap();
+        args.raw_args = raw_args;
+
+        // The command line arguments override the config.
+        assert_eq!(args.encoding, Some(String::from("utf-8")));
+        assert!(args.ignore_case);
+        assert_eq!(args.glob, vec!["!vendor"]);
+        assert_eq!(args.config_path, config_path);
+        assert_eq!(
+            args.rg_args().collect::<Vec<_>>(),
+            [
+                "--glob=!vendor",
+                "--encoding",
+                "utf-16",
+                "-i",
+                "foo",
+                "-E",
+                "utf-8"
+            ]
+        );
+        assert_eq!(search_cmdline(&args), "foo -E utf-8");
+
+        // The config can be ignored.
+        let raw_args = ["rgr", "foo", "--no-config"].map(OsString::from).to_vec();
+        let args = Args::parse_from(&raw_args);
+        let args = apply_config(args, &raw_args, config_path).unwrap();
+        assert!(!args.ignore_case);
+        assert_eq!(args.config_path, None);
+    }
+
+    #[test]
+    fn rejects_unsupported_arguments_in_the_config_file() {
+        let mut config = NamedTempFile::new().unwrap();
+        writeln!(config, "--colors=match:fg:red\n--max-count=1").unwrap();
+
+        let raw_args = ["rgr", "foo"].map(OsString::from).to_vec();
+        let args = Args::parse_from(&raw_args);
+        let err = apply_config(args, &raw_args, Some(config.path().to_owned())).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("Unsupported arguments in ripgrep config"));
+    }
+
+    #[test]
+    fn rejects_rgr_only_flags_in_the_config_file() {
+        let mut config = NamedTempFile::new().unwrap();
+        writeln!(config, "-e\n--yes\n--preserve-case\n--diff=out.patch").unwrap();
+
+        let raw_args = ["rgr", "foo"].map(OsString::from).to_vec();
+        let args = Args::parse_from(&raw_args);
+        let err = apply_config(args, &raw_args, Some(config.path().to_owned())).unwrap_err();
+        assert!(err
+            .to_string()
+            .ends_with("--preserve-case, --diff can only be passed on the command line"));
+    }
+
+    #[test]
+    fn ignores_rg_replace_in_the_config_file() {
+        let mut config = NamedTempFile::new().unwrap();
+        writeln!(config, "--replace=bar\n-r\nbaz\n-i").unwrap();
+
+        let raw_args = ["rgr", "foo", "a.txt"].map(OsString::from).to_vec();
+        let args = Args::parse_from(&raw_args);
+        let mut args = apply_config(args, &raw_args, Some(config.path().to_owned())).unwrap();
+        args.raw_args = raw_args;
+        let args = validate_arguments(args).unwrap();
+
+        assert!(!args.is_batch());
+        assert_eq!(args.replace, None);
+        assert_eq!(args.rg_args().collect::<Vec<_>>(), ["-i", "foo", "a.txt"]);
+    }
+
     #[test]
     fn does_not_allow_unrestricted_above_two() {
-        let args = parse_arguments_from(&["rgr", "-uuu", "pattern-pos"]).unwrap();
+        let args = parse_arguments_from(["rgr", "-uuu", "pattern-pos"]).unwrap();
         assert_eq!(args.unrestricted, 2);
     }
 
+    #[test]
+    fn requires_confirmation_to_replace() {
+        let args = parse_arguments_from(["rgr", "pattern-pos", "--replace", "foo"]);
+        assert!(args.is_err());
+
+        let args = parse_arguments_from(["rgr", "pattern-pos", "--replace", "foo", "--yes"]);
+        assert!(args.is_ok());
+
+        let args = parse_arguments_from(["rgr", "pattern-pos", "--replace", "foo", "--dry-run"]);
+        assert!(args.is_ok());
+
+        let args = parse_arguments_from(["rgr", "pattern-pos", "--replace", "foo", "--diff"]);
+        assert!(args.is_ok());
+    }
+
+    #[test]
+    fn reads_replacements_for_each_pattern() {
+        let args = parse_arguments_from([
+            "rgr",
+            "-e",
+            "a=b",
+            "-e",
+            "c",
+            "--replace-pattern",
+            "a=b=d=e",
+            "--replace-pattern=c=",
+            "--dry-run",
+        ])
+        .unwrap();
+        assert!(args.is_batch());
+        assert_eq!(
+            args.pattern_texts().unwrap(),
+            PatternTexts::from([
+                (String::from("a=b"), String::from("d=e")),
+                (String::from("c"), String::new())
+            ])
+        );
+
+        let args = parse_arguments_from(["rgr", "a", "--replace-pattern", "a"]);
+        assert!(args.is_err());
+
+        let args = parse_arguments_from(["rgr", "a", "--replace-pattern", "b=c", "--yes"]);
+        assert!(args.is_err());
+    }
+
+    #[test]
+    fn undoes_without_a_pattern() {
+        let args = parse_arguments_from(["rgr", "--undo"]);
+        assert!(args.unwrap().undo);
+
+        let args = Args::try_parse_from(["rgr", "pattern-pos", "--undo", "--replace", "foo"]);
+        assert!(args.is_err());
+    }
+
+    #[test]
+    fn parses_searches_entered_in_the_interface() {
+        let args = parse_search("-w 'foo bar' src").unwrap();
+        assert_eq!(args.pattern, Some("foo bar".to_owned()));
+        assert_eq!(args.paths, vec![PathBuf::from("src")]);
+        assert!(args.word_regexp);
+        assert_eq!(search_cmdline(&args), "-w 'foo bar' src");
+
+        let args = parse_search("--not-a-flag foo");
+        assert!(args.is_err());
+
+        let args = parse_search("foo --replace bar --yes");
+        assert!(args.is_err());
+
+        let args = parse_search("foo --rename");
+        assert!(args.is_err());
+
+        let args = parse_search("foo --operation=delete-line");
+        assert!(args.is_err());
+    }
+
     #[test]
     fn returns_rg_patterns() {
-        let args = parse_arguments_from(&[
+        let args = parse_arguments_from([
             "rgr",
             "-e",
             "pattern-flag",
@@ -113,7 +507,7 @@ mod tests {
         .unwrap();
 
         assert_eq!(
-            rg_patterns(&args),
+            args.rg_patterns(),
             vec!["pattern-flag", "pattern-flag-long"]
         );
     }
@@ -0,0 +1,119 @@
+use std::io::{self, Write};
+
+use similar::TextDiff;
+
+/// How many lines of context are included around each change.
+const CONTEXT_LINES: usize = 3;
+
+/// Writes a unified diff of the changes from `old` to `new` (the contents of the file at `path`) to `out`.
+/// Nothing is written if there are no changes.
+///
+/// The diff is made from the raw bytes of the file, rather than from decoded text: that way any BOM and the file's
+/// encoding are preserved, and the diff can be applied with `git apply` or `patch -p1`.
+pub fn write_unified_diff(
+    out: &mut dyn Write,
+    path: &[u8],
+    old: &[u8],
+    new: &[u8],
+) -> io::Result<()> {
+    if old == new {
+        return Ok(());
+    }
+
+    // Paths from ripgrep are relative to where it was run (unless absolute paths were passed to it), so prefix them
+    // in the same way as `git diff` does.
+    let path = path.strip_prefix(b"./").unwrap_or(path);
+    for prefix in [&b"--- a/"[..], &b"+++ b/"[..]] {
+        out.write_all(prefix)?;
+        out.write_all(path)?;
+        out.write_all(b"\n")?;
+    }
+
+    let diff = TextDiff::from_lines(old, new);
+    for hunk in diff
+        .unified_diff()
+        .context_radius(CONTEXT_LINES)
+        .iter_hunks()
+    {
+        // NOTE: the hunk's `Display` implementation is lossy, so we use `to_writer` to keep the bytes as they are.
+        hunk.to_writer(&mut *out)?;
+    }
+
+    Ok(())
+}
+
+/// Writes a diff which renames the file at `old_path` to `new_path` without changing its contents, in the same
+/// format as `git diff` (which `git apply` and `patch -p1` both understand).
+pub fn write_rename_diff(out: &mut dyn Write, old_path: &[u8], new_path: &[u8]) -> io::Result<()> {
+    let old_path = old_path.strip_prefix(b"./").unwrap_or(old_path);
+    let new_path = new_path.strip_prefix(b"./").unwrap_or(new_path);
+    for line in [
+        &[&b"diff --git a/"[..], old_path, b" b/", new_path][..],
+        &[b"similarity index 100%"],
+        &[b"rename from ", old_path],
+        &[b"rename to ", new_path],
+    ] {
+        for part in line {
+            out.write_all(part)?;
+        }
+        out.write_all(b"\n")?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::{write_rename_diff, write_unified_diff};
+
+    fn diff(path: &str, old: &[u8], new: &[u8]) -> Vec<u8> {
+        let mut out = vec![];
+        write_unified_diff(&mut out, path.as_bytes(), old, new).unwrap();
+        out
+    }
+
+    #[test]
+    fn it_writes_nothing_without_changes() {
+        assert_eq!(diff("foo", b"a\nb\n", b"a\nb\n"), b"");
+    }
+
+    #[test]
+    fn it_writes_a_unified_diff() {
+        assert_eq!(
+            String::from_utf8(diff("./src/foo.rs", b"a\nb\nc\n", b"a\nB\nc\n")).unwrap(),
+            "--- a/src/foo.rs\n+++ b/src/foo.rs\n@@ -1,3 +1,3 @@\n a\n-b\n+B\n c\n"
+        );
+    }
+
+    #[test]
+    fn it_marks_missing_newlines() {
+        assert_eq!(
+            String::from_utf8(diff("foo", b"a", b"b")).unwrap(),
+            "--- a/foo\n+++ b/foo\n@@ -1 +1 @@\n-a\n\\ No newline at end of file\n+b\n\\ No newline at end of file\n"
+        );
+    }
+
+    #[test]
+    fn it_keeps_the_raw_bytes() {
+        // UTF-16LE with a BOM
+        let old = b"\xff\xfea\x00\n\x00";
+        let new = b"\xff\xfeb\x00\n\x00";
+        let out = diff("foo", old, new);
+        assert_eq!(
+            out,
+            b"--- a/foo\n+++ b/foo\n@@ -1,2 +1,2 @@\n-\xff\xfea\x00\n+\xff\xfeb\x00\n \x00\n\\ No newline at end of file\n"
+        );
+    }
+
+    #[test]
+    fn it_writes_a_rename_diff() {
+        let mut out = vec![];
+        write_rename_diff(&mut out, b"./src/old_module.rs", b"./src/new/module.rs").unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "diff --git a/src/old_module.rs b/src/new/module.rs\nsimilarity index 100%\nrename from src/old_module.rs\nrename to src/new/module.rs\n"
+        );
+    }
+}
@@ -1,6 +1,6 @@
 use chardet::charset2encoding;
 use encoding::label::encoding_from_whatwg_label;
-use encoding::EncodingRef;
+use encoding::{DecoderTrap, EncodingRef};
 
 use crate::rg::RgEncoding;
 
@@ -20,11 +20,11 @@ pub fn get_encoder(bytes: &[u8], rg_encoding: &RgEncoding) -> (Option<Bom>, Enco
         // otherwise if the user passed an encoding use that
         .or_else(|| {
             let encoder = rg_encoding.encoder();
-            if encoder.is_some() {
+            if let Some(encoder) = encoder {
                 log::debug!(
                     "Found user encoding: {:?}, using encoder: {}",
                     rg_encoding,
-                    encoder.unwrap().name()
+                    encoder.name()
                 );
             }
 
@@ -64,6 +64,30 @@ pub fn get_encoder(bytes: &[u8], rg_encoding: &RgEncoding) -> (Option<Bom>, Enco
     (bom, encoder)
 }
 
+/// Decodes the contents of a file to show it, in the same way as when replacing its matches. Anything which can't
+/// be decoded is shown with the UTF-8 replacement character, and the BOM (if there is one) isn't shown.
+pub fn decode_lossy(bytes: &[u8], rg_encoding: &RgEncoding) -> String {
+    let (bom, encoder) = get_encoder(bytes, rg_encoding);
+    let bytes = match bom {
+        Some(bom) => &bytes[bom.len()..],
+        None => bytes,
+    };
+
+    match encoder.decode(bytes, DecoderTrap::Strict) {
+        Ok(decoded) => decoded,
+        // NOTE: ripgrep only transcodes files with a UTF-16 BOM, or when an encoding is passed to it, so otherwise
+        // it searched the bytes as they are (which is how they're replaced too).
+        Err(_)
+            if matches!(rg_encoding, RgEncoding::None) && matches!(bom, None | Some(Bom::Utf8)) =>
+        {
+            String::from_utf8_lossy(bytes).into_owned()
+        }
+        Err(_) => encoder
+            .decode(bytes, DecoderTrap::Replace)
+            .unwrap_or_else(|e| e.into_owned()),
+    }
+}
+
 /// A small wrapper to help with BOM (Byte Order Mark) detection.
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum Bom {
@@ -121,7 +145,7 @@ impl Bom {
 mod tests {
     use pretty_assertions::assert_eq;
 
-    use crate::encoding::{get_encoder, Bom, RgEncoding};
+    use crate::encoding::{decode_lossy, get_encoder, Bom, RgEncoding};
 
     #[test]
     fn test_bom_handles_empty_slices() {
@@ -179,6 +203,24 @@ mod tests {
         assert_eq!(Bom::Utf16le.encoder().name(), "utf-16le");
     }
 
+    #[test]
+    fn test_decode_lossy() {
+        let utf16le = [&Bom::BOM_UTF16LE[..], b"a\0\n\0b\0"].concat();
+        assert_eq!(decode_lossy(&utf16le, &RgEncoding::None), "a\nb");
+        let utf8 = [&Bom::BOM_UTF8[..], "añ\n".as_bytes()].concat();
+        assert_eq!(decode_lossy(&utf8, &RgEncoding::None), "añ\n");
+
+        // Bytes which aren't valid in the file's encoding are replaced.
+        assert_eq!(
+            decode_lossy(b"caf\xe9 \xff\n", &RgEncoding::None),
+            "caf\u{FFFD} \u{FFFD}\n"
+        );
+        assert_eq!(
+            decode_lossy(b"a\xffb", &RgEncoding::Some(encoding::all::ASCII)),
+            "a\u{FFFD}b"
+        );
+    }
+
     //
     // get_encoder
     //
@@ -0,0 +1,203 @@
+/// The history of the replacements and searches entered in the interface, which is kept between runs.
+use std::env;
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use tempfile::NamedTempFile;
+
+/// The environment variable which sets how many replacements (and searches) are kept in the history.
+pub const ENV_HISTORY_SIZE: &str = "RGR_HISTORY_SIZE";
+/// How many entries of each kind are kept, unless `ENV_HISTORY_SIZE` is set.
+pub const DEFAULT_HISTORY_SIZE: usize = 100;
+/// The name of the file (within the data directory) which holds the history.
+const HISTORY_FILE: &str = "history.json";
+
+/// Returns the size of the history set by `ENV_HISTORY_SIZE`, or the default if it isn't set (or is invalid).
+pub fn configured_size() -> usize {
+    match env::var(ENV_HISTORY_SIZE) {
+        Ok(size) => size.trim().parse().unwrap_or_else(|_| {
+            log::warn!("Invalid {}: {:?}", ENV_HISTORY_SIZE, size);
+            DEFAULT_HISTORY_SIZE
+        }),
+        Err(_) => DEFAULT_HISTORY_SIZE,
+    }
+}
+
+/// The entries of the history, each list with the most recent last.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+struct Entries {
+    #[serde(default)]
+    replacements: Vec<String>,
+    /// The arguments passed to `rg`, including the patterns.
+    #[serde(default)]
+    searches: Vec<String>,
+}
+
+#[derive(Debug)]
+pub struct History {
+    /// The file the history is saved to, or `None` if it's only kept in memory.
+    path: Option<PathBuf>,
+    /// The most entries of each kind which are kept, where 0 turns off the history.
+    size: usize,
+    entries: Entries,
+}
+
+impl History {
+    /// Creates an empty history which isn't saved.
+    pub fn new(size: usize) -> History {
+        History {
+            path: None,
+            size,
+            entries: Entries::default(),
+        }
+    }
+
+    /// Loads the history saved in `data_dir`, which is saved there again each time an entry is added.
+    /// If it can't be read, then the history starts out empty.
+    pub fn load(data_dir: impl AsRef<Path>, size: usize) -> History {
+        let path = data_dir.as_ref().join(HISTORY_FILE);
+        let entries = read_entries(&path).unwrap_or_else(|e| {
+            log::warn!("Failed to read history: {:#}", e);
+            Entries::default()
+        });
+
+        let mut history = History {
+            path: Some(path),
+            size,
+            entries,
+        };
+        history.truncate();
+        history
+    }
+
+    pub fn replacements(&self) -> &[String] {
+        &self.entries.replacements
+    }
+
+    pub fn searches(&self) -> &[String] {
+        &self.entries.searches
+    }
+
+    /// Adds replacement text to the history, unless it's empty.
+    pub fn add_replacement(&mut self, replacement: &str) {
+        if !replacement.is_empty() {
+            self.add(|entries| &mut entries.replacements, replacement);
+        }
+    }
+
+    /// Adds the arguments of a search to the history.
+    pub fn add_search(&mut self, cmdline: &str) {
+        self.add(|entries| &mut entries.searches, cmdline);
+    }
+
+    /// Adds `entry` as the most recent in the list, moving it there if it was already in the list.
+    fn add(&mut self, list: fn(&mut Entries) -> &mut Vec<String>, entry: &str) {
+        if self.size == 0 {
+            return;
+        }
+
+        // NOTE: the history is read again first, so the entries added by any other runs since it was loaded
+        // aren't lost.
+        if let Some(entries) = self
+            .path
+            .as_deref()
+            .and_then(|path| read_entries(path).ok())
+        {
+            self.entries = entries;
+        }
+
+        let list = list(&mut self.entries);
+        list.retain(|existing| existing != entry);
+        list.push(entry.to_owned());
+        self.truncate();
+        if let Err(e) = self.save() {
+            log::warn!("Failed to save history: {:#}", e);
+        }
+    }
+
+    /// Drops the oldest entries of each kind, so there are at most `size` of them.
+    fn truncate(&mut self) {
+        for list in [&mut self.entries.replacements, &mut self.entries.searches] {
+            list.drain(..list.len().saturating_sub(self.size));
+        }
+    }
+
+    fn save(&self) -> Result<()> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+
+        let dir = path.parent().unwrap_or_else(|| Path::new("."));
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create data directory: {}", dir.display()))?;
+
+        // Write to a temporary file first, so the history is never left half written.
+        let mut file = NamedTempFile::new_in(dir)?;
+        serde_json::to_writer_pretty(&mut file, &self.entries)?;
+        file.flush()?;
+        file.persist(path)?;
+        log::debug!("History: saved to {}", path.display());
+
+        Ok(())
+    }
+}
+
+/// Reads the history saved at `path`, which is empty if there's no history yet.
+fn read_entries(path: &Path) -> Result<Entries> {
+    match fs::read(path) {
+        Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(Entries::default()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use pretty_assertions::assert_eq;
+    use tempfile::tempdir;
+
+    use crate::history::{History, HISTORY_FILE};
+
+    #[test]
+    fn it_deduplicates_and_limits_entries() {
+        let mut history = History::new(3);
+        for replacement in ["foo", "bar", "", "foo", "baz", "qux"] {
+            history.add_replacement(replacement);
+        }
+        history.add_search("TESTS -w");
+
+        assert_eq!(history.replacements(), ["foo", "baz", "qux"]);
+        assert_eq!(history.searches(), ["TESTS -w"]);
+    }
+
+    #[test]
+    fn it_saves_and_loads_the_history() {
+        let data_dir = tempdir().unwrap();
+        let mut history = History::load(data_dir.path(), 10);
+        assert!(history.replacements().is_empty());
+        history.add_replacement("foo");
+
+        // Entries added by another run are kept.
+        let mut other = History::load(data_dir.path(), 10);
+        other.add_replacement("bar");
+        other.add_search("TESTS");
+        history.add_replacement("foo");
+
+        let history = History::load(data_dir.path(), 10);
+        assert_eq!(history.replacements(), ["bar", "foo"]);
+        assert_eq!(history.searches(), ["TESTS"]);
+        let history = History::load(data_dir.path(), 1);
+        assert_eq!(history.replacements(), ["foo"]);
+
+        // Nothing is saved when the history is turned off.
+        fs::remove_file(data_dir.path().join(HISTORY_FILE)).unwrap();
+        History::load(data_dir.path(), 0).add_replacement("foo");
+        assert!(!data_dir.path().join(HISTORY_FILE).exists());
+    }
+}
@@ -0,0 +1,357 @@
+/// A journal of the files changed in the last replacement run, so that it can be undone (see `--undo`).
+use std::fs::{self, File};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use tempfile::TempDir;
+
+use crate::replace::write_file;
+use crate::util::hash;
+
+/// The name of the directory (within the journal directory) which holds the journal of the last run.
+const LAST_RUN_DIR: &str = "last-run";
+/// The name of the file (within the journal's directory) which lists the files that were changed.
+const JOURNAL_FILE: &str = "journal.json";
+
+/// A file which was changed during a replacement run.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+struct JournalEntry {
+    /// The absolute path of the file.
+    path: PathBuf,
+    /// The name of the file (within the journal's directory) containing the original contents of the file.
+    backup: String,
+    /// The hash of the file's original contents.
+    original_hash: String,
+    /// The hash of the contents that were written to the file.
+    written_hash: String,
+}
+
+/// Records the original contents of each file before it's changed.
+/// The journal only replaces the previous run's journal once it's saved.
+#[derive(Debug)]
+pub struct Journal {
+    journal_dir: PathBuf,
+    staging_dir: TempDir,
+    entries: Vec<JournalEntry>,
+}
+
+impl Journal {
+    pub fn new(journal_dir: impl AsRef<Path>) -> Result<Journal> {
+        let journal_dir = journal_dir.as_ref().to_path_buf();
+        create_private_dir(&journal_dir).with_context(|| {
+            format!(
+                "Failed to create journal directory: {}",
+                journal_dir.display()
+            )
+        })?;
+        check_private_dir(&journal_dir)?;
+
+        let staging_dir = tempfile::Builder::new()
+            .prefix("journal")
+            .tempdir_in(&journal_dir)?;
+        log::debug!("Created journal: {}", staging_dir.path().display());
+
+        Ok(Journal {
+            journal_dir,
+            staging_dir,
+            entries: vec![],
+        })
+    }
+
+    /// Backs up the `original` contents of the file at `path`, which is about to be overwritten with `written`.
+    /// This must be called _before_ the file is written.
+    pub fn record(&mut self, path: &Path, original: &[u8], written: &[u8]) -> Result<()> {
+        let path = path.canonicalize()?;
+        let backup = format!("{}.orig", self.entries.len());
+        fs::write(self.staging_dir.path().join(&backup), original)?;
+
+        log::debug!("Journal: backed up {} to {}", path.display(), backup);
+        self.entries.push(JournalEntry {
+            path,
+            backup,
+            original_hash: hash(original),
+            written_hash: hash(written),
+        });
+
+        Ok(())
+    }
+
+    /// Forgets every file recorded so far, since they were restored (or never written) after all. Saving the journal
+    /// then leaves the previous run's journal in place.
+    pub fn discard(&mut self) {
+        log::debug!("Journal: discarding {} file(s)", self.entries.len());
+        self.entries.clear();
+    }
+
+    /// Saves the journal, replacing the journal of the previous run.
+    /// Nothing is saved if no files were recorded, so the previous run can still be undone.
+    pub fn save(self) -> Result<()> {
+        if self.entries.is_empty() {
+            log::debug!("Journal: no files were changed, not saving");
+            return Ok(());
+        }
+
+        let mut journal_file = File::create(self.staging_dir.path().join(JOURNAL_FILE))?;
+        serde_json::to_writer_pretty(&mut journal_file, &self.entries)?;
+        journal_file.flush()?;
+
+        let last_run_dir = self.journal_dir.join(LAST_RUN_DIR);
+        match fs::remove_dir_all(&last_run_dir) {
+            Err(e) if e.kind() != ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+
+        fs::rename(self.staging_dir.keep(), &last_run_dir)?;
+        log::debug!("Journal: saved to {}", last_run_dir.display());
+
+        Ok(())
+    }
+}
+
+/// What happened when undoing the changes to a single file.
+#[derive(Debug, PartialEq, Eq)]
+pub enum UndoResult {
+    /// The original contents were restored.
+    Restored,
+    /// The file already has its original contents.
+    Unchanged,
+    /// The file was changed after rgr wrote it, so it was left alone.
+    Modified,
+    /// The file could not be restored.
+    Failed(String),
+}
+
+/// Restores the original contents of each file changed in the last run. Files which were changed since rgr wrote
+/// them are not restored. The journal is removed once every file has its original contents again.
+/// Returns the path of each file and what happened to it.
+pub fn undo(journal_dir: impl AsRef<Path>) -> Result<Vec<(PathBuf, UndoResult)>> {
+    let journal_dir = journal_dir.as_ref();
+    check_private_dir(journal_dir)?;
+    let last_run_dir = journal_dir.join(LAST_RUN_DIR);
+    let journal_file = File::open(last_run_dir.join(JOURNAL_FILE)).map_err(|e| match e.kind() {
+        ErrorKind::NotFound => anyhow!("There is no replacement run to undo"),
+        _ => anyhow!("Failed to read journal: {}", e),
+    })?;
+    let entries: Vec<JournalEntry> = serde_json::from_reader(journal_file)?;
+
+    let results = entries
+        .into_iter()
+        .map(|entry| {
+            let result = match undo_entry(&last_run_dir, &entry) {
+                Ok(result) => result,
+                Err(e) => UndoResult::Failed(e.to_string()),
+            };
+
+            log::debug!("Undo: {}: {:?}", entry.path.display(), result);
+            (entry.path, result)
+        })
+        .collect::<Vec<_>>();
+
+    if results
+        .iter()
+        .all(|(_, r)| matches!(r, UndoResult::Restored | UndoResult::Unchanged))
+    {
+        fs::remove_dir_all(&last_run_dir)?;
+    }
+
+    Ok(results)
+}
+
+/// Undoes the last replacement run, and prints what happened to each file.
+/// Returns whether every file now has its original contents.
+pub fn perform_undo(journal_dir: impl AsRef<Path>) -> Result<bool> {
+    let results = undo(journal_dir)?;
+    let mut restored_all = true;
+    for (path, result) in &results {
+        let path = path.display();
+        match result {
+            UndoResult::Restored => println!("{}: restored", path),
+            UndoResult::Unchanged => println!("{}: already restored", path),
+            UndoResult::Modified => {
+                restored_all = false;
+                eprintln!(
+                    "{}: not restored, it was changed after it was replaced",
+                    path
+                );
+            }
+            UndoResult::Failed(e) => {
+                restored_all = false;
+                eprintln!("{}: failed: {}", path, e);
+            }
+        }
+    }
+
+    Ok(restored_all)
+}
+
+fn undo_entry(last_run_dir: &Path, entry: &JournalEntry) -> Result<UndoResult> {
+    let current_hash = hash(&fs::read(&entry.path)?);
+    if current_hash == entry.original_hash {
+        return Ok(UndoResult::Unchanged);
+    }
+    if current_hash != entry.written_hash {
+        return Ok(UndoResult::Modified);
+    }
+
+    let original = fs::read(last_run_dir.join(&entry.backup))?;
+    if hash(&original) != entry.original_hash {
+        return Err(anyhow!("The backup of this file is corrupt"));
+    }
+
+    write_file(&entry.path, &original)?;
+    Ok(UndoResult::Restored)
+}
+
+#[cfg(unix)]
+fn create_private_dir(dir: &Path) -> std::io::Result<()> {
+    use std::os::unix::fs::DirBuilderExt;
+    fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(dir)
+}
+
+#[cfg(not(unix))]
+fn create_private_dir(dir: &Path) -> std::io::Result<()> {
+    fs::create_dir_all(dir)
+}
+
+/// Checks that the journal directory belongs to the current user, and that no one else can write to it: undoing
+/// writes the backups within it over the user's files, so it mustn't be possible for anyone else to plant a journal.
+#[cfg(unix)]
+fn check_private_dir(dir: &Path) -> Result<()> {
+    use std::os::unix::fs::MetadataExt;
+
+    let metadata = fs::metadata(dir).map_err(|e| match e.kind() {
+        ErrorKind::NotFound => anyhow!("There is no replacement run to undo"),
+        _ => anyhow!("Failed to read journal directory {}: {}", dir.display(), e),
+    })?;
+    // SAFETY: geteuid is always successful, and has no side effects.
+    let uid = unsafe { libc::geteuid() };
+    if metadata.uid() != uid {
+        return Err(anyhow!(
+            "Refusing to use journal directory {}, it's not owned by the current user",
+            dir.display()
+        ));
+    }
+    if metadata.mode() & 0o022 != 0 {
+        return Err(anyhow!(
+            "Refusing to use journal directory {}, it's writable by other users",
+            dir.display()
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn check_private_dir(_dir: &Path) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use pretty_assertions::assert_eq;
+    use tempfile::tempdir;
+
+    use crate::journal::{undo, Journal, UndoResult, JOURNAL_FILE, LAST_RUN_DIR};
+
+    #[test]
+    fn it_undoes_the_last_run() {
+        let state_dir = tempdir().unwrap();
+        let files_dir = tempdir().unwrap();
+        let p = files_dir.path().join("file");
+        fs::write(&p, "foo").unwrap();
+        let p = p.canonicalize().unwrap();
+
+        let mut journal = Journal::new(state_dir.path()).unwrap();
+        journal.record(&p, b"foo", b"bar").unwrap();
+        fs::write(&p, "bar").unwrap();
+        journal.save().unwrap();
+
+        assert_eq!(
+            undo(state_dir.path()).unwrap(),
+            vec![(p.clone(), UndoResult::Restored)]
+        );
+        assert_eq!(fs::read_to_string(&p).unwrap(), "foo");
+
+        // The journal is removed once the run has been undone.
+        assert!(!state_dir.path().join(LAST_RUN_DIR).exists());
+        assert!(undo(state_dir.path()).is_err());
+    }
+
+    #[test]
+    fn it_refuses_to_undo_modified_files() {
+        let state_dir = tempdir().unwrap();
+        let files_dir = tempdir().unwrap();
+        let modified = files_dir.path().join("modified");
+        let unchanged = files_dir.path().join("unchanged");
+        fs::write(&modified, "foo").unwrap();
+        fs::write(&unchanged, "foo").unwrap();
+        let modified = modified.canonicalize().unwrap();
+        let unchanged = unchanged.canonicalize().unwrap();
+
+        let mut journal = Journal::new(state_dir.path()).unwrap();
+        journal.record(&modified, b"foo", b"bar").unwrap();
+        journal.record(&unchanged, b"foo", b"bar").unwrap();
+        journal.save().unwrap();
+
+        // Someone else changed this file after rgr wrote it.
+        fs::write(&modified, "baz").unwrap();
+
+        assert_eq!(
+            undo(state_dir.path()).unwrap(),
+            vec![
+                (modified.clone(), UndoResult::Modified),
+                (unchanged, UndoResult::Unchanged)
+            ]
+        );
+        assert_eq!(fs::read_to_string(&modified).unwrap(), "baz");
+
+        // The journal is kept since not every file could be restored.
+        assert!(state_dir
+            .path()
+            .join(LAST_RUN_DIR)
+            .join(JOURNAL_FILE)
+            .exists());
+    }
+
+    #[test]
+    fn it_keeps_the_previous_journal_if_nothing_changed() {
+        let state_dir = tempdir().unwrap();
+        let files_dir = tempdir().unwrap();
+        let p = files_dir.path().join("file");
+        fs::write(&p, "bar").unwrap();
+
+        let mut journal = Journal::new(state_dir.path()).unwrap();
+        journal.record(&p, b"foo", b"bar").unwrap();
+        journal.save().unwrap();
+
+        Journal::new(state_dir.path()).unwrap().save().unwrap();
+        assert_eq!(undo(state_dir.path()).unwrap().len(), 1);
+        assert_eq!(fs::read_to_string(&p).unwrap(), "foo");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn it_refuses_a_journal_directory_writable_by_others() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let journal_dir = tempdir().unwrap();
+        fs::create_dir(journal_dir.path().join(LAST_RUN_DIR)).unwrap();
+        fs::write(
+            journal_dir.path().join(LAST_RUN_DIR).join(JOURNAL_FILE),
+            "[]",
+        )
+        .unwrap();
+        assert_eq!(undo(journal_dir.path()).unwrap(), vec![]);
+
+        fs::set_permissions(journal_dir.path(), fs::Permissions::from_mode(0o777)).unwrap();
+        assert!(undo(journal_dir.path()).is_err());
+        assert!(Journal::new(journal_dir.path()).is_err());
+    }
+}
@@ -65,9 +65,14 @@
 //! [releases]: https://github.com/acheronfail/repgrep/releases
 //! [`ripgrep` installation instructions]: https://github.com/BurntSushi/ripgrep/#installation
 
+mod batch;
 mod cli;
+mod diff;
 mod encoding;
+mod history;
+mod journal;
 mod model;
+mod rename;
 mod replace;
 mod rg;
 mod ui;
@@ -77,16 +82,18 @@ use std::env;
 use std::fs::File;
 use std::process;
 
-use anyhow::Result;
-use clap::crate_name;
+use anyhow::{anyhow, Result};
 use flexi_logger::{opt_format, FileSpec, Logger};
 use rg::exec::run_ripgrep;
 use ui::tui::Tui;
 
-use crate::rg::read::read_messages;
+use crate::cli::Args;
+use crate::journal::Journal;
+use crate::model::{Reapplied, ReplacementCriteria, ReplacementOutput, Snapshots};
+use crate::rg::search::Search;
 
 fn init_logging() -> Result<::std::path::PathBuf> {
-    let log_dir = env::temp_dir().join(format!(".{}", crate_name!()));
+    let log_dir = util::state_dir();
     let log_spec = if cfg!(debug_assertions) {
         FileSpec::default()
             .directory(env::current_dir().unwrap())
@@ -106,6 +113,51 @@ fn init_logging() -> Result<::std::path::PathBuf> {
     Ok(log_dir)
 }
 
+/// Checks whether any files were changed since they were searched, and if so offers to search them again and
+/// re-apply the selections which still line up. Otherwise, the changed files are skipped.
+fn search_changed_files(args: &Args, criteria: &mut ReplacementCriteria) {
+    let changed = criteria.snapshots.changed_files();
+    if changed.is_empty() {
+        return;
+    }
+
+    log::warn!("Files changed since they were searched: {:?}", changed);
+    eprintln!("These files have changed since they were searched:");
+    for path in &changed {
+        eprintln!("  {}", path.display());
+    }
+
+    if !util::confirm("Search them again and re-apply the selections which still line up?") {
+        criteria.remove_files(&changed);
+        eprintln!("Skipping the changed files");
+        return;
+    }
+
+    let rg_args = args.rg_args_for_paths(&changed);
+    log::debug!("Searching changed files with args: {:?}", rg_args);
+    let rg_messages = match run_ripgrep(rg_args) {
+        Ok(rg_messages) => rg_messages,
+        Err(e) => {
+            log::warn!("Failed to search changed files: {}", e);
+            eprintln!(
+                "Skipping the changed files, searching them again failed: {}",
+                e
+            );
+            criteria.remove_files(&changed);
+            return;
+        }
+    };
+
+    criteria
+        .snapshots
+        .update(&changed, Snapshots::take(&rg_messages));
+    let Reapplied { reapplied, dropped } = criteria.reapply(&changed, rg_messages);
+    eprintln!(
+        "Re-applied {} selected match(es), {} no longer line up",
+        reapplied, dropped
+    );
+}
+
 fn main() {
     let log_dir = match init_logging() {
         Ok(dir) => dir,
@@ -134,42 +186,143 @@ fn main() {
         }
     };
 
-    macro_rules! run_ripgrep {
+    if args.undo {
+        let journal_dir = match util::journal_dir() {
+            Some(dir) => dir,
+            None => {
+                exit_with_error!("Failed to undo replacements: no directory for the journal");
+            }
+        };
+        match journal::perform_undo(journal_dir) {
+            Ok(true) => process::exit(0),
+            Ok(false) => {
+                log::warn!("Failed to undo all replacements");
+                process::exit(batch::EXIT_CODE_SKIPPED);
+            }
+            Err(err) => {
+                exit_with_error!("Failed to undo replacements: {}", err);
+            }
+        }
+    }
+
+    // Records the original contents of each file before it's written, so the run can be undone with `--undo`.
+    macro_rules! journal {
+        () => {{
+            let journal = util::journal_dir()
+                .ok_or_else(|| anyhow!("no directory for the journal"))
+                .and_then(Journal::new);
+            match journal {
+                Ok(journal) => journal,
+                Err(err) => {
+                    exit_with_error!("Failed to create a journal for the replacements: {}", err);
+                }
+            }
+        }};
+    }
+
+    macro_rules! search_ripgrep {
         () => {{
             let display_args = args.rg_args().into_iter().collect::<Vec<_>>();
             log::debug!("User args for rg: {:?}", display_args);
-            run_ripgrep(args.rg_args())
+            args.search()
         }};
     }
 
-    let rg_json = match env::var(cli::ENV_JSON_FILE) {
-        Ok(path) => {
+    let search = match env::var(cli::ENV_JSON_FILE) {
+        // NOTE: the results in the file are of searching the contents of files, so they can't be used for renaming.
+        Ok(path) if !args.rename => {
             log::debug!(
                 "Found {}={}, reading messages from file",
                 cli::ENV_JSON_FILE,
                 &path
             );
             match File::open(path) {
-                Ok(json_file) => read_messages(json_file),
+                Ok(json_file) => Ok(Search::file(json_file)),
                 Err(e) => {
                     log::warn!("Failed to open file: {}", e);
                     log::warn!("Falling back to running rg");
-                    run_ripgrep!()
+                    search_ripgrep!()
                 }
             }
         }
-        Err(_) => run_ripgrep!(),
+        _ => search_ripgrep!(),
     };
 
-    match rg_json {
-        Ok(rg_messages) => {
-            let rg_cmdline: String = args
-                .rg_args()
-                .map(|s| s.to_string_lossy().into_owned())
-                .collect::<Vec<_>>()
-                .join(" ");
+    match search {
+        Ok(search) if args.is_batch() => {
+            let rg_messages = match search.collect() {
+                Ok(rg_messages) => rg_messages,
+                Err(e) => {
+                    exit_with_error!("{}", e);
+                }
+            };
+
+            // Remember the state of each file when it was searched, so we don't write any which have changed since.
+            // (When renaming, the contents of the files don't matter.)
+            let snapshots = if args.rename {
+                Snapshots::default()
+            } else {
+                Snapshots::take(&rg_messages)
+            };
+            let mut replacement_criteria =
+                batch::select_all(args.replace.as_deref().unwrap_or_default(), rg_messages);
+            replacement_criteria.set_matcher(args.matcher());
+            if !args.replace_patterns.is_empty() {
+                let pattern_texts = match args.pattern_texts() {
+                    Ok(pattern_texts) => pattern_texts,
+                    Err(e) => {
+                        exit_with_error!("{}", e);
+                    }
+                };
+                batch::select_patterns(
+                    &mut replacement_criteria,
+                    pattern_texts,
+                    args.replace.is_some(),
+                );
+            }
+            replacement_criteria.set_preserve_case(args.preserve_case);
+            replacement_criteria.set_operation(args.operation());
+            replacement_criteria.set_output(args.replacement_output());
+            replacement_criteria.set_all_or_nothing(args.all_or_nothing);
+            replacement_criteria.set_snapshots(snapshots);
+            if let Some(encoding) = &args.encoding {
+                replacement_criteria.set_encoding(encoding);
+            }
 
-            let result = Tui::new(rg_cmdline, rg_messages).start();
+            let result = if args.rename {
+                rename::run(&replacement_criteria, args.dry_run, args.diff.as_deref())
+            } else {
+                let mut journal = match replacement_criteria.output {
+                    ReplacementOutput::Files if !args.dry_run => Some(journal!()),
+                    _ => None,
+                };
+                let result = batch::run(
+                    &replacement_criteria,
+                    args.dry_run,
+                    args.diff.as_deref(),
+                    journal.as_mut(),
+                );
+                if let Some(Err(err)) = journal.map(Journal::save) {
+                    log::warn!("Failed to save journal: {}", err);
+                    eprintln!("Failed to save journal, this run cannot be undone: {}", err);
+                }
+
+                result
+            };
+
+            match result {
+                Ok(true) => {}
+                Ok(false) => {
+                    log::warn!("Failed to perform all replacements");
+                    process::exit(batch::EXIT_CODE_SKIPPED);
+                }
+                Err(err) => {
+                    exit_with_error!("An error occurred during replacement: {}", err);
+                }
+            }
+        }
+        Ok(search) => {
+            let result = Tui::new(&args, search).start();
 
             // Restore terminal.
             if let Err(err) = Tui::restore_terminal() {
@@ -182,13 +335,50 @@ fn main() {
 
             // Handle application result.
             match result {
-                Ok(Some(mut replacement_criteria)) => {
+                Ok(Some((mut replacement_criteria, edited_args))) => {
+                    // The search may have been edited in the interface.
+                    let searched_args = edited_args.as_ref().unwrap_or(&args);
+
                     // If we detected an encoding passed to `rg`, then use that.
-                    if let Some(encoding) = args.encoding {
+                    if let Some(encoding) = &searched_args.encoding {
                         replacement_criteria.set_encoding(encoding);
                     }
+                    replacement_criteria.set_all_or_nothing(args.all_or_nothing);
+
+                    // When renaming, the contents of the files don't matter.
+                    if !args.rename {
+                        search_changed_files(searched_args, &mut replacement_criteria);
+                    }
+
+                    let result = match replacement_criteria.output {
+                        ReplacementOutput::Files if args.rename => {
+                            rename::perform_renames(replacement_criteria)
+                        }
+                        ReplacementOutput::Diff if args.rename => {
+                            rename::perform_diff(replacement_criteria, args.diff.as_deref())
+                        }
+                        ReplacementOutput::Files => {
+                            let mut journal = journal!();
+                            let result = replace::perform_replacements(
+                                replacement_criteria,
+                                Some(&mut journal),
+                            );
+                            if let Err(err) = journal.save() {
+                                log::warn!("Failed to save journal: {}", err);
+                                eprintln!(
+                                    "Failed to save journal, this run cannot be undone: {}",
+                                    err
+                                );
+                            }
+
+                            result
+                        }
+                        ReplacementOutput::Diff => {
+                            replace::perform_diff(replacement_criteria, args.diff.as_deref())
+                        }
+                    };
 
-                    match replace::perform_replacements(replacement_criteria) {
+                    match result {
                         Ok(_) => {}
                         Err(err) => {
                             exit_with_error!("An error occurred during replacement: {}", err);
@@ -0,0 +1,238 @@
+/// The casing style of some text, used to preserve the case of a match when replacing it.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum CaseStyle {
+    /// e.g.: `foo`
+    Lower,
+    /// e.g.: `FOO`
+    Upper,
+    /// e.g.: `Foo`
+    Title,
+    /// e.g.: `fooBar`
+    Camel,
+    /// e.g.: `FooBar`
+    Pascal,
+    /// e.g.: `foo_bar` or `foo-bar`
+    LowerSeparated(char),
+    /// e.g.: `FOO_BAR` or `FOO-BAR`
+    UpperSeparated(char),
+    /// Anything else: text with this style is left as is.
+    Unknown,
+}
+
+const SEPARATORS: [char; 2] = ['_', '-'];
+
+impl CaseStyle {
+    /// Detects the casing style of the given text.
+    pub fn detect(text: &str) -> CaseStyle {
+        if !text.chars().any(char::is_alphabetic)
+            || !text
+                .chars()
+                .all(|c| c.is_alphanumeric() || SEPARATORS.contains(&c))
+        {
+            return CaseStyle::Unknown;
+        }
+
+        let has_lower = text.chars().any(char::is_lowercase);
+        let has_upper = text.chars().any(char::is_uppercase);
+
+        let separator = SEPARATORS.iter().copied().find(|s| text.contains(*s));
+        if let Some(sep) = separator {
+            // Mixing separators isn't a style we can reproduce.
+            if SEPARATORS.iter().any(|s| *s != sep && text.contains(*s)) {
+                return CaseStyle::Unknown;
+            }
+
+            return match (has_lower, has_upper) {
+                (true, false) => CaseStyle::LowerSeparated(sep),
+                (false, true) => CaseStyle::UpperSeparated(sep),
+                _ => CaseStyle::Unknown,
+            };
+        }
+
+        match (has_lower, has_upper) {
+            (true, false) => CaseStyle::Lower,
+            (false, true) => CaseStyle::Upper,
+            _ => {
+                let first_is_upper = text
+                    .chars()
+                    .find(|c| c.is_alphabetic())
+                    .map(char::is_uppercase);
+                let upper_count = text.chars().filter(|c| c.is_uppercase()).count();
+                match (first_is_upper, upper_count) {
+                    (Some(true), 1) => CaseStyle::Title,
+                    (Some(true), _) => CaseStyle::Pascal,
+                    _ => CaseStyle::Camel,
+                }
+            }
+        }
+    }
+
+    /// Transforms `text` so that it has this casing style.
+    pub fn apply(self, text: &str) -> String {
+        // If the text isn't made up of words, then we can only change the case of the letters.
+        let words = match split_words(text) {
+            Some(words) if !words.is_empty() => words,
+            _ => {
+                return match self {
+                    CaseStyle::Lower | CaseStyle::LowerSeparated(_) => text.to_lowercase(),
+                    CaseStyle::Upper | CaseStyle::UpperSeparated(_) => text.to_uppercase(),
+                    CaseStyle::Title | CaseStyle::Pascal => capitalise_first(text),
+                    CaseStyle::Camel | CaseStyle::Unknown => text.to_owned(),
+                }
+            }
+        };
+
+        match self {
+            CaseStyle::Lower => text.to_lowercase(),
+            CaseStyle::Upper => text.to_uppercase(),
+            CaseStyle::Title => capitalise_first(text),
+            CaseStyle::Camel => words
+                .iter()
+                .enumerate()
+                .map(|(i, w)| {
+                    if i == 0 {
+                        w.to_lowercase()
+                    } else {
+                        capitalise_word(w)
+                    }
+                })
+                .collect(),
+            CaseStyle::Pascal => words.iter().map(|w| capitalise_word(w)).collect(),
+            CaseStyle::LowerSeparated(sep) => words
+                .iter()
+                .map(|w| w.to_lowercase())
+                .collect::<Vec<_>>()
+                .join(&sep.to_string()),
+            CaseStyle::UpperSeparated(sep) => words
+                .iter()
+                .map(|w| w.to_uppercase())
+                .collect::<Vec<_>>()
+                .join(&sep.to_string()),
+            CaseStyle::Unknown => text.to_owned(),
+        }
+    }
+}
+
+/// Transforms `replacement` so that it has the same casing style as `matched`.
+pub fn preserve_case(matched: &str, replacement: &str) -> String {
+    CaseStyle::detect(matched).apply(replacement)
+}
+
+/// Splits an identifier into its words, e.g.: `fooBar`, `FooBar` and `foo_bar` all become `["foo", "bar"]`
+/// (preserving the original case of each word). Returns `None` if `text` isn't an identifier.
+fn split_words(text: &str) -> Option<Vec<&str>> {
+    if !text
+        .chars()
+        .all(|c| c.is_alphanumeric() || SEPARATORS.contains(&c))
+    {
+        return None;
+    }
+
+    let mut words = vec![];
+    for part in text.split(&SEPARATORS[..]).filter(|p| !p.is_empty()) {
+        let chars = part.char_indices().collect::<Vec<_>>();
+        let mut start = 0;
+        for i in 1..chars.len() {
+            let (idx, curr) = chars[i];
+            let prev = chars[i - 1].1;
+            let next = chars.get(i + 1).map(|(_, c)| *c);
+
+            // A word starts at an uppercase letter which follows a lowercase letter or digit (`fooBar`), or at the
+            // last uppercase letter of an acronym which is followed by a lowercase letter (`HTTPServer`).
+            let is_boundary = curr.is_uppercase()
+                && (prev.is_lowercase()
+                    || prev.is_numeric()
+                    || (prev.is_uppercase() && next.is_some_and(char::is_lowercase)));
+
+            if is_boundary {
+                words.push(&part[start..idx]);
+                start = idx;
+            }
+        }
+
+        words.push(&part[start..]);
+    }
+
+    Some(words)
+}
+
+fn capitalise_first(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+fn capitalise_word(word: &str) -> String {
+    capitalise_first(&word.to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::model::case::{preserve_case, split_words, CaseStyle};
+
+    #[test]
+    fn it_detects_case_styles() {
+        assert_eq!(CaseStyle::detect("foo"), CaseStyle::Lower);
+        assert_eq!(CaseStyle::detect("foo2"), CaseStyle::Lower);
+        assert_eq!(CaseStyle::detect("FOO"), CaseStyle::Upper);
+        assert_eq!(CaseStyle::detect("Foo"), CaseStyle::Title);
+        assert_eq!(CaseStyle::detect("fooBar"), CaseStyle::Camel);
+        assert_eq!(CaseStyle::detect("FooBar"), CaseStyle::Pascal);
+        assert_eq!(CaseStyle::detect("foo_bar"), CaseStyle::LowerSeparated('_'));
+        assert_eq!(CaseStyle::detect("foo-bar"), CaseStyle::LowerSeparated('-'));
+        assert_eq!(CaseStyle::detect("FOO_BAR"), CaseStyle::UpperSeparated('_'));
+        assert_eq!(CaseStyle::detect("FOO-BAR"), CaseStyle::UpperSeparated('-'));
+        assert_eq!(CaseStyle::detect("Foo_Bar"), CaseStyle::Unknown);
+        assert_eq!(CaseStyle::detect("foo_bar-baz"), CaseStyle::Unknown);
+        assert_eq!(CaseStyle::detect("foo bar"), CaseStyle::Unknown);
+        assert_eq!(CaseStyle::detect("123"), CaseStyle::Unknown);
+        assert_eq!(CaseStyle::detect(""), CaseStyle::Unknown);
+    }
+
+    #[test]
+    fn it_splits_words() {
+        assert_eq!(split_words("foo"), Some(vec!["foo"]));
+        assert_eq!(split_words("fooBar"), Some(vec!["foo", "Bar"]));
+        assert_eq!(split_words("FooBar"), Some(vec!["Foo", "Bar"]));
+        assert_eq!(split_words("foo_bar"), Some(vec!["foo", "bar"]));
+        assert_eq!(split_words("FOO_BAR"), Some(vec!["FOO", "BAR"]));
+        assert_eq!(split_words("__foo--bar"), Some(vec!["foo", "bar"]));
+        assert_eq!(split_words("HTTPServer"), Some(vec!["HTTP", "Server"]));
+        assert_eq!(split_words("base64Value"), Some(vec!["base64", "Value"]));
+        assert_eq!(split_words("foo(bar)"), None);
+    }
+
+    #[test]
+    fn it_preserves_case() {
+        for replacement in &["bazQux", "BazQux", "baz_qux", "BAZ_QUX", "baz-qux"] {
+            assert_eq!(preserve_case("fooBar", replacement), "bazQux");
+            assert_eq!(preserve_case("FooBar", replacement), "BazQux");
+            assert_eq!(preserve_case("FOO_BAR", replacement), "BAZ_QUX");
+            assert_eq!(preserve_case("foo_bar", replacement), "baz_qux");
+            assert_eq!(preserve_case("foo-bar", replacement), "baz-qux");
+        }
+    }
+
+    #[test]
+    fn it_preserves_case_of_single_words() {
+        assert_eq!(preserve_case("foo", "Bar"), "bar");
+        assert_eq!(preserve_case("FOO", "bar"), "BAR");
+        assert_eq!(preserve_case("Foo", "bar"), "Bar");
+        assert_eq!(preserve_case("Foo", "barBaz"), "BarBaz");
+        assert_eq!(preserve_case("foo", "bar_baz"), "bar_baz");
+        assert_eq!(preserve_case("FOO", "bar_baz"), "BAR_BAZ");
+    }
+
+    #[test]
+    fn it_leaves_unknown_styles_alone() {
+        assert_eq!(preserve_case("Foo_Bar", "bazQux"), "bazQux");
+        assert_eq!(preserve_case("foo bar", "bazQux"), "bazQux");
+        assert_eq!(preserve_case("fooBar", "baz(qux)"), "baz(qux)");
+        assert_eq!(preserve_case("FOO_BAR", "baz(qux)"), "BAZ(QUX)");
+        assert_eq!(preserve_case("FooBar", "baz(qux)"), "Baz(qux)");
+    }
+}
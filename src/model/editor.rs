@@ -0,0 +1,279 @@
+/// Text which is being edited, and the position of the cursor within it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Editor {
+    text: String,
+    /// The byte offset of the cursor in `text`, which is always on a char boundary.
+    cursor: usize,
+}
+
+/// Whether `ch` is part of a word, for moving and deleting by words.
+fn is_word_char(ch: char) -> bool {
+    ch.is_alphanumeric() || ch == '_'
+}
+
+impl Editor {
+    /// Creates an editor with the cursor at the end of `text`.
+    pub fn new(text: impl Into<String>) -> Editor {
+        let text = text.into();
+        Editor {
+            cursor: text.len(),
+            text,
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.text.split('\n').count()
+    }
+
+    /// Returns the index of the line the cursor is on, and the text on that line before the cursor.
+    pub fn cursor_line(&self) -> (usize, &str) {
+        let line = self.text[..self.cursor].matches('\n').count();
+        (line, &self.text[self.line_start()..self.cursor])
+    }
+
+    /// The offset of the start of the line the cursor is on.
+    fn line_start(&self) -> usize {
+        self.text[..self.cursor].rfind('\n').map_or(0, |i| i + 1)
+    }
+
+    /// The offset of the end of the line the cursor is on (before its newline, if it has one).
+    fn line_end(&self) -> usize {
+        self.text[self.cursor..]
+            .find('\n')
+            .map_or(self.text.len(), |i| self.cursor + i)
+    }
+
+    fn prev_char_offset(&self) -> Option<usize> {
+        self.text[..self.cursor]
+            .char_indices()
+            .next_back()
+            .map(|(i, _)| i)
+    }
+
+    fn next_char_offset(&self) -> Option<usize> {
+        self.text[self.cursor..]
+            .chars()
+            .next()
+            .map(|ch| self.cursor + ch.len_utf8())
+    }
+
+    /// The offset of the start of the word before the cursor (skipping anything between it and the cursor).
+    fn prev_word_offset(&self) -> usize {
+        let before = self.text[..self.cursor].trim_end_matches(|ch| !is_word_char(ch));
+        before.trim_end_matches(is_word_char).len()
+    }
+
+    /// The offset of the end of the word after the cursor (skipping anything between it and the cursor).
+    fn next_word_offset(&self) -> usize {
+        let after = self.text[self.cursor..].trim_start_matches(|ch| !is_word_char(ch));
+        self.text.len() - after.trim_start_matches(is_word_char).len()
+    }
+
+    pub fn insert(&mut self, ch: char) {
+        self.text.insert(self.cursor, ch);
+        self.cursor += ch.len_utf8();
+    }
+
+    /// Deletes the char before the cursor.
+    pub fn backspace(&mut self) {
+        if let Some(offset) = self.prev_char_offset() {
+            self.delete_back_to(offset);
+        }
+    }
+
+    /// Deletes the char after the cursor.
+    pub fn delete(&mut self) {
+        if let Some(offset) = self.next_char_offset() {
+            self.text.replace_range(self.cursor..offset, "");
+        }
+    }
+
+    /// Deletes the word before the cursor, like <control+w> in a shell.
+    pub fn delete_word(&mut self) {
+        self.delete_back_to(self.prev_word_offset());
+    }
+
+    /// Deletes everything on the line before the cursor, like <control+u> in a shell.
+    pub fn delete_line(&mut self) {
+        self.delete_back_to(self.line_start());
+    }
+
+    fn delete_back_to(&mut self, offset: usize) {
+        self.text.replace_range(offset..self.cursor, "");
+        self.cursor = offset;
+    }
+
+    pub fn move_left(&mut self) {
+        if let Some(offset) = self.prev_char_offset() {
+            self.cursor = offset;
+        }
+    }
+
+    pub fn move_right(&mut self) {
+        if let Some(offset) = self.next_char_offset() {
+            self.cursor = offset;
+        }
+    }
+
+    pub fn move_word_left(&mut self) {
+        self.cursor = self.prev_word_offset();
+    }
+
+    pub fn move_word_right(&mut self) {
+        self.cursor = self.next_word_offset();
+    }
+
+    /// Moves the cursor to the start of its line.
+    pub fn move_home(&mut self) {
+        self.cursor = self.line_start();
+    }
+
+    /// Moves the cursor to the end of its line.
+    pub fn move_end(&mut self) {
+        self.cursor = self.line_end();
+    }
+
+    /// Moves the cursor to the line above (or below), keeping it in the same column if that line is long enough.
+    /// Returns whether there was a line to move to.
+    pub fn move_vertically(&mut self, up: bool) -> bool {
+        let column = self.cursor_line().1.chars().count();
+        let line_start = if up {
+            match self.line_start() {
+                0 => return false,
+                start => self.text[..start - 1].rfind('\n').map_or(0, |i| i + 1),
+            }
+        } else {
+            match self.line_end() {
+                end if end == self.text.len() => return false,
+                end => end + 1,
+            }
+        };
+
+        let line = self.text[line_start..]
+            .split('\n')
+            .next()
+            .unwrap_or_default();
+        let offset = line
+            .char_indices()
+            .nth(column)
+            .map_or(line.len(), |(i, _)| i);
+        self.cursor = line_start + offset;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::model::Editor;
+
+    /// Shows the text with a "|" where the cursor is.
+    fn show(editor: &Editor) -> String {
+        let (before, after) = editor.text.split_at(editor.cursor);
+        format!("{}|{}", before, after)
+    }
+
+    #[test]
+    fn it_inserts_and_deletes_at_the_cursor() {
+        let mut editor = Editor::new("fo bar");
+        assert_eq!(show(&editor), "fo bar|");
+
+        editor.move_word_left();
+        editor.move_left();
+        editor.insert('o');
+        assert_eq!(show(&editor), "foo| bar");
+
+        editor.move_right();
+        editor.delete();
+        editor.insert('B');
+        assert_eq!(show(&editor), "foo B|ar");
+
+        editor.backspace();
+        editor.backspace();
+        assert_eq!(show(&editor), "foo|ar");
+
+        editor.move_home();
+        editor.backspace();
+        editor.delete();
+        assert_eq!(show(&editor), "|ooar");
+
+        editor.move_end();
+        editor.delete();
+        assert_eq!(show(&editor), "ooar|");
+    }
+
+    #[test]
+    fn it_handles_multibyte_chars() {
+        let mut editor = Editor::new("añb");
+        editor.move_left();
+        editor.move_left();
+        assert_eq!(show(&editor), "a|ñb");
+        editor.delete();
+        assert_eq!(show(&editor), "a|b");
+        editor.insert('é');
+        editor.backspace();
+        editor.backspace();
+        assert_eq!(show(&editor), "|b");
+    }
+
+    #[test]
+    fn it_moves_and_deletes_by_words() {
+        let mut editor = Editor::new("foo_1 (bar)  baz");
+        editor.move_word_left();
+        assert_eq!(show(&editor), "foo_1 (bar)  |baz");
+        editor.move_word_left();
+        assert_eq!(show(&editor), "foo_1 (|bar)  baz");
+        editor.move_word_left();
+        editor.move_word_left();
+        assert_eq!(show(&editor), "|foo_1 (bar)  baz");
+        editor.move_word_right();
+        assert_eq!(show(&editor), "foo_1| (bar)  baz");
+        editor.move_word_right();
+        assert_eq!(show(&editor), "foo_1 (bar|)  baz");
+
+        editor.move_end();
+        editor.delete_word();
+        assert_eq!(show(&editor), "foo_1 (bar)  |");
+        editor.delete_word();
+        assert_eq!(show(&editor), "foo_1 (|");
+    }
+
+    #[test]
+    fn it_edits_multiple_lines() {
+        let mut editor = Editor::new("first\nab\nthird");
+        assert_eq!(editor.line_count(), 3);
+        assert_eq!(editor.cursor_line(), (2, "third"));
+
+        editor.move_home();
+        editor.move_right();
+        editor.move_right();
+        editor.move_right();
+        assert!(editor.move_vertically(true));
+        assert_eq!(show(&editor), "first\nab|\nthird");
+        assert!(editor.move_vertically(true));
+        assert_eq!(show(&editor), "fi|rst\nab\nthird");
+        assert!(!editor.move_vertically(true));
+        assert!(editor.move_vertically(false));
+        assert!(editor.move_vertically(false));
+        assert_eq!(show(&editor), "first\nab\nth|ird");
+        assert!(!editor.move_vertically(false));
+
+        editor.move_end();
+        editor.delete_line();
+        assert_eq!(show(&editor), "first\nab\n|");
+        editor.backspace();
+        assert_eq!(editor.cursor_line(), (1, "ab"));
+        editor.move_home();
+        editor.delete_line();
+        assert_eq!(show(&editor), "first\n|ab");
+    }
+}
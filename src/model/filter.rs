@@ -0,0 +1,59 @@
+use regex::{Regex, RegexBuilder};
+
+/// Narrows the list of matches down to those whose path or line text match what the user entered.
+/// The text is used as a regular expression if it's valid, and otherwise it's matched literally. Like
+/// `rg --smart-case`, it's case insensitive unless it contains an uppercase character.
+#[derive(Debug, Clone)]
+pub struct Filter {
+    text: String,
+    regex: Regex,
+}
+
+impl Filter {
+    pub fn new<S: AsRef<str>>(text: S) -> Filter {
+        let text = text.as_ref().to_owned();
+        let case_insensitive = !text.chars().any(char::is_uppercase);
+        let build = |source: &str| {
+            RegexBuilder::new(source)
+                .case_insensitive(case_insensitive)
+                .build()
+        };
+
+        // NOTE: the escaped text is always a valid regular expression.
+        let regex = build(&text).unwrap_or_else(|_| build(&regex::escape(&text)).unwrap());
+        Filter { text, regex }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn is_match(&self, haystack: &str) -> bool {
+        self.regex.is_match(haystack)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Filter;
+
+    #[test]
+    fn it_matches_regular_expressions() {
+        let filter = Filter::new(r"src/.*\.rs$");
+        assert!(filter.is_match("src/main.rs"));
+        assert!(!filter.is_match("src/main.rs.orig"));
+    }
+
+    #[test]
+    fn it_matches_invalid_regular_expressions_literally() {
+        let filter = Filter::new("foo(");
+        assert!(filter.is_match("call foo(bar)"));
+        assert!(!filter.is_match("call foo"));
+    }
+
+    #[test]
+    fn it_uses_smart_case() {
+        assert!(Filter::new("foo").is_match("FOO"));
+        assert!(!Filter::new("Foo").is_match("FOO"));
+    }
+}
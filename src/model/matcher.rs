@@ -0,0 +1,480 @@
+use std::ops::Range;
+
+use anyhow::Result;
+use regex::bytes::RegexBuilder;
+
+/// Options which affect how the user's patterns are compiled.
+/// These mirror the flags that were passed to `rg`, so that we find the same matches that it did.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MatcherOptions {
+    pub ignore_case: bool,
+    pub smart_case: bool,
+    pub word_regexp: bool,
+    pub multiline_dotall: bool,
+    pub crlf: bool,
+    pub fixed_strings: bool,
+    pub pcre2: bool,
+}
+
+/// The range of each group in a match (or `None` if the group didn't participate in the match).
+type Groups = Vec<Option<Range<usize>>>;
+
+/// A compiled pattern, using the same regex engine that `rg` did.
+#[derive(Debug, Clone)]
+enum Regex {
+    Default(regex::bytes::Regex),
+    Pcre2(pcre2::bytes::Regex),
+}
+
+impl Regex {
+    fn new(source: &str, case_insensitive: bool, options: &MatcherOptions) -> Result<Regex> {
+        // NOTE: ripgrep always treats `^` and `$` as line anchors.
+        let regex = if options.pcre2 {
+            Regex::Pcre2(
+                pcre2::bytes::RegexBuilder::new()
+                    .caseless(case_insensitive)
+                    .multi_line(true)
+                    .dotall(options.multiline_dotall)
+                    .crlf(options.crlf)
+                    .utf(true)
+                    .ucp(true)
+                    .jit_if_available(true)
+                    .build(source)?,
+            )
+        } else {
+            Regex::Default(
+                RegexBuilder::new(source)
+                    .case_insensitive(case_insensitive)
+                    .multi_line(true)
+                    .dot_matches_new_line(options.multiline_dotall)
+                    .crlf(options.crlf)
+                    .build()?,
+            )
+        };
+
+        Ok(regex)
+    }
+
+    /// Returns the range of each group if the pattern matches exactly `range` within `haystack`.
+    fn groups_at(&self, haystack: &[u8], range: &Range<usize>) -> Option<Groups> {
+        match self {
+            Regex::Default(regex) => regex
+                .captures_at(haystack, range.start)
+                .filter(|caps| caps.get(0).map(|m| m.range()) == Some(range.clone()))
+                .map(|caps| caps.iter().map(|m| m.map(|m| m.range())).collect()),
+            Regex::Pcre2(regex) => {
+                let mut locations = regex.capture_locations();
+                match regex.captures_read_at(&mut locations, haystack, range.start) {
+                    Ok(Some(m)) if m.start() == range.start && m.end() == range.end => Some(
+                        (0..locations.len())
+                            .map(|i| locations.get(i).map(|(start, end)| start..end))
+                            .collect(),
+                    ),
+                    Ok(_) => None,
+                    Err(e) => {
+                        log::debug!("Failed to match with PCRE2: {}", e);
+                        None
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns the range of the first match in `haystack` which starts at or after `start`.
+    fn find_at(&self, haystack: &[u8], start: usize) -> Option<Range<usize>> {
+        match self {
+            Regex::Default(regex) => regex.find_at(haystack, start).map(|m| m.range()),
+            Regex::Pcre2(regex) => match regex.find_at(haystack, start) {
+                Ok(m) => m.map(|m| m.start()..m.end()),
+                Err(e) => {
+                    log::debug!("Failed to match with PCRE2: {}", e);
+                    None
+                }
+            },
+        }
+    }
+
+    /// Returns the index of the group with the given name.
+    fn group_index(&self, name: &str) -> Option<usize> {
+        match self {
+            Regex::Default(regex) => regex.capture_names().position(|n| n == Some(name)),
+            Regex::Pcre2(regex) => regex
+                .capture_names()
+                .iter()
+                .position(|n| n.as_deref() == Some(name)),
+        }
+    }
+}
+
+/// Re-runs the user's patterns over the matches that `rg` reported, so that capture groups can be
+/// expanded in the replacement text.
+#[derive(Debug, Clone)]
+pub struct Matcher {
+    /// The patterns as they were given to `rg`.
+    patterns: Vec<String>,
+    /// Each pattern which could be compiled, along with its index in `patterns`.
+    regexes: Vec<(usize, Regex)>,
+}
+
+impl Default for Matcher {
+    fn default() -> Self {
+        Matcher::empty()
+    }
+}
+
+impl Matcher {
+    /// A matcher without any patterns: only `$0` and `$$` are expanded.
+    pub const fn empty() -> Matcher {
+        Matcher {
+            patterns: vec![],
+            regexes: vec![],
+        }
+    }
+
+    pub fn new<S: AsRef<str>>(patterns: &[S], options: MatcherOptions) -> Matcher {
+        let patterns = patterns
+            .iter()
+            .map(|pattern| pattern.as_ref().to_owned())
+            .collect::<Vec<_>>();
+        let regexes = patterns
+            .iter()
+            .enumerate()
+            .filter_map(|(i, pattern)| {
+                let case_insensitive = options.ignore_case
+                    || (options.smart_case && !pattern.chars().any(char::is_uppercase));
+
+                // NOTE: the escaped pattern is valid for both regex engines.
+                let source = if options.fixed_strings {
+                    regex::escape(pattern)
+                } else {
+                    pattern.to_owned()
+                };
+                let source = if options.word_regexp {
+                    format!(r"\b(?:{})\b", source)
+                } else {
+                    source
+                };
+
+                match Regex::new(&source, case_insensitive, &options) {
+                    Ok(regex) => Some((i, regex)),
+                    Err(e) => {
+                        log::warn!("Failed to compile pattern \"{}\": {}", pattern, e);
+                        log::warn!("Capture groups will not be expanded for this pattern");
+                        None
+                    }
+                }
+            })
+            .collect();
+
+        Matcher { patterns, regexes }
+    }
+
+    /// Returns the pattern at `index`, as it was given to `rg`.
+    pub fn pattern(&self, index: usize) -> Option<&str> {
+        self.patterns.get(index).map(String::as_str)
+    }
+
+    /// Finds the groups for the match at `range` within `haystack`, along with the pattern which matched (and its
+    /// index). Each pattern is tried in turn, and the first one that matches exactly `range` is used.
+    fn groups_at(&self, haystack: &[u8], range: &Range<usize>) -> Option<(usize, &Regex, Groups)> {
+        self.regexes.iter().find_map(|(i, regex)| {
+            regex
+                .groups_at(haystack, range)
+                .map(|groups| (*i, regex, groups))
+        })
+    }
+
+    /// Returns the index of the pattern which produced the match at `range` within `haystack`, if any of them
+    /// match exactly `range` (preferring earlier patterns, as `rg` does).
+    pub fn pattern_at(&self, haystack: &[u8], range: &Range<usize>) -> Option<usize> {
+        self.groups_at(haystack, range).map(|(i, _, _)| i)
+    }
+
+    /// Finds every match of the patterns in `haystack`, in order and without overlapping. Like `rg`, the leftmost
+    /// match is used (preferring earlier patterns if several match at the same position), and empty matches are
+    /// ignored.
+    pub fn find_all(&self, haystack: &[u8]) -> Vec<Range<usize>> {
+        let mut ranges = vec![];
+        let mut start = 0;
+        while start <= haystack.len() {
+            let next = self
+                .regexes
+                .iter()
+                .filter_map(|(_, regex)| regex.find_at(haystack, start))
+                .min_by_key(|range| range.start);
+
+            match next {
+                None => break,
+                Some(range) if range.is_empty() => start = range.start + 1,
+                Some(range) => {
+                    start = range.end;
+                    ranges.push(range);
+                }
+            }
+        }
+
+        ranges
+    }
+
+    /// Expands the capture group references (`$1`, `$name`, `${name}`) in `replacement` for the match at
+    /// `range` within `haystack`. Use `$$` for a literal `$`.
+    ///
+    /// If none of the patterns match at `range` then `$0` is expanded to the match and all other groups
+    /// are expanded to an empty string.
+    pub fn expand(&self, replacement: &str, haystack: &[u8], range: Range<usize>) -> String {
+        // Fast path: nothing to expand.
+        if !replacement.contains('$') {
+            return replacement.to_owned();
+        }
+
+        let groups = self.groups_at(haystack, &range);
+        if groups.is_none() {
+            log::debug!("No pattern matched at {:?}, only expanding $0", range);
+        }
+
+        let group_bytes = |groups: &[Option<Range<usize>>], i: usize| {
+            groups
+                .get(i)
+                .cloned()
+                .flatten()
+                .and_then(|range| haystack.get(range))
+        };
+        let expanded = expand_with(replacement, |group| match (&groups, group) {
+            (Some((_, _, groups)), Group::Index(i)) => group_bytes(groups, i),
+            (Some((_, regex, groups)), Group::Name(name)) => {
+                regex.group_index(name).and_then(|i| group_bytes(groups, i))
+            }
+            (None, Group::Index(0)) => haystack.get(range.clone()),
+            (None, _) => None,
+        });
+
+        String::from_utf8_lossy(&expanded).into_owned()
+    }
+}
+
+/// A reference to a capture group in the replacement text.
+#[derive(Debug, PartialEq, Eq)]
+enum Group<'a> {
+    Index(usize),
+    Name(&'a str),
+}
+
+/// Expands the replacement text, using `lookup` to find the contents of each group.
+/// This follows the same rules as `regex::Captures::expand` (and therefore `rg --replace`):
+///
+/// * `$$` is a literal `$`
+/// * `${name}` refers to a group by name (or index if `name` is a number)
+/// * `$name` uses the longest sequence of `[_0-9a-zA-Z]` as the name
+/// * any other `$` is left as is
+/// * groups which didn't participate in the match are replaced with an empty string
+fn expand_with<'h>(replacement: &str, lookup: impl Fn(Group) -> Option<&'h [u8]>) -> Vec<u8> {
+    let mut dst = Vec::with_capacity(replacement.len());
+    let mut rest = replacement;
+    while let Some(idx) = rest.find('$') {
+        dst.extend_from_slice(&rest.as_bytes()[..idx]);
+        rest = &rest[idx..];
+
+        if rest.starts_with("$$") {
+            dst.push(b'$');
+            rest = &rest[2..];
+            continue;
+        }
+
+        let (name, len) = if let Some(braced) = rest.strip_prefix("${") {
+            match braced.find('}') {
+                Some(end) => (&braced[..end], end + 3),
+                None => ("", 0),
+            }
+        } else {
+            let end = rest[1..]
+                .find(|c: char| !(c == '_' || c.is_ascii_alphanumeric()))
+                .unwrap_or(rest.len() - 1);
+            (&rest[1..=end], end + 1)
+        };
+
+        if name.is_empty() {
+            dst.push(b'$');
+            rest = &rest[1..];
+            continue;
+        }
+
+        let group = match name.parse::<usize>() {
+            Ok(i) => Group::Index(i),
+            Err(_) => Group::Name(name),
+        };
+        if let Some(bytes) = lookup(group) {
+            dst.extend_from_slice(bytes);
+        }
+
+        rest = &rest[len..];
+    }
+
+    dst.extend_from_slice(rest.as_bytes());
+    dst
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::model::{Matcher, MatcherOptions};
+
+    fn matcher(patterns: &[&str]) -> Matcher {
+        Matcher::new(patterns, MatcherOptions::default())
+    }
+
+    #[test]
+    fn it_does_not_expand_text_without_groups() {
+        let m = matcher(&[r"foo\((\w+)\)"]);
+        assert_eq!(m.expand("bar", b"foo(baz)", 0..8), "bar");
+    }
+
+    #[test]
+    fn it_expands_numbered_groups() {
+        let m = matcher(&[r"foo\((\w+)\)"]);
+        assert_eq!(m.expand("bar($1)", b"foo(baz)", 0..8), "bar(baz)");
+        assert_eq!(m.expand("${1}_", b"foo(baz)", 0..8), "baz_");
+        assert_eq!(m.expand("[$0]", b"foo(baz)", 0..8), "[foo(baz)]");
+    }
+
+    #[test]
+    fn it_expands_named_groups() {
+        let m = matcher(&[r"(?P<fn>\w+)\((?P<arg>\w+)\)"]);
+        assert_eq!(m.expand("$arg.$fn()", b"foo(baz)", 0..8), "baz.foo()");
+        assert_eq!(m.expand("${arg}s", b"foo(baz)", 0..8), "bazs");
+    }
+
+    #[test]
+    fn it_escapes_dollar_signs() {
+        let m = matcher(&[r"foo\((\w+)\)"]);
+        assert_eq!(m.expand("$$1", b"foo(baz)", 0..8), "$1");
+        assert_eq!(m.expand("$$$1", b"foo(baz)", 0..8), "$baz");
+        assert_eq!(m.expand("cost: $", b"foo(baz)", 0..8), "cost: $");
+        assert_eq!(m.expand("${1", b"foo(baz)", 0..8), "${1");
+    }
+
+    #[test]
+    fn it_expands_unknown_groups_to_nothing() {
+        let m = matcher(&[r"foo\((\w+)\)"]);
+        assert_eq!(m.expand("<$2>", b"foo(baz)", 0..8), "<>");
+        assert_eq!(m.expand("<$nope>", b"foo(baz)", 0..8), "<>");
+        // `$1a` is the group named "1a", the same as `rg --replace`
+        assert_eq!(m.expand("<$1a>", b"foo(baz)", 0..8), "<>");
+    }
+
+    #[test]
+    fn it_matches_at_the_submatch_offset() {
+        let m = matcher(&[r"(\w)\w+"]);
+        let haystack = b"one two three";
+        assert_eq!(m.expand("$1", haystack, 0..3), "o");
+        assert_eq!(m.expand("$1", haystack, 4..7), "t");
+        assert_eq!(m.expand("$1", haystack, 8..13), "t");
+    }
+
+    #[test]
+    fn it_finds_all_matches() {
+        let m = matcher(&["ab", "a", "x*"]);
+        assert_eq!(m.find_all(b"cabaxx"), vec![1..3, 3..4, 4..6]);
+        assert!(matcher(&[]).find_all(b"cabaxx").is_empty());
+    }
+
+    #[test]
+    fn it_uses_the_surrounding_line_for_context() {
+        let m = matcher(&[r"\bfoo(\d)"]);
+        let haystack = b"xfoo1 foo2";
+        assert_eq!(m.expand("$1", haystack, 6..10), "2");
+    }
+
+    #[test]
+    fn it_uses_the_pattern_which_matched() {
+        let m = matcher(&[r"a(\d)", r"b(\d)"]);
+        assert_eq!(m.expand("$1", b"a1 b2", 0..2), "1");
+        assert_eq!(m.expand("$1", b"a1 b2", 3..5), "2");
+    }
+
+    #[test]
+    fn it_attributes_matches_to_patterns() {
+        // The second pattern fails to compile, but the others keep their indices.
+        let m = matcher(&[r"a\d", r"(", r"b\d", r"\d"]);
+        assert_eq!(m.pattern_at(b"a1 b2 3", &(0..2)), Some(0));
+        assert_eq!(m.pattern_at(b"a1 b2 3", &(3..5)), Some(2));
+        assert_eq!(m.pattern_at(b"a1 b2 3", &(6..7)), Some(3));
+        assert_eq!(m.pattern_at(b"a1 b2 3", &(1..3)), None);
+        assert_eq!(m.pattern(1), Some("("));
+        assert_eq!(m.pattern(4), None);
+    }
+
+    #[test]
+    fn it_only_expands_the_whole_match_if_nothing_matched() {
+        let m = Matcher::empty();
+        assert_eq!(m.expand("[$0]", b"a1 b2", 3..5), "[b2]");
+        assert_eq!(m.expand("[$1]", b"a1 b2", 3..5), "[]");
+    }
+
+    #[test]
+    fn it_respects_matcher_options() {
+        let options = MatcherOptions {
+            ignore_case: true,
+            ..MatcherOptions::default()
+        };
+        let m = Matcher::new(&["f(o+)"], options);
+        assert_eq!(m.expand("$1", b"FOO", 0..3), "OO");
+
+        let options = MatcherOptions {
+            smart_case: true,
+            ..MatcherOptions::default()
+        };
+        let m = Matcher::new(&["f(o+)", "B(a+)"], options);
+        assert_eq!(m.expand("$1", b"FOO", 0..3), "OO");
+        assert_eq!(m.expand("$1", b"baa", 0..3), "");
+
+        let options = MatcherOptions {
+            word_regexp: true,
+            ..MatcherOptions::default()
+        };
+        let m = Matcher::new(&["f(o+)"], options);
+        assert_eq!(m.expand("$1", b"foo", 0..3), "oo");
+        assert_eq!(m.expand("$1", b"foox", 0..3), "");
+    }
+
+    #[test]
+    fn it_matches_fixed_strings_literally() {
+        let options = MatcherOptions {
+            fixed_strings: true,
+            ..MatcherOptions::default()
+        };
+        let m = Matcher::new(&["a.b(c)"], options);
+        assert_eq!(m.expand("[$0]", b"a.b(c)", 0..6), "[a.b(c)]");
+        assert_eq!(m.expand("[$1]", b"a.b(c)", 0..6), "[]");
+        assert_eq!(m.expand("[$0]", b"axb(c)", 0..6), "[axb(c)]");
+
+        let options = MatcherOptions {
+            fixed_strings: true,
+            pcre2: true,
+            ..MatcherOptions::default()
+        };
+        let m = Matcher::new(&["$1.00 (x)"], options);
+        assert!(m.groups_at(b"$1.00 (x)", &(0..9)).is_some());
+    }
+
+    #[test]
+    fn it_uses_pcre2() {
+        let options = MatcherOptions {
+            pcre2: true,
+            ..MatcherOptions::default()
+        };
+
+        // Look-around, which the default engine doesn't support.
+        let m = Matcher::new(&[r"(?<=\$)(\d+)(?=\.)"], options);
+        assert_eq!(m.expand("<$1>", b"cost: $12.50", 7..9), "<12>");
+
+        // Backreferences and named groups.
+        let m = Matcher::new(&[r"(?<word>\w+) \k<word>"], options);
+        assert_eq!(m.expand("$word", b"the the end", 0..7), "the");
+        assert_eq!(m.expand("${1}!", b"the the end", 0..7), "the!");
+
+        // These patterns fail to compile without PCRE2.
+        let m = Matcher::new(&[r"(?<=\$)(\d+)"], MatcherOptions::default());
+        assert_eq!(m.expand("<$1>", b"cost: $12.50", 7..9), "<>");
+    }
+}
@@ -1,7 +1,21 @@
+pub mod case;
+pub mod editor;
+pub mod filter;
+pub mod matcher;
 pub mod movement;
+pub mod predicate;
+pub mod preview;
 pub mod printable;
 pub mod replacement;
+pub mod snapshot;
 
+pub use case::*;
+pub use editor::*;
+pub use filter::*;
+pub use matcher::*;
 pub use movement::*;
+pub use predicate::*;
+pub use preview::*;
 pub use printable::*;
 pub use replacement::*;
+pub use snapshot::*;
@@ -0,0 +1,117 @@
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::{anyhow, Error, Result};
+use globset::{GlobBuilder, GlobMatcher};
+use regex::bytes::Regex;
+
+/// Decides which matches are selected (or deselected) in bulk. This is parsed from what the user entered:
+///
+/// * `glob:GLOB` tests the path of the file, or only its name if the glob doesn't contain a `/`
+/// * `path:PATH` tests whether the file is `PATH`, or is inside of the directory `PATH`
+/// * `line:REGEX` tests the line(s) containing the match
+/// * `REGEX` (or `match:REGEX`) tests the text of the match itself
+#[derive(Debug, Clone)]
+pub enum Predicate {
+    Glob { glob: GlobMatcher, file_name: bool },
+    Path(PathBuf),
+    Line(Regex),
+    Match(Regex),
+}
+
+/// Removes any `.` components, so `./src/main.rs` and `src/main.rs` are the same.
+fn normalize(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|c| !matches!(c, Component::CurDir))
+        .collect()
+}
+
+impl FromStr for Predicate {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Predicate> {
+        let (kind, value) = match s.split_once(':') {
+            Some((kind @ ("glob" | "path" | "line" | "match"), value)) => (kind, value),
+            _ => ("match", s),
+        };
+        if value.is_empty() {
+            return Err(anyhow!("Nothing to match was entered"));
+        }
+
+        let predicate = match kind {
+            "glob" => Predicate::Glob {
+                glob: GlobBuilder::new(value)
+                    .literal_separator(true)
+                    .build()?
+                    .compile_matcher(),
+                file_name: !value.contains('/'),
+            },
+            "path" => Predicate::Path(normalize(Path::new(value))),
+            "line" => Predicate::Line(Regex::new(value)?),
+            _ => Predicate::Match(Regex::new(value)?),
+        };
+
+        Ok(predicate)
+    }
+}
+
+impl Predicate {
+    /// Whether the match `text`, on the given `lines` of the file at `path`, satisfies the predicate.
+    pub fn is_match(&self, path: &Path, lines: &[u8], text: &[u8]) -> bool {
+        match self {
+            Predicate::Glob { glob, file_name } => {
+                if *file_name {
+                    path.file_name().is_some_and(|name| glob.is_match(name))
+                } else {
+                    glob.is_match(normalize(path))
+                }
+            }
+            Predicate::Path(prefix) => normalize(path).starts_with(prefix),
+            Predicate::Line(regex) => regex.is_match(lines),
+            Predicate::Match(regex) => regex.is_match(text),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::Predicate;
+
+    fn is_match(predicate: &str, path: &str, lines: &str, text: &str) -> bool {
+        let predicate = predicate.parse::<Predicate>().unwrap();
+        predicate.is_match(Path::new(path), lines.as_bytes(), text.as_bytes())
+    }
+
+    #[test]
+    fn it_matches_globs() {
+        assert!(is_match("glob:*_test.rs", "./src/foo_test.rs", "", ""));
+        assert!(!is_match("glob:*_test.rs", "./src/foo.rs", "", ""));
+        assert!(is_match("glob:src/**/*.rs", "./src/ui/app.rs", "", ""));
+        assert!(!is_match("glob:src/*.rs", "./src/ui/app.rs", "", ""));
+    }
+
+    #[test]
+    fn it_matches_paths() {
+        assert!(is_match("path:src/ui", "./src/ui/app.rs", "", ""));
+        assert!(is_match("path:./src/main.rs", "src/main.rs", "", ""));
+        assert!(!is_match("path:src/u", "src/ui/app.rs", "", ""));
+    }
+
+    #[test]
+    fn it_matches_lines_and_matches() {
+        assert!(is_match("line:// keep", "a", "foo(); // keep\n", "foo"));
+        assert!(!is_match("line:// keep", "a", "foo();\n", "foo"));
+        assert!(is_match("^fo+$", "a", "", "foo"));
+        assert!(is_match("match:^fo+$", "a", "", "foo"));
+        assert!(!is_match("^fo+$", "a", "", "food"));
+    }
+
+    #[test]
+    fn it_fails_to_parse_invalid_predicates() {
+        assert!("glob:a[".parse::<Predicate>().is_err());
+        assert!("line:(".parse::<Predicate>().is_err());
+        assert!("line:".parse::<Predicate>().is_err());
+    }
+}
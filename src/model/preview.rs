@@ -0,0 +1,69 @@
+use std::fs;
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use crate::encoding::decode_lossy;
+use crate::rg::RgEncoding;
+
+/// The lines of a file, which are read to preview the lines around a match.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilePreview {
+    lines: Vec<String>,
+}
+
+impl FilePreview {
+    /// Reads the file at `path`, decoding it in the same way as when its matches are replaced.
+    pub fn read(path: &Path, rg_encoding: &RgEncoding) -> Result<FilePreview> {
+        let bytes =
+            fs::read(path).with_context(|| format!("Failed to read file: {}", path.display()))?;
+        let text = decode_lossy(&bytes, rg_encoding);
+
+        let mut lines = text.split('\n').map(str::to_owned).collect::<Vec<_>>();
+        // The newline at the end of the last line doesn't start another line.
+        if text.ends_with('\n') {
+            lines.pop();
+        }
+
+        Ok(FilePreview { lines })
+    }
+
+    /// Returns the lines with the given line numbers (which start at 1), as far as the file has them.
+    pub fn lines(&self, line_numbers: RangeInclusive<usize>) -> &[String] {
+        let start = line_numbers.start().saturating_sub(1).min(self.lines.len());
+        let end = (*line_numbers.end()).clamp(start, self.lines.len());
+        &self.lines[start..end]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use pretty_assertions::assert_eq;
+    use tempfile::tempdir;
+
+    use crate::model::FilePreview;
+    use crate::rg::RgEncoding;
+
+    #[test]
+    fn it_reads_the_lines_of_a_file() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("file");
+        // UTF-16LE with a BOM, which ripgrep transcodes before searching it.
+        let bytes = "one\r\ntwo\nthree\n"
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .collect::<Vec<_>>();
+        fs::write(&path, [&[0xFF, 0xFE][..], &bytes].concat()).unwrap();
+
+        let preview = FilePreview::read(&path, &RgEncoding::None).unwrap();
+        assert_eq!(preview.lines(1..=2), ["one\r", "two"]);
+        assert_eq!(preview.lines(0..=1), ["one\r"]);
+        assert_eq!(preview.lines(3..=10), ["three"]);
+        assert!(preview.lines(4..=5).is_empty());
+
+        assert!(FilePreview::read(&dir.path().join("missing"), &RgEncoding::None).is_err());
+    }
+}
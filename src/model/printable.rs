@@ -5,19 +5,14 @@ use crate::rg::de::ArbitraryData;
 
 type OneLine = bool;
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, Default)]
 pub enum PrintableStyle {
+    #[default]
     Hidden,
     Common(OneLine),
     All(OneLine),
 }
 
-impl Default for PrintableStyle {
-    fn default() -> Self {
-        PrintableStyle::Hidden
-    }
-}
-
 impl Display for PrintableStyle {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}", self.symbol())
@@ -94,7 +89,7 @@ impl Printable for &str {
                         // Print common whitespace as symbols
                         '\x09' => s.push('→'), // HT (Horizontal Tab)
                         '\x0A' => s.push_str(if oneline { "¬" } else { "¬\n" }), // LF (Line feed)
-                        '\x0D' => s.push('¤'),  // CR (Carriage return)
+                        '\x0D' => s.push('¤'), // CR (Carriage return)
                         '\x20' => s.push('␣'), // SP (Space)
                         // Print other control characters with a replacement
                         '\x00' | '\x01' | '\x02' | '\x03' | '\x04' | '\x05' | '\x06' | '\x07'
@@ -1,14 +1,115 @@
 use std::collections::hash_map::Entry;
 use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::path::PathBuf;
 
-use crate::rg::de::{ArbitraryData, RgMessageKind};
-use crate::ui::line::Item;
+use crate::model::{Matcher, Snapshots};
+use crate::rg::de::{ArbitraryData, RgMessage, RgMessageKind};
+use crate::ui::line::{Item, SubItem};
+
+/// What should be done with the replacements once they've been made.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ReplacementOutput {
+    /// Write the replacements to the files.
+    #[default]
+    Files,
+    /// Write a unified diff of the replacements, rather than changing any files.
+    Diff,
+}
+
+/// What is done with each line containing selected matches.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    /// Replace the selected matches with the replacement text.
+    #[default]
+    Substitute,
+    /// Delete the lines containing the selected matches.
+    DeleteLine,
+    /// Insert the replacement text as a new line above the lines containing the selected matches.
+    InsertAbove,
+    /// Insert the replacement text as a new line below the lines containing the selected matches.
+    InsertBelow,
+}
+
+impl Operation {
+    /// Cycles through each possible value of an `Operation`.
+    pub fn cycle(self) -> Self {
+        match self {
+            Operation::Substitute => Operation::DeleteLine,
+            Operation::DeleteLine => Operation::InsertAbove,
+            Operation::InsertAbove => Operation::InsertBelow,
+            Operation::InsertBelow => Operation::Substitute,
+        }
+    }
+
+    /// Whether the operation changes whole lines rather than the matches in them.
+    pub fn is_line_operation(self) -> bool {
+        !matches!(self, Operation::Substitute)
+    }
+}
+
+impl Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Operation::Substitute => "Substitute",
+            Operation::DeleteLine => "DeleteLine",
+            Operation::InsertAbove => "InsertAbove",
+            Operation::InsertBelow => "InsertBelow",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Replacement text for the matches of particular patterns (as they were given to `rg`), which is used instead of
+/// the replacement text for all matches.
+pub type PatternTexts = HashMap<String, String>;
+
+/// Returns the replacement text for the pattern which produced the match, if it has any.
+pub fn pattern_text<'a>(
+    sub_item: &SubItem,
+    matcher: &Matcher,
+    pattern_texts: &'a PatternTexts,
+) -> Option<&'a str> {
+    sub_item
+        .pattern
+        .and_then(|i| matcher.pattern(i))
+        .and_then(|pattern| pattern_texts.get(pattern))
+        .map(String::as_str)
+}
 
 #[derive(Debug)]
 pub struct ReplacementCriteria {
     pub items: Vec<Item>,
     pub text: String,
+    pub pattern_texts: PatternTexts,
     pub encoding: Option<String>,
+    pub matcher: Matcher,
+    pub preserve_case: bool,
+    pub operation: Operation,
+    pub output: ReplacementOutput,
+    /// Whether to write either every file or none of them (see `replace_files_transactionally`).
+    pub all_or_nothing: bool,
+    pub snapshots: Snapshots,
+}
+
+/// How many of the selected matches in the files which were searched again could be selected again.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Reapplied {
+    pub reapplied: usize,
+    pub dropped: usize,
+}
+
+/// Identifies a matched line by its file, its contents, and how many lines in that file before it had the same
+/// contents (so lines are still identified if lines before them are added or removed).
+type LineKey = (Option<PathBuf>, Vec<u8>, usize);
+
+fn line_key(item: &Item, seen: &mut HashMap<(Option<PathBuf>, Vec<u8>), usize>) -> LineKey {
+    let path = item.path_buf();
+    let lines = item.lines().map(ArbitraryData::to_vec).unwrap_or_default();
+    let n = seen.entry((path.clone(), lines.clone())).or_insert(0);
+    *n += 1;
+
+    (path, lines, *n)
 }
 
 impl ReplacementCriteria {
@@ -17,7 +118,14 @@ impl ReplacementCriteria {
         ReplacementCriteria {
             text,
             items,
+            pattern_texts: PatternTexts::new(),
             encoding: None,
+            matcher: Matcher::empty(),
+            preserve_case: false,
+            operation: Operation::default(),
+            output: ReplacementOutput::default(),
+            all_or_nothing: false,
+            snapshots: Snapshots::default(),
         }
     }
 
@@ -25,6 +133,102 @@ impl ReplacementCriteria {
         self.encoding = Some(encoding.as_ref().to_owned());
     }
 
+    pub fn set_matcher(&mut self, matcher: Matcher) {
+        self.matcher = matcher;
+    }
+
+    pub fn set_pattern_texts(&mut self, pattern_texts: PatternTexts) {
+        self.pattern_texts = pattern_texts;
+    }
+
+    pub fn set_preserve_case(&mut self, preserve_case: bool) {
+        self.preserve_case = preserve_case;
+    }
+
+    pub fn set_operation(&mut self, operation: Operation) {
+        self.operation = operation;
+    }
+
+    pub fn set_output(&mut self, output: ReplacementOutput) {
+        self.output = output;
+    }
+
+    pub fn set_all_or_nothing(&mut self, all_or_nothing: bool) {
+        self.all_or_nothing = all_or_nothing;
+    }
+
+    pub fn set_snapshots(&mut self, snapshots: Snapshots) {
+        self.snapshots = snapshots;
+    }
+
+    /// Removes all the items for the given files, so they won't be changed.
+    pub fn remove_files(&mut self, paths: &[PathBuf]) -> Vec<Item> {
+        let (removed, items) = self
+            .items
+            .drain(..)
+            .partition(|item| item.path_buf().is_some_and(|p| paths.contains(&p)));
+        self.items = items;
+
+        removed
+    }
+
+    /// Replaces the items for the given files with the results of searching them again (`rg_messages`).
+    /// A match is selected again if it's on a line with the same contents as before (counting lines with identical
+    /// contents in order) and at the same position in that line; any other matches in those files are not selected.
+    pub fn reapply(&mut self, paths: &[PathBuf], rg_messages: Vec<RgMessage>) -> Reapplied {
+        let removed = self.remove_files(paths);
+
+        let mut seen = HashMap::new();
+        let previous = removed
+            .iter()
+            .filter(|item| item.kind == RgMessageKind::Match)
+            .map(|item| (line_key(item, &mut seen), item))
+            .collect::<HashMap<_, _>>();
+
+        let next_index = self.items.iter().map(|i| i.index + 1).max().unwrap_or(0);
+        let mut items = rg_messages
+            .into_iter()
+            .filter(|rg_message| !matches!(rg_message, RgMessage::Summary { .. }))
+            .enumerate()
+            .map(|(i, rg_message)| {
+                let mut item = Item::new(next_index + i, rg_message);
+                item.attribute_patterns(&self.matcher);
+                item
+            })
+            .collect::<Vec<_>>();
+
+        let mut seen = HashMap::new();
+        let mut reapplied = 0;
+        for item in items
+            .iter_mut()
+            .filter(|item| item.kind == RgMessageKind::Match)
+        {
+            let previous_item = previous.get(&line_key(item, &mut seen));
+            for idx in 0..item.sub_items().len() {
+                let (should_replace, replacement) = previous_item
+                    .and_then(|p| {
+                        let sub_match = &item.sub_items()[idx].sub_match;
+                        p.sub_items().iter().find(|s| &s.sub_match == sub_match)
+                    })
+                    .map_or((false, None), |s| (s.should_replace, s.replacement.clone()));
+
+                if should_replace {
+                    reapplied += 1;
+                }
+                item.set_should_replace(idx, should_replace);
+                item.set_replacement(idx, replacement);
+            }
+        }
+
+        let selected = removed.iter().map(Item::replace_count).sum::<usize>();
+        self.items.extend(items);
+
+        Reapplied {
+            reapplied,
+            dropped: selected.saturating_sub(reapplied),
+        }
+    }
+
     pub fn as_map(&self) -> HashMap<&ArbitraryData, Vec<&Item>> {
         self.items
             .iter()
@@ -43,3 +247,88 @@ impl ReplacementCriteria {
             })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use pretty_assertions::assert_eq;
+
+    use crate::model::{Reapplied, ReplacementCriteria};
+    use crate::rg::de::test_utilities::RgMessageBuilder;
+    use crate::rg::de::{RgMessage, RgMessageKind, SubMatch};
+    use crate::ui::line::Item;
+
+    fn rg_match(path: &str, offset: usize, lines: &str, submatches: Vec<SubMatch>) -> RgMessage {
+        RgMessageBuilder::new(RgMessageKind::Match)
+            .with_path_text(path)
+            .with_offset(offset)
+            .with_lines_text(lines)
+            .with_submatches(submatches)
+            .build()
+    }
+
+    #[test]
+    fn it_reapplies_selections_which_still_line_up() {
+        let foo = || SubMatch::new_text("foo", 0..3);
+        let mut first = Item::new(
+            0,
+            rg_match(
+                "a",
+                0,
+                "foo foo\n",
+                vec![foo(), SubMatch::new_text("foo", 4..7)],
+            ),
+        );
+        first.set_should_replace(1, false);
+        let mut second = Item::new(1, rg_match("a", 8, "foo bar\n", vec![foo()]));
+        second.set_replacement(0, Some(String::from("baz")));
+        let third = Item::new(2, rg_match("a", 16, "foo qux\n", vec![foo()]));
+        let other = Item::new(3, rg_match("b", 0, "foo\n", vec![foo()]));
+
+        let mut criteria = ReplacementCriteria::new("x", vec![first, second, third, other]);
+        let reapplied = criteria.reapply(
+            &[PathBuf::from("a")],
+            vec![
+                // A line was added before the others, and the last line was changed.
+                rg_match("a", 0, "foo\n", vec![foo()]),
+                rg_match(
+                    "a",
+                    4,
+                    "foo foo\n",
+                    vec![foo(), SubMatch::new_text("foo", 4..7)],
+                ),
+                rg_match("a", 12, "foo bar\n", vec![foo()]),
+                rg_match("a", 20, "foo quux\n", vec![foo()]),
+            ],
+        );
+        assert_eq!(
+            reapplied,
+            Reapplied {
+                reapplied: 2,
+                dropped: 1
+            }
+        );
+
+        let selections = criteria
+            .items
+            .iter()
+            .map(|item| {
+                let selections = (0..item.sub_items().len())
+                    .map(|i| (item.get_should_replace(i), item.get_replacement(i)))
+                    .collect::<Vec<_>>();
+                (item.path_buf().unwrap(), item.offset().unwrap(), selections)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            selections,
+            vec![
+                (PathBuf::from("b"), 0, vec![(true, None)]),
+                (PathBuf::from("a"), 0, vec![(false, None)]),
+                (PathBuf::from("a"), 4, vec![(true, None), (false, None)]),
+                (PathBuf::from("a"), 12, vec![(true, Some("baz"))]),
+                (PathBuf::from("a"), 20, vec![(false, None)]),
+            ]
+        );
+    }
+}
@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use anyhow::{anyhow, Result};
+
+use crate::rg::de::RgMessage;
+use crate::util::hash;
+
+/// The state of a file when it was searched.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileSnapshot {
+    len: u64,
+    modified: Option<SystemTime>,
+    hash: String,
+}
+
+impl FileSnapshot {
+    pub fn take(path: &Path) -> io::Result<FileSnapshot> {
+        let metadata = fs::metadata(path)?;
+        let contents = fs::read(path)?;
+
+        Ok(FileSnapshot {
+            len: contents.len() as u64,
+            modified: metadata.modified().ok(),
+            hash: hash(&contents),
+        })
+    }
+
+    /// Takes a snapshot of the file if `rg_message` is the start of its results.
+    pub fn of_message(rg_message: &RgMessage) -> Option<(PathBuf, FileSnapshot)> {
+        let path = match rg_message {
+            RgMessage::Begin { path } => path,
+            _ => return None,
+        };
+
+        let path_buf = match path.to_path_buf() {
+            Ok(path_buf) => path_buf,
+            Err(e) => {
+                log::warn!("Failed to read path {}: {}", path, e);
+                return None;
+            }
+        };
+        match FileSnapshot::take(&path_buf) {
+            Ok(snapshot) => Some((path_buf, snapshot)),
+            Err(e) => {
+                log::warn!("Failed to take snapshot of {}: {}", path_buf.display(), e);
+                None
+            }
+        }
+    }
+
+    /// Whether the file at `path` has changed since this snapshot was taken.
+    /// The file is only hashed if its size is the same but it was modified since.
+    fn is_changed(&self, path: &Path) -> bool {
+        let metadata = match fs::metadata(path) {
+            Ok(metadata) => metadata,
+            Err(_) => return true,
+        };
+
+        if metadata.len() != self.len {
+            return true;
+        }
+
+        match (metadata.modified().ok(), self.modified) {
+            (Some(modified), Some(snapshot_modified)) if modified == snapshot_modified => false,
+            _ => fs::read(path).map_or(true, |contents| hash(&contents) != self.hash),
+        }
+    }
+}
+
+/// Snapshots of each file which was searched, so we can tell if any were changed before writing them.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Snapshots(HashMap<PathBuf, FileSnapshot>);
+
+impl Snapshots {
+    /// Takes a snapshot of each file in the given `rg` results.
+    pub fn take<'a>(rg_messages: impl IntoIterator<Item = &'a RgMessage>) -> Snapshots {
+        let mut snapshots = Snapshots::default();
+        for rg_message in rg_messages {
+            snapshots.add(rg_message);
+        }
+
+        snapshots
+    }
+
+    /// Takes a snapshot of the file if `rg_message` is the start of its results.
+    pub fn add(&mut self, rg_message: &RgMessage) {
+        if let Some((path, snapshot)) = FileSnapshot::of_message(rg_message) {
+            self.insert(path, snapshot);
+        }
+    }
+
+    pub fn insert(&mut self, path: PathBuf, snapshot: FileSnapshot) {
+        self.0.insert(path, snapshot);
+    }
+
+    /// Replaces the snapshots of the given files with those in `other`.
+    pub fn update(&mut self, paths: &[PathBuf], other: Snapshots) {
+        for path in paths {
+            self.0.remove(path);
+        }
+        self.0.extend(other.0);
+    }
+
+    /// Returns the files which have changed since they were searched, sorted by path.
+    pub fn changed_files(&self) -> Vec<PathBuf> {
+        let mut changed = self
+            .0
+            .iter()
+            .filter(|(path, snapshot)| snapshot.is_changed(path))
+            .map(|(path, _)| path.clone())
+            .collect::<Vec<_>>();
+        changed.sort();
+
+        changed
+    }
+
+    /// Checks that the `contents` read from the file at `path` are the same as when it was searched.
+    /// Files without a snapshot are always allowed.
+    pub fn check(&self, path: &Path, contents: &[u8]) -> Result<()> {
+        match self.0.get(path) {
+            Some(snapshot)
+                if snapshot.len != contents.len() as u64 || snapshot.hash != hash(contents) =>
+            {
+                Err(anyhow!("The file has changed since it was searched"))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tempfile::tempdir;
+
+    use crate::model::Snapshots;
+    use crate::rg::de::test_utilities::RgMessageBuilder;
+    use crate::rg::de::RgMessageKind;
+
+    #[test]
+    fn it_detects_changed_files() {
+        let dir = tempdir().unwrap();
+        let changed = dir.path().join("changed");
+        let touched = dir.path().join("touched");
+        let unchanged = dir.path().join("unchanged");
+        for p in [&changed, &touched, &unchanged] {
+            fs::write(p, "foo").unwrap();
+        }
+
+        let rg_messages = [&changed, &touched, &unchanged]
+            .iter()
+            .map(|p| {
+                RgMessageBuilder::new(RgMessageKind::Begin)
+                    .with_path_text(p.to_string_lossy())
+                    .build()
+            })
+            .collect::<Vec<_>>();
+        let snapshots = Snapshots::take(&rg_messages);
+        assert!(snapshots.changed_files().is_empty());
+
+        fs::write(&changed, "bar").unwrap();
+        // Rewriting a file with the same contents isn't a change.
+        fs::write(&touched, "foo").unwrap();
+
+        assert_eq!(snapshots.changed_files(), vec![changed.clone()]);
+        assert!(snapshots.check(&changed, b"bar").is_err());
+        assert!(snapshots.check(&unchanged, b"foo").is_ok());
+        assert!(snapshots.check(&dir.path().join("other"), b"bar").is_ok());
+    }
+}
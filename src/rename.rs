@@ -0,0 +1,564 @@
+/// Renaming files whose paths match the pattern, rather than replacing the matches in their contents (see `--rename`).
+///
+/// Each file listed by `rg --files` whose path matches is presented as a match, where the "line" is the path itself.
+/// That way the matches can be selected and the new paths previewed in the same way as any other replacement.
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::cli::Args;
+use crate::diff::write_rename_diff;
+use crate::model::{Matcher, ReplacementCriteria, ReplacementOutput};
+use crate::replace::diff_output;
+use crate::rg::de::{ArbitraryData, Duration, RgMessage, RgMessageKind, Stats, SubMatch};
+use crate::rg::exec::list_files;
+use crate::rg::search::Search;
+use crate::ui::line::Item;
+
+/// What happened when renaming a single file.
+#[derive(Debug)]
+pub struct FileRename {
+    /// The path of the file.
+    pub path: String,
+    /// The path the file was (or would be) renamed to.
+    pub new_path: String,
+    /// If set, an error occurred and the file was not renamed.
+    pub error: Option<anyhow::Error>,
+}
+
+fn duration(elapsed: std::time::Duration) -> Duration {
+    Duration {
+        secs: elapsed.as_secs() as usize,
+        nanos: elapsed.subsec_nanos() as usize,
+        human: format!("{:.6}s", elapsed.as_secs_f64()),
+    }
+}
+
+/// Creates the messages `rg` would have sent if it had searched the given `paths` for the patterns in `matcher`.
+/// Paths without any matches are left out.
+fn path_messages(paths: Vec<Vec<u8>>, matcher: &Matcher, start: Instant) -> Vec<RgMessage> {
+    let mut rg_messages = vec![];
+    let mut total = Stats {
+        elapsed: duration(std::time::Duration::ZERO),
+        searches: paths.len(),
+        searches_with_match: 0,
+        bytes_searched: 0,
+        bytes_printed: 0,
+        matched_lines: 0,
+        matches: 0,
+    };
+
+    for path in paths {
+        total.bytes_searched += path.len();
+        let ranges = matcher.find_all(&path);
+        if ranges.is_empty() {
+            continue;
+        }
+
+        let stats = Stats {
+            elapsed: duration(std::time::Duration::ZERO),
+            searches: 1,
+            searches_with_match: 1,
+            bytes_searched: path.len(),
+            bytes_printed: 0,
+            matched_lines: 1,
+            matches: ranges.len(),
+        };
+        total.searches_with_match += 1;
+        total.matched_lines += 1;
+        total.matches += ranges.len();
+
+        let submatches = ranges
+            .into_iter()
+            .map(|range| SubMatch {
+                text: ArbitraryData::from_bytes(path[range.clone()].to_vec()),
+                range,
+            })
+            .collect();
+        let path = ArbitraryData::from_bytes(path);
+        rg_messages.push(RgMessage::Begin { path: path.clone() });
+        rg_messages.push(RgMessage::Match {
+            path: path.clone(),
+            lines: path.clone(),
+            line_number: None,
+            absolute_offset: 0,
+            submatches,
+        });
+        rg_messages.push(RgMessage::End {
+            path,
+            binary_offset: None,
+            stats,
+        });
+    }
+
+    total.elapsed = duration(start.elapsed());
+    rg_messages.push(RgMessage::Summary {
+        elapsed_total: total.elapsed.clone(),
+        stats: total,
+    });
+
+    rg_messages
+}
+
+/// Lists the files which `rg` would search, and finds the matches in each of their paths.
+pub fn search(args: &Args) -> Result<Search> {
+    let start = Instant::now();
+    let rg_args = args.rg_args_for_paths(&args.paths);
+    log::debug!("Listing files to rename with args: {:?}", rg_args);
+
+    let paths = list_files(rg_args)?;
+    Search::messages(&path_messages(paths, &args.matcher(), start))
+}
+
+/// Returns the path that the selected matches in `item` (which must be a match of its path) are replaced to make.
+fn new_path(criteria: &ReplacementCriteria, item: &Item) -> Option<ArbitraryData> {
+    let mut path = item.lines()?.to_vec();
+    for sub_item in item.sub_items().iter().rev().filter(|s| s.should_replace) {
+        let replacement = item.replacement_text(
+            sub_item,
+            &criteria.matcher,
+            &criteria.text,
+            &criteria.pattern_texts,
+            criteria.preserve_case,
+        );
+        path.splice(sub_item.sub_match.range.clone(), replacement.into_bytes());
+    }
+
+    Some(ArbitraryData::from_bytes(path))
+}
+
+/// Checks that the file at `path` can be renamed to `new_path` without overwriting any file which another file in
+/// this run was already renamed to (so this also works for a dry run).
+fn check_rename(path: &Path, new_path: &Path, targets: &mut HashSet<PathBuf>) -> Result<()> {
+    if new_path.as_os_str().is_empty() {
+        return Err(anyhow!("The new path is empty"));
+    }
+
+    path.symlink_metadata()
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    if !targets.insert(new_path.to_owned()) {
+        return Err(anyhow!(
+            "Refusing to overwrite {}, since another file is also renamed to it",
+            new_path.display()
+        ));
+    }
+
+    Ok(())
+}
+
+fn already_exists(new_path: &Path) -> anyhow::Error {
+    anyhow!(
+        "Refusing to overwrite {}, since it already exists",
+        new_path.display()
+    )
+}
+
+/// Checks that nothing exists at `new_path` yet.
+/// NOTE: this is only for when no files are renamed (a dry run or a diff), since a file could be created at
+/// `new_path` after it's checked. Renaming uses `rename_no_replace` instead.
+fn check_not_exists(new_path: &Path) -> Result<()> {
+    match new_path.symlink_metadata() {
+        Ok(_) => Err(already_exists(new_path)),
+        Err(_) => Ok(()),
+    }
+}
+
+/// Renames the file at `path` to `new_path`, failing with `ErrorKind::AlreadyExists` rather than replacing anything
+/// at `new_path` (unlike `fs::rename`, which silently replaces it on unix).
+fn rename_no_replace(path: &Path, new_path: &Path) -> io::Result<()> {
+    #[cfg(target_os = "linux")]
+    {
+        use std::ffi::CString;
+        use std::os::unix::ffi::OsStrExt;
+
+        let from = CString::new(path.as_os_str().as_bytes())?;
+        let to = CString::new(new_path.as_os_str().as_bytes())?;
+        // SAFETY: both paths are NUL-terminated strings, which live until after the call.
+        let result = unsafe {
+            libc::renameat2(
+                libc::AT_FDCWD,
+                from.as_ptr(),
+                libc::AT_FDCWD,
+                to.as_ptr(),
+                libc::RENAME_NOREPLACE,
+            )
+        };
+        if result == 0 {
+            return Ok(());
+        }
+
+        // NOTE: not every filesystem supports this, so then a hard link is used instead.
+        let e = io::Error::last_os_error();
+        if !matches!(
+            e.raw_os_error(),
+            Some(libc::EINVAL) | Some(libc::ENOSYS) | Some(libc::EOPNOTSUPP)
+        ) {
+            return Err(e);
+        }
+    }
+
+    // Linking fails if anything exists at `new_path`.
+    fs::hard_link(path, new_path)?;
+    fs::remove_file(path)
+}
+
+/// Renames the file at `path_data` to `new_path_data`, creating any missing directories.
+fn rename_file(path_data: &ArbitraryData, new_path_data: &ArbitraryData) -> Result<()> {
+    let new_path = new_path_data.to_path_buf()?;
+    if let Some(parent) = new_path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory {}", parent.display()))?;
+    }
+
+    log::debug!("Renaming {} to {}", path_data, new_path_data);
+    rename_no_replace(&path_data.to_path_buf()?, &new_path).map_err(|e| match e.kind() {
+        ErrorKind::AlreadyExists => already_exists(&new_path),
+        _ => e.into(),
+    })
+}
+
+/// Works out the new path of each file with selected matches (in order of their paths), checks that it can be
+/// renamed and then passes its old and new paths to `action`. Returns what happened to each file.
+/// Files whose paths wouldn't change are left out.
+fn for_each_rename<F>(criteria: &ReplacementCriteria, mut action: F) -> Vec<FileRename>
+where
+    F: FnMut(&ArbitraryData, &ArbitraryData) -> Result<()>,
+{
+    log::trace!("--- PERFORM RENAMES ---");
+    log::debug!("Replacement text: \"{}\"", criteria.text);
+
+    let mut renames = criteria
+        .items
+        .iter()
+        .filter(|item| item.kind == RgMessageKind::Match && item.replace_count() > 0)
+        .filter_map(|item| Some((item.path()?.clone(), new_path(criteria, item)?)))
+        .filter(|(path_data, new_path_data)| path_data != new_path_data)
+        .collect::<Vec<_>>();
+    renames.sort_by_cached_key(|(path_data, _)| path_data.to_string());
+
+    let mut targets = HashSet::new();
+    renames
+        .into_iter()
+        .map(|(path_data, new_path_data)| {
+            let result = path_data
+                .to_path_buf()
+                .and_then(|path| Ok((path, new_path_data.to_path_buf()?)))
+                .and_then(|(path, new_path)| check_rename(&path, &new_path, &mut targets))
+                .and_then(|_| action(&path_data, &new_path_data));
+
+            if let Err(e) = &result {
+                log::warn!("Failed to rename {}: {}", path_data, e);
+            }
+
+            FileRename {
+                path: path_data.to_string(),
+                new_path: new_path_data.to_string(),
+                error: result.err(),
+            }
+        })
+        .collect()
+}
+
+/// Renames each file with selected matches in its path, creating any missing directories.
+/// If `dry_run` is set, then the renames are checked but no files are renamed.
+pub fn rename_files(criteria: &ReplacementCriteria, dry_run: bool) -> Vec<FileRename> {
+    for_each_rename(criteria, |path_data, new_path_data| {
+        if dry_run {
+            log::debug!("Dry run, not renaming: {}", path_data);
+            return check_not_exists(&new_path_data.to_path_buf()?);
+        }
+
+        rename_file(path_data, new_path_data)
+    })
+}
+
+/// Writes a diff of the renames to `out` rather than renaming any files. Returns what happened to each file.
+pub fn diff_renames(criteria: &ReplacementCriteria, out: &mut dyn Write) -> Vec<FileRename> {
+    for_each_rename(criteria, |path_data, new_path_data| {
+        check_not_exists(&new_path_data.to_path_buf()?)?;
+        write_rename_diff(out, &path_data.to_vec(), &new_path_data.to_vec())?;
+        Ok(())
+    })
+}
+
+/// Renames the files (or writes a diff of the renames) without the interface, and prints what happened to each
+/// file. See `batch::run`, which does the same for replacements.
+/// Returns whether every file was renamed.
+pub fn run(
+    criteria: &ReplacementCriteria,
+    dry_run: bool,
+    diff_path: Option<&Path>,
+) -> Result<bool> {
+    let (renames, dry_run) = match criteria.output {
+        ReplacementOutput::Files => (rename_files(criteria, dry_run), dry_run),
+        ReplacementOutput::Diff => {
+            let mut out = diff_output(diff_path)?;
+            let renames = diff_renames(criteria, &mut out);
+            out.flush()?;
+            (renames, true)
+        }
+    };
+
+    let to_stderr = criteria.output == ReplacementOutput::Diff;
+    for rename in &renames {
+        if to_stderr || rename.error.is_some() {
+            eprintln!("{}", format_rename(rename));
+        } else {
+            println!("{}", format_rename(rename));
+        }
+    }
+
+    if to_stderr {
+        eprintln!("{}", format_total(&renames, dry_run));
+    } else {
+        println!("{}", format_total(&renames, dry_run));
+    }
+
+    Ok(renames.iter().all(|rename| rename.error.is_none()))
+}
+
+fn format_rename(rename: &FileRename) -> String {
+    match &rename.error {
+        Some(e) => format!("{}: failed: {}", rename.path, e),
+        None => format!("{} -> {}", rename.path, rename.new_path),
+    }
+}
+
+fn format_total(renames: &[FileRename], dry_run: bool) -> String {
+    let failed = renames.iter().filter(|r| r.error.is_some()).count();
+    let mut line = format!(
+        "{} file(s) {}",
+        renames.len() - failed,
+        if dry_run { "to rename" } else { "renamed" }
+    );
+    if failed > 0 {
+        line.push_str(&format!(", {} file(s) failed", failed));
+    }
+    if dry_run {
+        line.push_str(" (dry run, no files were renamed)");
+    }
+
+    line
+}
+
+/// Prints any errors, and returns an error if not all files were renamed.
+fn report_renames(renames: Vec<FileRename>) -> Result<()> {
+    let mut did_fail = false;
+    for rename in renames {
+        if let Some(e) = &rename.error {
+            eprintln!("Failed to rename {}: {}", rename.path, e);
+            did_fail = true;
+        }
+    }
+
+    if did_fail {
+        Err(anyhow!("Failed to rename all files, see log"))
+    } else {
+        Ok(())
+    }
+}
+
+/// Renames the files selected in the interface.
+pub fn perform_renames(criteria: ReplacementCriteria) -> Result<()> {
+    report_renames(rename_files(&criteria, false))
+}
+
+/// Writes a diff of the renames selected in the interface to the file at `diff_path` (or STDOUT).
+pub fn perform_diff(criteria: ReplacementCriteria, diff_path: Option<&Path>) -> Result<()> {
+    let mut out = diff_output(diff_path)?;
+    let renames = diff_renames(&criteria, &mut out);
+    out.flush()?;
+
+    report_renames(renames)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+    use std::time::Instant;
+
+    use pretty_assertions::assert_eq;
+    use tempfile::tempdir;
+
+    use crate::batch::select_all;
+    use crate::model::{Matcher, MatcherOptions, ReplacementCriteria};
+    use crate::rename::{
+        diff_renames, for_each_rename, format_total, path_messages, rename_file, rename_files,
+    };
+    use crate::rg::de::test_utilities::*;
+    use crate::rg::de::{RgMessage, RgMessageKind, SubMatch};
+
+    fn criteria(text: &str, pattern: &str, paths: &[&Path]) -> ReplacementCriteria {
+        let matcher = Matcher::new(&[pattern], MatcherOptions::default());
+        let paths = paths
+            .iter()
+            .map(|p| p.to_str().unwrap().as_bytes().to_vec())
+            .collect();
+        let mut criteria = select_all(text, path_messages(paths, &matcher, Instant::now()));
+        criteria.set_matcher(matcher);
+        criteria
+    }
+
+    #[test]
+    fn it_matches_paths() {
+        let matcher = Matcher::new(&["old"], MatcherOptions::default());
+        let paths = vec![b"src/old_module.rs".to_vec(), b"src/main.rs".to_vec()];
+        let rg_messages = path_messages(paths, &matcher, Instant::now());
+
+        assert_eq!(rg_messages.len(), 4);
+        assert_eq!(
+            rg_messages[1],
+            RgMessageBuilder::new(RgMessageKind::Match)
+                .with_path_text("src/old_module.rs")
+                .with_lines_text("src/old_module.rs")
+                .with_offset(0)
+                .with_submatches(vec![SubMatch::new_text("old", 4..7)])
+                .build()
+        );
+        match &rg_messages[3] {
+            RgMessage::Summary { stats, .. } => {
+                assert_eq!(stats.searches, 2);
+                assert_eq!(stats.matches, 1);
+            }
+            other => panic!("expected a summary, got: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn it_renames_files() {
+        let dir = tempdir().unwrap();
+        let old = dir.path().join("old_module.rs");
+        fs::write(&old, "contents").unwrap();
+
+        let criteria = criteria("new/module", "old_module", &[&old]);
+        let renames = rename_files(&criteria, false);
+        assert_eq!(renames.len(), 1);
+        assert!(renames[0].error.is_none());
+
+        // Any missing directories are created.
+        let new = dir.path().join("new").join("module.rs");
+        assert_eq!(renames[0].new_path, new.display().to_string());
+        assert_eq!(fs::read_to_string(new).unwrap(), "contents");
+        assert!(!old.exists());
+    }
+
+    #[test]
+    fn it_expands_capture_groups_in_paths() {
+        let dir = tempdir().unwrap();
+        let old = dir.path().join("test_foo.rs");
+        fs::write(&old, "").unwrap();
+
+        let criteria = criteria("${1}_test", r"test_(\w+)", &[&old]);
+        let renames = rename_files(&criteria, false);
+        assert!(renames[0].error.is_none());
+        assert!(dir.path().join("foo_test.rs").exists());
+    }
+
+    #[test]
+    fn it_refuses_to_overwrite_files() {
+        let dir = tempdir().unwrap();
+        let a = dir.path().join("a_old.rs");
+        let b = dir.path().join("b_old.rs");
+        let existing = dir.path().join("a_new.rs");
+        for path in [&a, &b, &existing] {
+            fs::write(path, path.display().to_string()).unwrap();
+        }
+
+        // `a` would overwrite an existing file.
+        let criteria = self::criteria("_new", "_old", &[&a, &b]);
+        let renames = rename_files(&criteria, true);
+        assert!(renames[0].error.is_some());
+        assert!(renames[1].error.is_none());
+
+        // Both files would be renamed to the same path, so only the first one is.
+        let criteria = self::criteria("x", r"[ab]_old", &[&a, &b]);
+        let renames = rename_files(&criteria, false);
+        assert!(renames[0].error.is_none());
+        assert_eq!(
+            renames[1].error.as_ref().unwrap().to_string(),
+            format!(
+                "Refusing to overwrite {}, since another file is also renamed to it",
+                dir.path().join("x.rs").display()
+            )
+        );
+        assert_eq!(fs::read_to_string(&b).unwrap(), b.display().to_string());
+        assert_eq!(
+            fs::read_to_string(&existing).unwrap(),
+            existing.display().to_string()
+        );
+    }
+
+    #[test]
+    fn it_does_not_overwrite_files_created_after_checking() {
+        let dir = tempdir().unwrap();
+        let old = dir.path().join("old.rs");
+        let new = dir.path().join("new.rs");
+        fs::write(&old, "old").unwrap();
+
+        let criteria = criteria("new", "old", &[&old]);
+        let renames = for_each_rename(&criteria, |path_data, new_path_data| {
+            // The file appears after the rename was checked, but before it's renamed.
+            fs::write(new_path_data.to_path_buf()?, "new").unwrap();
+            rename_file(path_data, new_path_data)
+        });
+        assert!(renames[0]
+            .error
+            .as_ref()
+            .unwrap()
+            .to_string()
+            .ends_with("since it already exists"));
+        assert_eq!(fs::read_to_string(&old).unwrap(), "old");
+        assert_eq!(fs::read_to_string(&new).unwrap(), "new");
+    }
+
+    #[test]
+    fn it_only_renames_selected_matches() {
+        let dir = tempdir().unwrap();
+        let old = dir.path().join("a_old_old.rs");
+        fs::write(&old, "").unwrap();
+
+        let mut criteria = criteria("_new", "_old", &[&old]);
+        let item = criteria
+            .items
+            .iter_mut()
+            .find(|item| item.kind == RgMessageKind::Match)
+            .unwrap();
+        item.set_should_replace(0, false);
+
+        let renames = rename_files(&criteria, false);
+        assert!(renames[0].error.is_none());
+        assert!(dir.path().join("a_old_new.rs").exists());
+    }
+
+    #[test]
+    fn it_writes_a_diff_of_the_renames() {
+        let dir = tempdir().unwrap();
+        let old = dir.path().join("a_old.rs");
+        fs::write(&old, "").unwrap();
+
+        let mut out = vec![];
+        let renames = diff_renames(&criteria("_new", "_old", &[&old]), &mut out);
+        assert!(renames[0].error.is_none());
+        assert!(old.exists());
+
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.ends_with(&format!(
+            "rename to {}\n",
+            dir.path().join("a_new.rs").display()
+        )));
+    }
+
+    #[test]
+    fn it_formats_the_total() {
+        assert_eq!(format_total(&[], false), "0 file(s) renamed");
+        assert_eq!(
+            format_total(&[], true),
+            "0 file(s) to rename (dry run, no files were renamed)"
+        );
+    }
+}
@@ -1,58 +1,208 @@
-use std::fs::OpenOptions;
-use std::io::{Read, Write};
+use std::fmt::{self, Display};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
 
 use anyhow::{anyhow, Context, Result};
-use encoding::{DecoderTrap, EncoderTrap};
-use tempfile::NamedTempFile;
+use encoding::{DecoderTrap, EncoderTrap, EncodingRef};
+use tempfile::{NamedTempFile, TempPath};
 
+use crate::diff::write_unified_diff;
 use crate::encoding::{get_encoder, Bom};
-use crate::model::ReplacementCriteria;
+use crate::journal::Journal;
+use crate::model::{Operation, ReplacementCriteria};
 use crate::rg::de::{ArbitraryData, SubMatch};
 use crate::rg::RgEncoding;
 use crate::ui::line::Item;
 
+/// What happened when making replacements in a single file.
+#[derive(Debug)]
+pub struct FileReplacement {
+    /// The path of the file.
+    pub path: String,
+    /// How many matches were replaced.
+    pub replaced: usize,
+    /// How many matches were skipped, because the bytes in the file didn't match those reported by `rg`.
+    pub skipped: usize,
+    /// If set, an error occurred and the file was not written.
+    pub error: Option<anyhow::Error>,
+    /// How the replacements were made in the file, if it could be read.
+    pub strategy: Option<ReplaceStrategy>,
+    /// How the file was written, if it was.
+    pub written: Option<FileWrite>,
+}
+
+/// How the replacements were made in a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplaceStrategy {
+    /// The file was decoded with this encoding, and encoded again after the replacements were made.
+    Decoded(&'static str),
+    /// The file isn't valid in this encoding, so the replacements were encoded with it and spliced into the file's
+    /// bytes, leaving the rest of them as they were.
+    Bytes(&'static str),
+}
+
+impl Display for ReplaceStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplaceStrategy::Decoded(encoding) => write!(f, "decoded as {}", encoding),
+            ReplaceStrategy::Bytes(encoding) => write!(f, "spliced bytes, not valid {}", encoding),
+        }
+    }
+}
+
+/// How a file was written (see `write_file`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileWrite {
+    pub strategy: WriteStrategy,
+    /// If the path was a symlink, then the file it points to (which was written instead).
+    pub symlink_target: Option<PathBuf>,
+}
+
+impl FileWrite {
+    /// Whether the file was written any differently than by replacing it with a temporary file.
+    pub fn is_notable(&self) -> bool {
+        self.strategy != WriteStrategy::TempFile || self.symlink_target.is_some()
+    }
+}
+
+impl Display for FileWrite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.strategy)?;
+        if let Some(target) = &self.symlink_target {
+            write!(f, ", through symlink to {}", target.display())?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteStrategy {
+    /// A temporary file was written and then moved over the file.
+    TempFile,
+    /// The file has this many hard links, so it was overwritten in place so that every link sees the change.
+    InPlace(u64),
+}
+
+impl Display for WriteStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WriteStrategy::TempFile => write!(f, "via temporary file"),
+            WriteStrategy::InPlace(links) => write!(f, "in place, {} hard links", links),
+        }
+    }
+}
+
+impl FileReplacement {
+    fn new(path: String) -> FileReplacement {
+        FileReplacement {
+            path,
+            replaced: 0,
+            skipped: 0,
+            error: None,
+            strategy: None,
+            written: None,
+        }
+    }
+
+    /// Whether every selected match in the file was replaced.
+    pub fn is_complete(&self) -> bool {
+        self.skipped == 0 && self.error.is_none()
+    }
+}
+
+/// The contents of a file, which the replacements are made in.
+enum Contents {
+    /// The file was decoded into a string (without any BOM).
+    Decoded(String),
+    /// The file couldn't be decoded, so these are its bytes, and the replacements are encoded with the encoder.
+    Bytes(Vec<u8>, EncodingRef),
+}
+
+impl Contents {
+    /// Returns the bytes at `range`, or `None` if it's out of bounds (or not on a char boundary).
+    fn get(&self, range: Range<usize>) -> Option<&[u8]> {
+        match self {
+            Contents::Decoded(s) => s.get(range).map(str::as_bytes),
+            Contents::Bytes(bytes, _) => bytes.get(range),
+        }
+    }
+
+    /// Replaces the bytes at `range` (which must be valid, see `get`) with `text`.
+    fn splice(&mut self, range: Range<usize>, text: &str) -> Result<()> {
+        match self {
+            Contents::Decoded(s) => s.replace_range(range, text),
+            Contents::Bytes(bytes, encoder) => {
+                let encoded = encoder
+                    .encode(text, EncoderTrap::Strict)
+                    .map_err(|e| anyhow!("Failed to encode replacement: {}", e))?;
+                bytes.splice(range, encoded);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Decodes the file's contents (without its BOM), or returns its bytes if it can't be decoded and ripgrep searched its
+/// bytes as they are, since then the offsets of the matches are still correct.
+fn decode(
+    original_contents: &[u8],
+    bom: Option<Bom>,
+    encoder: EncodingRef,
+    rg_encoding: &RgEncoding,
+) -> Result<Contents> {
+    // Strip the BOM before we decode.
+    let file_contents = match bom {
+        // NOTE: we don't strip a UTF8 BOM, because ripgrep doesn't either
+        // See: https://github.com/BurntSushi/ripgrep/issues/1638
+        None | Some(Bom::Utf8) => original_contents,
+        Some(bom) => &original_contents[bom.len()..],
+    };
+
+    log::trace!("Decoding file");
+    match encoder.decode(file_contents, DecoderTrap::Strict) {
+        Ok(decoded) => Ok(Contents::Decoded(decoded)),
+        // NOTE: ripgrep only transcodes files with a UTF-16 BOM, or when an encoding is passed to it.
+        Err(e)
+            if matches!(rg_encoding, RgEncoding::None) && matches!(bom, None | Some(Bom::Utf8)) =>
+        {
+            log::debug!("Failed to decode file ({}), replacing its bytes instead", e);
+            Ok(Contents::Bytes(original_contents.to_vec(), encoder))
+        }
+        Err(e) => Err(anyhow!("Failed to decode file: {}", e)),
+    }
+}
+
+/// Reads the file and makes the replacements in it, returning the original contents of the file and its contents
+/// after the replacements were made.
 fn perform_replacements_in_file(
     criteria: &ReplacementCriteria,
     rg_encoding: &RgEncoding,
     (path_data, mut items): (&ArbitraryData, Vec<&Item>),
-) -> Result<bool> {
+    summary: &mut FileReplacement,
+) -> Result<(Vec<u8>, Vec<u8>)> {
     log::debug!("File: {} (item count: {})", path_data, items.len());
     let path_buf = path_data.to_path_buf()?;
 
-    // Check the file for a BOM, detect its encoding and then decode it into a string.
-    let (bom, encoder, mut file_as_str) = {
-        let mut file_contents = vec![];
-        OpenOptions::new()
-            .read(true)
-            .open(&path_buf)?
-            .read_to_end(&mut file_contents)?;
-
-        // Search for a BOM and attempt to detect file encoding.
-        let (bom, encoder) = get_encoder(&file_contents, rg_encoding);
-        log::debug!("BOM: {:?}", bom);
-        log::debug!("Encoder: {}", encoder.name());
-
-        // Strip the BOM before we decode.
-        match bom {
-            // NOTE: we don't strip a UTF8 BOM, because ripgrep doesn't either
-            // See: https://github.com/BurntSushi/ripgrep/issues/1638
-            None | Some(Bom::Utf8) => {}
-            Some(_) => {
-                file_contents = file_contents
-                    .iter()
-                    .skip(bom.unwrap().len())
-                    .copied()
-                    .collect();
-            }
-        }
-
-        log::trace!("Decoding file");
-        let decoded = encoder
-            .decode(&file_contents, DecoderTrap::Strict)
-            .map_err(|e| anyhow!("Failed to decode file: {}", e))?;
-
-        (bom, encoder, decoded)
-    };
+    let mut original_contents = vec![];
+    OpenOptions::new()
+        .read(true)
+        .open(&path_buf)?
+        .read_to_end(&mut original_contents)?;
+    criteria.snapshots.check(&path_buf, &original_contents)?;
+
+    // Check the file for a BOM, detect its encoding and then decode it.
+    let (bom, encoder) = get_encoder(&original_contents, rg_encoding);
+    log::debug!("BOM: {:?}", bom);
+    log::debug!("Encoder: {}", encoder.name());
+    let mut contents = decode(&original_contents, bom, encoder, rg_encoding)?;
+    summary.strategy = Some(match contents {
+        Contents::Decoded(_) => ReplaceStrategy::Decoded(encoder.name()),
+        Contents::Bytes(..) => ReplaceStrategy::Bytes(encoder.name()),
+    });
 
     // Sort the items so they're in order - ripgrep should give them to us in order anyway but we sort them here to
     // future-proof against any changes.
@@ -62,11 +212,15 @@ fn perform_replacements_in_file(
 
     // Iterate over the items in _reverse_ order -> this is so offsets can stay the same even though we're making
     // changes to the string.
-    let mut did_skip_replacement = false;
     for (i, item) in items.iter().rev().enumerate() {
         let offset = item.offset().unwrap();
         log::debug!("Item[{}] offset: {}", i, offset);
 
+        if criteria.operation.is_line_operation() {
+            perform_line_operation(criteria, &mut contents, item, &path_buf, summary)?;
+            continue;
+        }
+
         // Iterate backwards so the offset doesn't change as we make replacements.
         for (i, sub_item) in item
             .sub_items()
@@ -79,35 +233,317 @@ fn perform_replacements_in_file(
             log::debug!("SubMatch[{}] range: {:?}, data: \"{}\"", i, range, text);
 
             let normalised_range = (offset + range.start)..(offset + range.end);
-            let str_to_remove = &file_as_str[normalised_range.clone()];
+            let bytes_to_remove = contents.get(normalised_range.clone());
             let matched_bytes = text.to_vec();
 
-            if str_to_remove.as_bytes() == matched_bytes.as_slice() {
-                let removed_str = str_to_remove.to_string();
-                file_as_str.replace_range(normalised_range, &criteria.text);
+            if bytes_to_remove == Some(matched_bytes.as_slice()) {
+                let replacement = item.replacement_text(
+                    sub_item,
+                    &criteria.matcher,
+                    &criteria.text,
+                    &criteria.pattern_texts,
+                    criteria.preserve_case,
+                );
+                contents.splice(normalised_range, &replacement)?;
 
                 log::debug!(
                     "Replacement - reported line: {:?}, removed: \"{}\", added: \"{}\"",
                     item.line_number(),
-                    removed_str,
-                    criteria.text
+                    text,
+                    replacement
                 );
+                summary.replaced += 1;
             } else {
                 log::warn!("Matched bytes do not match bytes to replace!");
                 log::warn!("\tFile: \"{}\"", path_buf.display());
                 log::warn!("\tMatch: data=\"{}\", bytes={:?}", text, matched_bytes);
                 log::warn!("\tOffset: {}", offset + range.start);
-                did_skip_replacement = true;
+                summary.skipped += 1;
             }
         }
     }
 
+    let file_as_str = match contents {
+        // The bytes which weren't replaced are left as they were, including any BOM.
+        Contents::Bytes(bytes, _) => return Ok((original_contents, bytes)),
+        Contents::Decoded(file_as_str) => file_as_str,
+    };
+
     // Convert back into the detected encoding.
     log::trace!("Re-encoding file");
     let replaced_contents = encoder
         .encode(&file_as_str, EncoderTrap::Strict)
         .map_err(|e| anyhow!("Failed to encode replaced string: {}", e))?;
 
+    // Write a BOM if one existed beforehand.
+    let mut contents = vec![];
+    if let Some(bom) = bom {
+        // NOTE: we don't strip a UTF8 BOM, because ripgrep doesn't either therefore no need to re-write one
+        // See: https://github.com/BurntSushi/ripgrep/issues/1638
+        if !matches!(bom, Bom::Utf8) {
+            let bom_bytes = bom.bytes();
+            log::debug!("Writing BOM: {:?}", bom_bytes);
+            contents.extend_from_slice(bom_bytes);
+        }
+    }
+    contents.extend(replaced_contents);
+
+    Ok((original_contents, contents))
+}
+
+/// Deletes the lines containing the item's matches, or inserts a line above or below them (depending on the
+/// operation in `criteria`) if any of its matches are selected. The inserted line is the replacement text of the
+/// first selected match, so capture groups and case preservation work the same as when substituting.
+fn perform_line_operation(
+    criteria: &ReplacementCriteria,
+    contents: &mut Contents,
+    item: &Item,
+    path_buf: &Path,
+    summary: &mut FileReplacement,
+) -> Result<()> {
+    let selected = item
+        .sub_items()
+        .iter()
+        .filter(|s| s.should_replace)
+        .collect::<Vec<_>>();
+    let first_selected = match selected.first() {
+        Some(sub_item) => sub_item,
+        None => return Ok(()),
+    };
+
+    let offset = item.offset().unwrap();
+    let lines = item.lines().map(ArbitraryData::to_vec).unwrap_or_default();
+    let range = offset..(offset + lines.len());
+    if contents.get(range.clone()) != Some(lines.as_slice()) {
+        log::warn!("Matched lines do not match lines to change!");
+        log::warn!("\tFile: \"{}\"", path_buf.display());
+        log::warn!("\tLines: {:?}", item.lines());
+        log::warn!("\tOffset: {}", offset);
+        summary.skipped += selected.len();
+        return Ok(());
+    }
+
+    let eol = if lines.ends_with(b"\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    };
+    let text = item.replacement_text(
+        first_selected,
+        &criteria.matcher,
+        &criteria.text,
+        &criteria.pattern_texts,
+        criteria.preserve_case,
+    );
+    match criteria.operation {
+        Operation::Substitute => unreachable!("substitutions are not line operations"),
+        Operation::DeleteLine => contents.splice(range, "")?,
+        Operation::InsertAbove => {
+            contents.splice(range.start..range.start, &format!("{}{}", text, eol))?
+        }
+        // The last line of the file may not end with a newline.
+        Operation::InsertBelow if lines.ends_with(b"\n") => {
+            contents.splice(range.end..range.end, &format!("{}{}", text, eol))?
+        }
+        Operation::InsertBelow => {
+            contents.splice(range.end..range.end, &format!("{}{}", eol, text))?
+        }
+    }
+
+    log::debug!(
+        "{} - reported line: {:?}, text: \"{}\"",
+        criteria.operation,
+        item.line_number(),
+        text
+    );
+    summary.replaced += selected.len();
+
+    Ok(())
+}
+
+/// Copies the permissions, ownership (where possible) and extended attributes of the file at `path_buf` onto `file`,
+/// so that they're kept when `file` replaces it.
+fn copy_metadata(path_buf: &Path, file: &File) -> Result<()> {
+    let metadata = match fs::metadata(path_buf) {
+        Ok(metadata) => metadata,
+        // There's nothing to keep if the file doesn't exist.
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+
+    #[cfg(unix)]
+    {
+        copy_xattrs(path_buf, file);
+        copy_ownership(&metadata, file);
+    }
+
+    // NOTE: this is done last, since changing the owner may clear the setuid and setgid bits, and a read-only file
+    // may not allow its extended attributes to be set.
+    log::debug!("Permissions: {:?}", metadata.permissions());
+    file.set_permissions(metadata.permissions())?;
+
+    Ok(())
+}
+
+#[cfg(unix)]
+fn copy_ownership(metadata: &fs::Metadata, file: &File) {
+    use std::os::unix::fs::{fchown, MetadataExt};
+
+    // Only root can change the owner of a file, but anyone can change its group to one which they're in.
+    if let Err(e) = fchown(file, Some(metadata.uid()), Some(metadata.gid())) {
+        log::debug!("Failed to set the owner to {}: {}", metadata.uid(), e);
+        if let Err(e) = fchown(file, None, Some(metadata.gid())) {
+            log::warn!("Failed to set the group to {}: {}", metadata.gid(), e);
+        }
+    }
+}
+
+#[cfg(unix)]
+fn copy_xattrs(path_buf: &Path, file: &File) {
+    use xattr::FileExt;
+
+    let names = match xattr::list(path_buf) {
+        Ok(names) => names,
+        Err(e) => {
+            log::debug!("Failed to list extended attributes: {}", e);
+            return;
+        }
+    };
+
+    for name in names {
+        let result = xattr::get(path_buf, &name)
+            .and_then(|value| file.set_xattr(&name, &value.unwrap_or_default()));
+        if let Err(e) = result {
+            log::warn!("Failed to copy extended attribute {:?}: {}", name, e);
+        }
+    }
+}
+
+/// Overwrites the file at `path_buf` with `contents`, returning how it was written.
+///
+/// If `path_buf` is a symlink then the file it points to is written instead, so the symlink is kept. A file with other
+/// hard links is overwritten in place so that every link sees the change, otherwise it's atomically replaced with a
+/// temporary file.
+pub fn write_file(path_buf: &Path, contents: &[u8]) -> Result<FileWrite> {
+    stage_write(path_buf, contents)?.persist()
+}
+
+/// A write of a file which has been prepared but not yet made, see `stage_write`.
+struct StagedWrite<'a> {
+    /// The file to write (which is the target if the path was a symlink).
+    target: PathBuf,
+    write: FileWrite,
+    staged: Staged<'a>,
+}
+
+enum Staged<'a> {
+    /// A temporary file with the new contents, which replaces the file.
+    TempFile(TempPath),
+    /// The new contents, which overwrite the file.
+    InPlace(&'a [u8]),
+}
+
+impl StagedWrite<'_> {
+    /// Writes the file, returning how it was written.
+    fn persist(self) -> Result<FileWrite> {
+        match self.staged {
+            Staged::TempFile(temp_path) => {
+                // Overwrite the original file with the patched temp file.
+                log::debug!(
+                    "Moving {} to {}",
+                    temp_path.display(),
+                    self.target.display()
+                );
+                temp_path.persist(&self.target)?;
+            }
+            Staged::InPlace(contents) => write_in_place(&self.target, contents)?,
+        }
+
+        Ok(self.write)
+    }
+}
+
+/// Prepares to overwrite the file at `path_buf` with `contents` (see `write_file`), writing the temporary file if one
+/// is needed. If the returned write is dropped rather than persisted, the file is left as it is.
+fn stage_write<'a>(path_buf: &Path, contents: &'a [u8]) -> Result<StagedWrite<'a>> {
+    let symlink_target = resolve_symlink(path_buf)?;
+    let target = symlink_target
+        .clone()
+        .unwrap_or_else(|| path_buf.to_path_buf());
+
+    let (strategy, staged) = match hard_link_count(&target) {
+        links if links > 1 => (WriteStrategy::InPlace(links), Staged::InPlace(contents)),
+        _ => (
+            WriteStrategy::TempFile,
+            Staged::TempFile(write_temp_file(&target, contents)?),
+        ),
+    };
+
+    Ok(StagedWrite {
+        target,
+        write: FileWrite {
+            strategy,
+            symlink_target,
+        },
+        staged,
+    })
+}
+
+/// If `path_buf` is a symlink, returns the file it (eventually) points to. Fails if that isn't a regular file, since
+/// writing through the symlink would then do something other than change a file.
+fn resolve_symlink(path_buf: &Path) -> Result<Option<PathBuf>> {
+    match fs::symlink_metadata(path_buf) {
+        Ok(metadata) if metadata.file_type().is_symlink() => {}
+        _ => return Ok(None),
+    }
+
+    let target = fs::canonicalize(path_buf)
+        .with_context(|| format!("Failed to resolve symlink: {}", path_buf.display()))?;
+    if !fs::metadata(&target)?.is_file() {
+        return Err(anyhow!(
+            "Refusing to write through symlink {} to {}, which isn't a regular file",
+            path_buf.display(),
+            target.display()
+        ));
+    }
+
+    log::debug!(
+        "Writing through symlink {} to {}",
+        path_buf.display(),
+        target.display()
+    );
+    Ok(Some(target))
+}
+
+#[cfg(unix)]
+fn hard_link_count(path_buf: &Path) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+
+    fs::metadata(path_buf).map_or(1, |metadata| metadata.nlink())
+}
+
+// NOTE: the number of links isn't available on other platforms without unstable features, so files are always
+// replaced there.
+#[cfg(not(unix))]
+fn hard_link_count(_: &Path) -> u64 {
+    1
+}
+
+/// Overwrites the file at `path_buf` with `contents`, keeping the same file (and so its links and metadata).
+fn write_in_place(path_buf: &Path, contents: &[u8]) -> Result<()> {
+    log::debug!("Writing in place: {}", path_buf.display());
+    let mut file = OpenOptions::new()
+        .write(true)
+        .truncate(true)
+        .open(path_buf)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+
+    Ok(())
+}
+
+/// Writes `contents` to a temporary file next to the file at `path_buf`, which can then atomically replace it.
+fn write_temp_file(path_buf: &Path, contents: &[u8]) -> Result<TempPath> {
     // Create a temporary file.
     let parent_dir = path_buf.parent().with_context(|| {
         anyhow!(
@@ -119,53 +555,272 @@ fn perform_replacements_in_file(
     let temp_file_path = temp_file.path().display().to_string();
     log::debug!("Creating temporary file: {}", temp_file_path);
 
-    // Write a BOM if one existed beforehand.
-    if let Some(bom) = bom {
-        // NOTE: we don't strip a UTF8 BOM, because ripgrep doesn't either therefore no need to re-write one
-        // See: https://github.com/BurntSushi/ripgrep/issues/1638
-        if !matches!(bom, Bom::Utf8) {
-            let bom_bytes = bom.bytes();
-            log::debug!("Writing BOM: {:?}", bom_bytes);
-            temp_file.write_all(bom_bytes)?;
-        }
-    }
-
     // Write the replaced contents.
     log::debug!("Writing: {}", temp_file_path);
-    temp_file.write_all(&replaced_contents)?;
+    temp_file.write_all(contents)?;
+
+    // Keep the permissions (etc) of the original file, since otherwise the temporary file's are used.
+    copy_metadata(path_buf, temp_file.as_file())?;
 
-    // Overwrite the original file with the patched temp file.
-    log::debug!("Moving {} to {}", temp_file_path, path_buf.display());
-    temp_file.into_temp_path().persist(&path_buf)?;
+    Ok(temp_file.into_temp_path())
+}
 
-    Ok(did_skip_replacement)
+/// Groups the items to replace by their file (so we only open each file once), sorted by path.
+fn files_by_path(criteria: &ReplacementCriteria) -> Vec<(&ArbitraryData, Vec<&Item>)> {
+    let mut files = criteria.as_map().into_iter().collect::<Vec<_>>();
+    files.sort_by_cached_key(|(path_data, _)| path_data.to_string());
+    files
 }
 
-pub fn perform_replacements(criteria: ReplacementCriteria) -> Result<()> {
+/// Makes the replacements described by `criteria` in each file (in order of their paths), and passes the original
+/// and replaced contents of each file to `output`, which returns how the file was written (if it was). Returns what
+/// happened in each file.
+fn for_each_file<F>(criteria: &ReplacementCriteria, mut output: F) -> Vec<FileReplacement>
+where
+    F: FnMut(&ArbitraryData, &[u8], &[u8]) -> Result<Option<FileWrite>>,
+{
     log::trace!("--- PERFORM REPLACEMENTS ---");
     log::debug!("Replacement text: \"{}\"", criteria.text);
 
     let rg_encoding = RgEncoding::from(&criteria.encoding);
     log::debug!("User passed encoding: {:?}", rg_encoding);
 
-    // Group items by their file so we only open each file once.
-    let mut did_skip_replacement = false;
-    for meta in criteria.as_map() {
-        match perform_replacements_in_file(&criteria, &rg_encoding, meta) {
-            Ok(did_skip) => {
-                if did_skip {
-                    did_skip_replacement = true
+    files_by_path(criteria)
+        .into_iter()
+        .map(|meta| {
+            let path_data = meta.0;
+            let mut summary = FileReplacement::new(path_data.to_string());
+            let result = perform_replacements_in_file(criteria, &rg_encoding, meta, &mut summary)
+                .and_then(|(original, replaced)| output(path_data, &original, &replaced));
+
+            match result {
+                Ok(written) => summary.written = written,
+                Err(e) => {
+                    log::warn!("Failed to make all replacements: {}", e);
+                    summary.error = Some(e);
                 }
             }
+
+            summary
+        })
+        .collect()
+}
+
+/// Makes the replacements described by `criteria`, returning what happened in each file (sorted by path).
+/// If `dry_run` is set, then the replacements are checked but no files are written.
+/// If a `journal` is given, then the original contents of each file are recorded in it before the file is written.
+/// If `criteria` is set to write all files or none, then see `replace_files_transactionally`.
+pub fn replace_files(
+    criteria: &ReplacementCriteria,
+    dry_run: bool,
+    mut journal: Option<&mut Journal>,
+) -> Vec<FileReplacement> {
+    if criteria.all_or_nothing && !dry_run {
+        return replace_files_transactionally(criteria, journal);
+    }
+
+    for_each_file(criteria, |path_data, original, replaced| {
+        if dry_run {
+            log::debug!("Dry run, not writing: {}", path_data);
+            return Ok(None);
+        }
+
+        let path_buf = path_data.to_path_buf()?;
+        if let Some(journal) = journal.as_deref_mut() {
+            journal.record(&path_buf, original, replaced)?;
+        }
+
+        write_file(&path_buf, replaced).map(Some)
+    })
+}
+
+/// Makes the replacements described by `criteria` in either every file or none of them, returning what happened in
+/// each file (sorted by path). This is done in three steps:
+///
+/// 1. Each file is read and patched, so that if any file can't be decoded (or any match is skipped) nothing is written
+/// 2. The patched contents of each file are staged (in a temporary file next to it, where possible)
+/// 3. The staged files are written, and if any of them fails then the files already written are restored
+///
+/// If a `journal` is given, then the original contents of each file are recorded in it before any file is written,
+/// and discarded again if the files are restored.
+fn replace_files_transactionally(
+    criteria: &ReplacementCriteria,
+    mut journal: Option<&mut Journal>,
+) -> Vec<FileReplacement> {
+    log::trace!("--- PERFORM REPLACEMENTS (ALL OR NOTHING) ---");
+    let rg_encoding = RgEncoding::from(&criteria.encoding);
+
+    let mut summaries = vec![];
+    let mut patched: Vec<Patched> = vec![];
+    for meta in files_by_path(criteria) {
+        let path_data = meta.0;
+        let mut summary = FileReplacement::new(path_data.to_string());
+        let result = perform_replacements_in_file(criteria, &rg_encoding, meta, &mut summary)
+            .and_then(|(original, replaced)| Ok((path_data.to_path_buf()?, original, replaced)));
+        match result {
+            Ok(contents) => patched.push(contents),
+            Err(e) => summary.error = Some(e),
+        }
+        summaries.push(summary);
+    }
+    if !summaries.iter().all(FileReplacement::is_complete) {
+        log::warn!("Not every match can be replaced, so no files will be written");
+        return abandon(summaries, 0);
+    }
+
+    let mut staged = vec![];
+    for (summary, (path_buf, _, replaced)) in summaries.iter_mut().zip(&patched) {
+        match stage_write(path_buf, replaced) {
+            Ok(write) => staged.push(write),
             Err(e) => {
-                did_skip_replacement = true;
-                log::warn!("Failed to make all replacements: {}", e);
-                eprintln!("Failed to make all replacements: {}", e);
-                continue;
+                log::warn!("Failed to stage {}: {}", path_buf.display(), e);
+                summary.error = Some(e);
+                return abandon(summaries, 0);
             }
         }
     }
 
+    if let Some(journal) = journal.as_deref_mut() {
+        for (path_buf, original, replaced) in &patched {
+            if let Err(e) = journal.record(path_buf, original, replaced) {
+                log::warn!("Failed to record {}: {}", path_buf.display(), e);
+                journal.discard();
+                return abandon(summaries, 0);
+            }
+        }
+    }
+
+    persist_all(summaries, &patched, staged, journal)
+}
+
+/// The path, original contents and replaced contents of a file.
+type Patched = (PathBuf, Vec<u8>, Vec<u8>);
+
+/// Writes each staged file, and if any fails to be written then restores the ones which were already written (and
+/// discards them from the `journal`, so that the previous run can still be undone).
+fn persist_all(
+    mut summaries: Vec<FileReplacement>,
+    patched: &[Patched],
+    staged: Vec<StagedWrite>,
+    journal: Option<&mut Journal>,
+) -> Vec<FileReplacement> {
+    for (i, write) in staged.into_iter().enumerate() {
+        // NOTE: unlike replacing a file, writing it in place can fail part-way, so then it's restored too.
+        let in_place = matches!(write.staged, Staged::InPlace(_));
+        match write.persist() {
+            Ok(written) => summaries[i].written = Some(written),
+            Err(e) => {
+                log::warn!("Failed to write {}: {}", patched[i].0.display(), e);
+                summaries[i].error = Some(e);
+                let restore = if in_place { i + 1 } else { i };
+                for (summary, (path_buf, original, _)) in
+                    summaries.iter_mut().zip(patched).take(restore)
+                {
+                    restore_file(summary, path_buf, original);
+                }
+                if let Some(journal) = journal {
+                    journal.discard();
+                }
+
+                return abandon(summaries, i);
+            }
+        }
+    }
+
+    summaries
+}
+
+/// Writes the `original` contents back to the file at `path_buf`, after another file failed to be written.
+fn restore_file(summary: &mut FileReplacement, path_buf: &Path, original: &[u8]) {
+    log::debug!("Restoring: {}", path_buf.display());
+    let reason = match summary.error.take() {
+        Some(e) => e.to_string(),
+        None => String::from("another file failed to be written"),
+    };
+    summary.error = Some(match write_file(path_buf, original) {
+        Ok(_) => {
+            summary.replaced = 0;
+            anyhow!("{} (restored the original contents)", reason)
+        }
+        Err(e) => anyhow!(
+            "{} (failed to restore the original contents: {})",
+            reason,
+            e
+        ),
+    });
+    summary.written = None;
+}
+
+/// Marks each file (from `start`) which didn't already fail as not written, since not every file can be written.
+fn abandon(mut summaries: Vec<FileReplacement>, start: usize) -> Vec<FileReplacement> {
+    for summary in summaries.iter_mut().skip(start) {
+        if summary.error.is_none() {
+            summary.error = Some(anyhow!("Not written, since not every file can be written"));
+        }
+        summary.replaced = 0;
+    }
+
+    summaries
+}
+
+/// Makes the replacements described by `criteria`, but rather than writing the files a unified diff of the changes
+/// to each file is written to `out`. Returns what happened in each file (sorted by path).
+pub fn diff_files(criteria: &ReplacementCriteria, out: &mut dyn Write) -> Vec<FileReplacement> {
+    for_each_file(criteria, |path_data, original, replaced| {
+        write_unified_diff(out, &path_data.to_vec(), original, replaced)?;
+        Ok(None)
+    })
+}
+
+/// Opens the file at `path` for writing a diff to, or STDOUT if no path (or "-") was given.
+pub fn diff_output(path: Option<&Path>) -> Result<Box<dyn Write>> {
+    match path {
+        Some(path) if path != Path::new("-") => {
+            let file = File::create(path)
+                .with_context(|| format!("Failed to create diff file: {}", path.display()))?;
+            Ok(Box::new(file))
+        }
+        _ => Ok(Box::new(io::stdout())),
+    }
+}
+
+/// Writes the replacements to each file, recording the original contents in `journal` (if given).
+pub fn perform_replacements(
+    criteria: ReplacementCriteria,
+    journal: Option<&mut Journal>,
+) -> Result<()> {
+    report_replacements(replace_files(&criteria, false, journal))
+}
+
+/// Writes a unified diff of the replacements to the file at `diff_path` (or STDOUT), without changing any files.
+pub fn perform_diff(criteria: ReplacementCriteria, diff_path: Option<&Path>) -> Result<()> {
+    let mut out = diff_output(diff_path)?;
+    let summaries = diff_files(&criteria, &mut out);
+    out.flush()?;
+
+    report_replacements(summaries)
+}
+
+/// Prints any errors (and any files which had their bytes spliced, or weren't written via a temporary file), and
+/// returns an error if not all replacements were made.
+fn report_replacements(summaries: Vec<FileReplacement>) -> Result<()> {
+    let mut did_skip_replacement = false;
+    for summary in summaries {
+        if let Some(e) = &summary.error {
+            eprintln!("Failed to make all replacements: {}", e);
+        }
+        if let Some(strategy @ ReplaceStrategy::Bytes(_)) = summary.strategy {
+            eprintln!("{}: {}", summary.path, strategy);
+        }
+        if let Some(written) = summary.written.as_ref().filter(|w| w.is_notable()) {
+            eprintln!("{}: written {}", summary.path, written);
+        }
+
+        if !summary.is_complete() {
+            did_skip_replacement = true;
+        }
+    }
+
     if did_skip_replacement {
         log::warn!("Failed to perform all replacements");
         Err(anyhow!("Failed to perform all replacements, see log"))
@@ -178,16 +833,22 @@ pub fn perform_replacements(criteria: ReplacementCriteria) -> Result<()> {
 mod tests {
     use std::fs::{self, OpenOptions};
     use std::io::{Read, Write};
-    use std::path::PathBuf;
+    use std::path::{Path, PathBuf};
 
     use base64_simd::STANDARD as base64;
     use pretty_assertions::assert_eq;
-    use tempfile::NamedTempFile;
+    use tempfile::{tempdir, NamedTempFile};
 
+    use crate::batch::select_all;
+    use crate::journal::{undo, Journal};
     use crate::model::*;
-    use crate::replace::perform_replacements;
+    use crate::replace::{
+        diff_files, perform_replacements, persist_all, replace_files, stage_write, write_file,
+        FileReplacement, FileWrite, ReplaceStrategy, Staged, StagedWrite, WriteStrategy,
+    };
     use crate::rg::de::test_utilities::RgMessageBuilder;
     use crate::rg::de::{Duration, RgMessageKind, Stats, SubMatch};
+    use crate::rg::read::read_messages;
     use crate::ui::line::*;
 
     macro_rules! temp_item {
@@ -253,7 +914,7 @@ mod tests {
             build_item(RgMessageKind::Summary, &p5),
         ];
 
-        perform_replacements(ReplacementCriteria::new("NEW_VALUE", items)).unwrap();
+        perform_replacements(ReplacementCriteria::new("NEW_VALUE", items), None).unwrap();
         assert_eq!(fs::read_to_string(p1).unwrap(), text);
         assert_eq!(fs::read_to_string(p2).unwrap(), text);
         assert_eq!(fs::read_to_string(p3).unwrap(), "NEW_VALUE bar baz");
@@ -268,7 +929,7 @@ mod tests {
         let (item3, p3) = temp_item!(0, "bar baz foo", vec![SubMatch::new_text("foo", 8..11)]);
 
         let items = vec![item1, item2, item3];
-        perform_replacements(ReplacementCriteria::new("NEW_VALUE", items)).unwrap();
+        perform_replacements(ReplacementCriteria::new("NEW_VALUE", items), None).unwrap();
         assert_eq!(fs::read_to_string(p1).unwrap(), "NEW_VALUE bar baz");
         assert_eq!(fs::read_to_string(p2).unwrap(), "baz NEW_VALUE bar");
         assert_eq!(fs::read_to_string(p3).unwrap(), "bar baz NEW_VALUE");
@@ -286,7 +947,7 @@ mod tests {
         items[1].set_should_replace(0, true);
         items[2].set_should_replace(0, false);
 
-        perform_replacements(ReplacementCriteria::new("NEW_VALUE", items)).unwrap();
+        perform_replacements(ReplacementCriteria::new("NEW_VALUE", items), None).unwrap();
         assert_eq!(fs::read_to_string(p1).unwrap(), "foo bar baz");
         assert_eq!(fs::read_to_string(p2).unwrap(), "baz NEW_VALUE bar");
         assert_eq!(fs::read_to_string(p3).unwrap(), "bar baz foo");
@@ -304,7 +965,7 @@ mod tests {
             ]
         );
 
-        perform_replacements(ReplacementCriteria::new("NEW_VALUE", vec![item])).unwrap();
+        perform_replacements(ReplacementCriteria::new("NEW_VALUE", vec![item]), None).unwrap();
         assert_eq!(
             fs::read_to_string(p).unwrap(),
             "NEW_VALUE NEW_VALUE NEW_VALUE"
@@ -346,7 +1007,7 @@ mod tests {
             ),
         ];
 
-        perform_replacements(ReplacementCriteria::new("NEW_VALUE", items)).unwrap();
+        perform_replacements(ReplacementCriteria::new("NEW_VALUE", items), None).unwrap();
         assert_eq!(
             fs::read_to_string(p).unwrap(),
             "NEW_VALUE bar baz\n...\nbaz NEW_VALUE bar\n...\nbar baz NEW_VALUE"
@@ -379,13 +1040,448 @@ mod tests {
             ),
         ];
 
-        perform_replacements(ReplacementCriteria::new("NEW_VALUE", items)).unwrap();
+        perform_replacements(ReplacementCriteria::new("NEW_VALUE", items), None).unwrap();
         assert_eq!(
             fs::read_to_string(p).unwrap(),
             "foo bar baz\n...\nbaz NEW_VALUE bar\n...\nbar NEW_VALUE foo"
         );
     }
 
+    #[test]
+    fn it_expands_capture_groups_in_replacements() {
+        let (item, p) = temp_item!(
+            0,
+            "foo(bar) foo(baz)",
+            vec![
+                SubMatch::new_text("foo(bar)", 0..8),
+                SubMatch::new_text("foo(baz)", 9..17),
+            ]
+        );
+
+        let mut criteria = ReplacementCriteria::new("qux($1, $$)", vec![item]);
+        criteria.set_matcher(Matcher::new(&[r"foo\((\w+)\)"], MatcherOptions::default()));
+        perform_replacements(criteria, None).unwrap();
+        assert_eq!(fs::read_to_string(p).unwrap(), "qux(bar, $) qux(baz, $)");
+    }
+
+    #[test]
+    fn it_summarises_replacements_without_writing_in_a_dry_run() {
+        let (item, p) = temp_item!(
+            0,
+            "foo bar foo",
+            vec![
+                SubMatch::new_text("foo", 0..3),
+                SubMatch::new_text("xxx", 4..7),
+                SubMatch::new_text("foo", 8..11),
+            ]
+        );
+
+        let criteria = ReplacementCriteria::new("baz", vec![item]);
+        let summaries = replace_files(&criteria, true, None);
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(summaries[0].path, p.to_string_lossy());
+        assert_eq!(summaries[0].replaced, 2);
+        assert_eq!(summaries[0].skipped, 1);
+        assert!(summaries[0].error.is_none());
+        assert!(!summaries[0].is_complete());
+        assert_eq!(fs::read_to_string(&p).unwrap(), "foo bar foo");
+
+        let summaries = replace_files(&criteria, false, None);
+        assert_eq!(summaries[0].replaced, 2);
+        assert_eq!(fs::read_to_string(&p).unwrap(), "baz bar baz");
+    }
+
+    #[test]
+    fn it_records_replaced_files_in_the_journal() {
+        let (item, p) = temp_item!(0, "foo bar", vec![SubMatch::new_text("bar", 4..7)]);
+        let state_dir = tempdir().unwrap();
+
+        let mut journal = Journal::new(state_dir.path()).unwrap();
+        perform_replacements(
+            ReplacementCriteria::new("baz", vec![item]),
+            Some(&mut journal),
+        )
+        .unwrap();
+        journal.save().unwrap();
+        assert_eq!(fs::read_to_string(&p).unwrap(), "foo baz");
+
+        undo(state_dir.path()).unwrap();
+        assert_eq!(fs::read_to_string(&p).unwrap(), "foo bar");
+    }
+
+    #[test]
+    fn it_does_not_write_files_changed_since_they_were_searched() {
+        let (item, p) = temp_item!(0, "foo bar", vec![SubMatch::new_text("bar", 4..7)]);
+
+        let mut criteria = ReplacementCriteria::new("baz", vec![item]);
+        criteria.set_snapshots(Snapshots::take(&[RgMessageBuilder::new(
+            RgMessageKind::Begin,
+        )
+        .with_path_text(p.to_string_lossy())
+        .build()]));
+        fs::write(&p, "foo bar!").unwrap();
+
+        let summaries = replace_files(&criteria, false, None);
+        assert!(summaries[0].error.is_some());
+        assert_eq!(fs::read_to_string(&p).unwrap(), "foo bar!");
+    }
+
+    #[test]
+    fn it_writes_a_diff_without_changing_files() {
+        let (item, p) = temp_item!(0, "foo bar foo\n", vec![SubMatch::new_text("bar", 4..7)]);
+
+        let mut diff = vec![];
+        let summaries = diff_files(&ReplacementCriteria::new("baz", vec![item]), &mut diff);
+        assert_eq!(summaries[0].replaced, 1);
+        assert_eq!(fs::read_to_string(&p).unwrap(), "foo bar foo\n");
+
+        let path = p.to_string_lossy();
+        assert_eq!(
+            String::from_utf8(diff).unwrap(),
+            format!(
+                "--- a/{}\n+++ b/{}\n@@ -1 +1 @@\n-foo bar foo\n+foo baz foo\n",
+                path, path
+            )
+        );
+    }
+
+    #[test]
+    fn it_writes_a_clean_diff_of_many_matches() {
+        let text = (0..1200)
+            .map(|i| format!("foo {}\n", i))
+            .collect::<String>();
+        let p = temp_file!(text);
+        let mut json = vec![];
+        let mut offset = 0;
+        for (i, line) in text.split_inclusive('\n').enumerate() {
+            let rg_message = RgMessageBuilder::new(RgMessageKind::Match)
+                .with_path_text(p.to_string_lossy())
+                .with_lines_text(line)
+                .with_line_number(i + 1)
+                .with_offset(offset)
+                .with_submatches(vec![SubMatch::new_text("foo", 0..3)])
+                .build();
+            serde_json::to_writer(&mut json, &rg_message).unwrap();
+            json.push(b'\n');
+            offset += line.len();
+        }
+
+        // The progress of reading the messages is kept apart from the diff, which may be written to STDOUT.
+        let mut progress = vec![];
+        let rg_messages = read_messages(json.as_slice(), Some(&mut progress)).unwrap();
+        assert_eq!(
+            String::from_utf8(progress).unwrap(),
+            "\rMatches found: ~1000\n"
+        );
+
+        let mut diff = vec![];
+        let summaries = diff_files(&select_all("bar", rg_messages), &mut diff);
+        assert_eq!(summaries[0].replaced, 1200);
+        let diff = String::from_utf8(diff).unwrap();
+        let path = p.to_string_lossy();
+        assert!(diff.starts_with(&format!("--- a/{}\n+++ b/{}\n@@ ", path, path)));
+        assert_eq!(
+            diff.lines()
+                .filter(|line| line.starts_with("+bar "))
+                .count(),
+            1200
+        );
+    }
+
+    #[test]
+    fn it_uses_per_match_replacements() {
+        let (mut item, p) = temp_item!(
+            0,
+            "foo foo foo",
+            vec![
+                SubMatch::new_text("foo", 0..3),
+                SubMatch::new_text("foo", 4..7),
+                SubMatch::new_text("foo", 8..11),
+            ]
+        );
+
+        item.set_replacement(1, Some(String::from("baz")));
+        perform_replacements(ReplacementCriteria::new("bar", vec![item]), None).unwrap();
+        assert_eq!(fs::read_to_string(p).unwrap(), "bar baz bar");
+    }
+
+    #[test]
+    fn it_preserves_case_in_replacements() {
+        let (item, p) = temp_item!(
+            0,
+            "fooBar FOO_BAR foo-bar",
+            vec![
+                SubMatch::new_text("fooBar", 0..6),
+                SubMatch::new_text("FOO_BAR", 7..14),
+                SubMatch::new_text("foo-bar", 15..22),
+            ]
+        );
+
+        let mut criteria = ReplacementCriteria::new("baz_qux", vec![item]);
+        criteria.set_preserve_case(true);
+        perform_replacements(criteria, None).unwrap();
+        assert_eq!(fs::read_to_string(p).unwrap(), "bazQux BAZ_QUX baz-qux");
+    }
+
+    fn line_items(path: &Path, lines: &[(usize, &str)]) -> Vec<Item> {
+        lines
+            .iter()
+            .enumerate()
+            .map(|(i, (offset, lines))| {
+                Item::new(
+                    i,
+                    RgMessageBuilder::new(RgMessageKind::Match)
+                        .with_path_text(path.to_string_lossy())
+                        .with_submatches(vec![SubMatch::new_text("foo", 0..3)])
+                        .with_lines_text(*lines)
+                        .with_offset(*offset)
+                        .build(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn it_deletes_lines_with_selected_matches() {
+        let p = temp_file!("foo 1\nbar\nfoo 2\r\nfoo 3\nfoo 4");
+        let mut items = line_items(
+            &p,
+            &[
+                (0, "foo 1\n"),
+                (10, "foo 2\r\n"),
+                (17, "foo 3\n"),
+                (23, "foo 4"),
+            ],
+        );
+        items[2].set_should_replace(0, false);
+
+        let mut criteria = ReplacementCriteria::new("", items);
+        criteria.set_operation(Operation::DeleteLine);
+        let summaries = replace_files(&criteria, false, None);
+        assert_eq!(summaries[0].replaced, 3);
+        assert!(summaries[0].is_complete());
+        assert_eq!(fs::read_to_string(p).unwrap(), "bar\nfoo 3\n");
+    }
+
+    #[test]
+    fn it_inserts_lines_above_and_below_matches() {
+        let contents = "foo 1\r\nbar\nfoo 2";
+        let lines = [(0, "foo 1\r\n"), (11, "foo 2")];
+
+        let p = temp_file!(contents);
+        let mut criteria = ReplacementCriteria::new("// $0", line_items(&p, &lines));
+        criteria.set_matcher(Matcher::new(&["foo"], MatcherOptions::default()));
+        criteria.set_operation(Operation::InsertAbove);
+        perform_replacements(criteria, None).unwrap();
+        assert_eq!(
+            fs::read_to_string(p).unwrap(),
+            "// foo\r\nfoo 1\r\nbar\n// foo\nfoo 2"
+        );
+
+        let p = temp_file!(contents);
+        let mut criteria = ReplacementCriteria::new("baz", line_items(&p, &lines));
+        criteria.set_operation(Operation::InsertBelow);
+        perform_replacements(criteria, None).unwrap();
+        assert_eq!(
+            fs::read_to_string(p).unwrap(),
+            "foo 1\r\nbaz\r\nbar\nfoo 2\nbaz"
+        );
+    }
+
+    #[test]
+    fn it_skips_line_operations_if_the_lines_do_not_match() {
+        let p = temp_file!("foo 1\nfoo 2\n");
+        let items = line_items(&p, &[(0, "foo 1\n"), (6, "foo 3\n")]);
+
+        let mut criteria = ReplacementCriteria::new("", items);
+        criteria.set_operation(Operation::DeleteLine);
+        let summaries = replace_files(&criteria, true, None);
+        assert_eq!(summaries[0].replaced, 1);
+        assert_eq!(summaries[0].skipped, 1);
+    }
+
+    #[test]
+    fn it_writes_no_files_if_any_match_cannot_be_replaced() {
+        let p1 = temp_file!("foo 1\n");
+        // The match doesn't match the contents of this file, so it's skipped.
+        let p2 = temp_file!("baz 2\n");
+        let mut items = line_items(&p1, &[(0, "foo 1\n")]);
+        items.extend(line_items(&p2, &[(0, "foo 2\n")]));
+
+        let mut criteria = ReplacementCriteria::new("bar", items);
+        criteria.set_all_or_nothing(true);
+        let summaries = replace_files(&criteria, false, None);
+        assert_eq!(fs::read_to_string(&p1).unwrap(), "foo 1\n");
+        assert_eq!(fs::read_to_string(&p2).unwrap(), "baz 2\n");
+        assert!(summaries
+            .iter()
+            .all(|s| s.replaced == 0 && s.error.is_some()));
+        assert_eq!(summaries.iter().map(|s| s.skipped).sum::<usize>(), 1);
+    }
+
+    #[test]
+    fn it_restores_written_files_if_any_fail_to_be_written() {
+        let d = tempdir().unwrap();
+        let p1 = d.path().join("a");
+        let p2 = d.path().join("missing").join("b");
+        fs::write(&p1, "foo").unwrap();
+
+        let summaries = vec![
+            FileReplacement::new(p1.display().to_string()),
+            FileReplacement::new(p2.display().to_string()),
+        ];
+        let patched = vec![
+            (p1.clone(), b"foo".to_vec(), b"bar".to_vec()),
+            (p2.clone(), b"foo".to_vec(), b"bar".to_vec()),
+        ];
+        let staged = vec![
+            stage_write(&p1, b"bar").unwrap(),
+            // This fails to be written, since its directory doesn't exist.
+            StagedWrite {
+                target: p2,
+                write: FileWrite {
+                    strategy: WriteStrategy::InPlace(2),
+                    symlink_target: None,
+                },
+                staged: Staged::InPlace(b"bar"),
+            },
+        ];
+
+        let state_dir = tempdir().unwrap();
+        let mut journal = Journal::new(state_dir.path()).unwrap();
+        journal.record(&p1, b"foo", b"bar").unwrap();
+
+        let summaries = persist_all(summaries, &patched, staged, Some(&mut journal));
+        assert_eq!(fs::read_to_string(&p1).unwrap(), "foo");
+        assert!(summaries[0]
+            .error
+            .as_ref()
+            .unwrap()
+            .to_string()
+            .ends_with("(restored the original contents)"));
+        assert!(summaries[1].error.is_some());
+        assert!(summaries.iter().all(|s| s.written.is_none()));
+
+        // Nothing was changed, so there's nothing to undo.
+        journal.save().unwrap();
+        assert!(undo(state_dir.path()).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn it_keeps_executable_scripts_executable() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let (item, p) = temp_item!(
+            0,
+            "#!/bin/sh\necho foo\n",
+            vec![SubMatch::new_text("foo", 15..18)]
+        );
+        fs::set_permissions(&p, fs::Permissions::from_mode(0o754)).unwrap();
+
+        perform_replacements(ReplacementCriteria::new("bar", vec![item]), None).unwrap();
+        assert_eq!(fs::read_to_string(&p).unwrap(), "#!/bin/sh\necho bar\n");
+        assert_eq!(
+            fs::metadata(&p).unwrap().permissions().mode() & 0o7777,
+            0o754
+        );
+    }
+
+    // NOTE: on Windows a read-only file can't be replaced by another file.
+    #[cfg(unix)]
+    #[test]
+    fn it_keeps_read_only_files_read_only() {
+        let (item, p) = temp_item!(0, "foo", vec![SubMatch::new_text("foo", 0..3)]);
+        let mut permissions = fs::metadata(&p).unwrap().permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(&p, permissions).unwrap();
+
+        perform_replacements(ReplacementCriteria::new("bar", vec![item]), None).unwrap();
+        assert_eq!(fs::read_to_string(&p).unwrap(), "bar");
+        assert!(fs::metadata(&p).unwrap().permissions().readonly());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn it_keeps_extended_attributes() {
+        let (item, p) = temp_item!(0, "foo", vec![SubMatch::new_text("foo", 0..3)]);
+        // Not every filesystem supports extended attributes.
+        if xattr::set(&p, "user.rgr.test", b"value").is_err() {
+            return;
+        }
+
+        perform_replacements(ReplacementCriteria::new("bar", vec![item]), None).unwrap();
+        assert_eq!(fs::read_to_string(&p).unwrap(), "bar");
+        assert_eq!(
+            xattr::get(&p, "user.rgr.test").unwrap(),
+            Some(b"value".to_vec())
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn it_writes_through_symlinks() {
+        let d = tempdir().unwrap();
+        let target = d.path().join("target");
+        let link = d.path().join("link");
+        fs::write(&target, "foo").unwrap();
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        let items = line_items(&link, &[(0, "foo")]);
+        let summaries = replace_files(&ReplacementCriteria::new("bar", items), false, None);
+        assert!(fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(fs::read_to_string(&target).unwrap(), "bar");
+        assert_eq!(
+            summaries[0].written,
+            Some(FileWrite {
+                strategy: WriteStrategy::TempFile,
+                symlink_target: Some(target.canonicalize().unwrap()),
+            })
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn it_refuses_to_write_through_symlinks_to_other_files() {
+        let d = tempdir().unwrap();
+        let link = d.path().join("link");
+        std::os::unix::fs::symlink("/dev/null", &link).unwrap();
+
+        let error = write_file(&link, b"bar").unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("Refusing to write through symlink"));
+        assert!(fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn it_writes_files_with_hard_links_in_place() {
+        let d = tempdir().unwrap();
+        let p = d.path().join("file");
+        let other = d.path().join("other");
+        fs::write(&p, "foo").unwrap();
+        fs::hard_link(&p, &other).unwrap();
+
+        let items = line_items(&p, &[(0, "foo")]);
+        let summaries = replace_files(&ReplacementCriteria::new("bar", items), false, None);
+        assert_eq!(fs::read_to_string(&p).unwrap(), "bar");
+        assert_eq!(fs::read_to_string(&other).unwrap(), "bar");
+        assert_eq!(
+            summaries[0].written,
+            Some(FileWrite {
+                strategy: WriteStrategy::InPlace(2),
+                symlink_target: None,
+            })
+        );
+    }
+
     // TODO: write a similar test for Windows/macOS systems
     #[test]
     #[cfg(not(any(target_os = "macos", target_os = "windows")))]
@@ -410,13 +1506,13 @@ mod tests {
             0,
             RgMessageBuilder::new(RgMessageKind::Match)
                 .with_path_base64(base64.encode_to_string(p.as_os_str().as_bytes()))
-                .with_lines_text(lines.to_string())
+                .with_lines_text(lines)
                 .with_submatches(vec![SubMatch::new_text("o", 4..5)])
                 .with_offset(0)
                 .build(),
         );
 
-        perform_replacements(ReplacementCriteria::new(" on", vec![item])).unwrap();
+        perform_replacements(ReplacementCriteria::new(" on", vec![item]), None).unwrap();
         assert_eq!(fs::read_to_string(p).unwrap(), "hell on earth");
     }
 
@@ -445,7 +1541,7 @@ mod tests {
                     })
                     .collect();
 
-                perform_replacements(ReplacementCriteria::new($replace, items)).unwrap();
+                perform_replacements(ReplacementCriteria::new($replace, items), None).unwrap();
 
                 // Read file bytes.
                 let mut file_bytes = vec![];
@@ -553,4 +1649,32 @@ mod tests {
         ("foo", "A"),
         &[(0, 0..3), (16, 4..7), (32, 8..11)]
     );
+
+    // The following are generated with:
+    //   printf "<NEEDLE> bar baz\ncaf\xe9\nbaz <NEEDLE> bar" | xxd -p -c 128
+    // (i.e. UTF-8 with a stray Latin-1 byte, which can't be decoded)
+
+    const INVALID_UTF8_FOO: &str = "666f6f206261722062617a0a636166e90a62617a20666f6f20626172";
+    const INVALID_UTF8_RUST: &str = "52555354206261722062617a0a636166e90a62617a205255535420626172";
+
+    simple_test!(
+        multiline_longer_invalid_utf8,
+        INVALID_UTF8_FOO,
+        INVALID_UTF8_RUST,
+        ("foo", "RUST"),
+        &[(0, 0..3), (17, 4..7)]
+    );
+
+    #[test]
+    fn it_splices_bytes_if_a_file_cannot_be_decoded() {
+        let p = temp_file!(bytes, b"foo \xff\xfe foo\n");
+        let items = line_items(&p, &[(0, "foo \u{fffd}\u{fffd} foo\n")]);
+
+        let summaries = replace_files(&ReplacementCriteria::new("bär", items), false, None);
+        assert!(matches!(
+            summaries[0].strategy,
+            Some(ReplaceStrategy::Bytes(_))
+        ));
+        assert_eq!(fs::read(&p).unwrap(), b"b\xc3\xa4r \xff\xfe foo\n");
+    }
 }
@@ -104,6 +104,16 @@ impl ArbitraryData {
         })
     }
 
+    /// Stores `bytes` as text if they're valid UTF-8, otherwise as Base64 (like `rg` does).
+    pub fn from_bytes(bytes: Vec<u8>) -> ArbitraryData {
+        match String::from_utf8(bytes) {
+            Ok(text) => ArbitraryData::Text { text },
+            Err(e) => ArbitraryData::Base64 {
+                bytes: base64.encode_to_string(e.into_bytes()),
+            },
+        }
+    }
+
     pub fn to_path_buf(&self) -> Result<PathBuf> {
         self.to_os_string().map(PathBuf::from)
     }
@@ -174,6 +184,22 @@ mod tests {
         )
     }
 
+    #[test]
+    fn arbitrary_data_from_bytes() {
+        assert_eq!(
+            ArbitraryData::from_bytes(b"foo".to_vec()),
+            Text {
+                text: "foo".to_owned()
+            }
+        );
+        assert_eq!(
+            ArbitraryData::from_bytes(vec![0x66, 0x6f, 0x80, 0x6f]),
+            Base64 {
+                bytes: "Zm+Abw==".to_owned()
+            }
+        );
+    }
+
     #[cfg(unix)]
     #[test]
     fn arbitrary_data_to_os_string_unix() {
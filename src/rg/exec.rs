@@ -1,68 +1,87 @@
 use std::ffi::OsStr;
 use std::fmt::Display;
-use std::io::{ErrorKind, Read};
-use std::process::{Command, Stdio};
+use std::io::{self, ErrorKind};
+use std::process::{Child, Command, ExitStatus, Stdio};
 
 use anyhow::{anyhow, Error, Result};
 
 use crate::rg::de::RgMessage;
+use crate::rg::search::Search;
 
 fn rg_run_error(msg: impl Display) -> Error {
     anyhow!("An error occurred when running `rg`:\n\n{}", msg)
 }
 
-pub fn run_ripgrep<I, S>(args: I) -> Result<Vec<RgMessage>>
+fn rg_spawn_error(e: io::Error) -> Error {
+    if let ErrorKind::NotFound = e.kind() {
+        anyhow!("Failed to find `rg`! Please make sure it's installed and available in PATH.")
+    } else {
+        rg_run_error(e)
+    }
+}
+
+/// Starts `rg` with the given arguments, with its output piped back to us.
+pub fn spawn_ripgrep<I, S>(args: I) -> Result<Child>
 where
     I: IntoIterator<Item = S>,
     S: AsRef<OsStr>,
 {
-    let mut child = match Command::new("rg")
+    Command::new("rg")
         // We use the JSON output
         .arg("--json")
-        // We don't (yet?) support reading `rg`'s config files
+        // Any arguments from `rg`'s config file are checked and passed to us, see `cli::apply_config`
         .arg("--no-config")
         .args(args)
         .stdout(Stdio::piped())
+        // NOTE: this is piped so it doesn't draw over the interface, see `check_exit_status`.
+        .stderr(Stdio::piped())
         .spawn()
-    {
-        Ok(child) => child,
-        Err(e) => {
-            if let ErrorKind::NotFound = e.kind() {
-                return Err(anyhow!(
-                    "Failed to find `rg`! Please make sure it's installed and available in PATH."
-                ));
-            } else {
-                return Err(rg_run_error(e));
-            }
-        }
-    };
+        .map_err(rg_spawn_error)
+}
+
+/// Runs `rg --files` with the given arguments, and returns the path of each file it would search.
+pub fn list_files<I, S>(args: I) -> Result<Vec<Vec<u8>>>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let output = Command::new("rg")
+        .arg("--files")
+        .arg("--no-config")
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(rg_spawn_error)?;
+    check_exit_status(output.status, &String::from_utf8_lossy(&output.stderr))?;
 
-    // Read messages from child process.
-    let rg_messages = super::read::read_messages(child.stdout.as_mut().unwrap())?;
+    Ok(output
+        .stdout
+        .split(|b| *b == b'\n')
+        .filter(|path| !path.is_empty())
+        .map(<[u8]>::to_vec)
+        .collect())
+}
 
-    // Wait for ripgrep to finish before returning.
-    match child.wait() {
-        Ok(exit_status) if exit_status.success() => Ok(rg_messages),
-        Ok(_) => {
-            let mut rg_stderr = String::new();
-            Err(
-                match child
-                    .stderr
-                    .as_mut()
-                    .unwrap()
-                    .read_to_string(&mut rg_stderr)
-                {
-                    Ok(_) => {
-                        if rg_stderr.is_empty() {
-                            anyhow!("No matches found")
-                        } else {
-                            rg_run_error(rg_stderr)
-                        }
-                    }
-                    Err(e) => anyhow!("failed to read rg's stderr: {}", e),
-                },
-            )
+/// Checks whether `rg` was successful, using what it wrote to STDERR to describe any error.
+pub fn check_exit_status(exit_status: ExitStatus, rg_stderr: &str) -> Result<()> {
+    if exit_status.success() {
+        if !rg_stderr.is_empty() {
+            log::warn!("rg wrote to stderr: {}", rg_stderr);
         }
-        Err(e) => Err(anyhow!("failed to wait for rg to end: {}", e)),
+
+        Ok(())
+    } else if rg_stderr.is_empty() {
+        Err(anyhow!("No matches found"))
+    } else {
+        Err(rg_run_error(rg_stderr))
     }
 }
+
+/// Runs `rg` with the given arguments, and waits for all of its results.
+pub fn run_ripgrep<I, S>(args: I) -> Result<Vec<RgMessage>>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    Search::ripgrep(args)?.collect()
+}
@@ -2,5 +2,6 @@ pub mod de;
 pub mod encoding;
 pub mod exec;
 pub mod read;
+pub mod search;
 
 pub use self::encoding::*;
@@ -1,29 +1,58 @@
-use std::io::{self, BufRead, BufReader, Read, Write};
+use std::io::{BufRead, BufReader, Read, Write};
 
 use anyhow::{anyhow, Result};
 
 use crate::rg::de::RgMessage;
 
-pub fn read_messages<R: Read>(rdr: R) -> Result<Vec<RgMessage>> {
+/// Reads each message from `rdr` as it arrives, and passes it to `on_message`.
+/// Stops reading early if `on_message` returns `false`.
+pub fn stream_messages<R: Read>(
+    rdr: R,
+    mut on_message: impl FnMut(RgMessage) -> bool,
+) -> Result<()> {
+    let reader = BufReader::new(rdr);
+    for line in reader.lines() {
+        let rg_msg: RgMessage =
+            serde_json::from_str(&line?).map_err(|e| anyhow!("Failed to parse JSON: {}", e))?;
+
+        if !on_message(rg_msg) {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads every message from `rdr`. For large result lists, the number of messages read so far is written to
+/// `progress` (if given) every so often.
+pub fn read_messages<R: Read>(
+    rdr: R,
+    mut progress: Option<&mut dyn Write>,
+) -> Result<Vec<RgMessage>> {
     let mut saw_match_message = false;
 
     let mut rg_messages: Vec<RgMessage> = vec![];
-    let reader = BufReader::new(rdr);
-    for (i, line) in reader.lines().enumerate() {
-        // For large result lists show some progress in the terminal.
-        if i > 0 && i % 1000 == 0 {
-            let _ = io::stdout().write_all(format!("\rMatches found: ~{}", i).as_bytes());
-            let _ = io::stdout().flush();
+    stream_messages(rdr, |rg_msg| {
+        let i = rg_messages.len();
+        if let Some(out) = progress
+            .as_mut()
+            .filter(|_| i > 0 && i.is_multiple_of(1000))
+        {
+            let _ = write!(out, "\rMatches found: ~{}", i);
+            let _ = out.flush();
         }
 
-        let rg_msg: RgMessage =
-            serde_json::from_str(&line?).map_err(|e| anyhow!("Failed to parse JSON: {}", e))?;
-
         if !saw_match_message && matches!(rg_msg, RgMessage::Match { .. }) {
             saw_match_message = true;
         }
 
         rg_messages.push(rg_msg);
+        true
+    })?;
+
+    // Finish the line the progress was written on.
+    if let Some(out) = progress.filter(|_| rg_messages.len() > 1000) {
+        let _ = writeln!(out);
     }
 
     // We expect at least one message.
@@ -0,0 +1,290 @@
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::{self, Cursor, IsTerminal, Read, Write};
+use std::path::PathBuf;
+use std::process::Child;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use anyhow::{anyhow, Result};
+
+use crate::model::FileSnapshot;
+use crate::rg::de::RgMessage;
+use crate::rg::exec::{check_exit_status, spawn_ripgrep};
+use crate::rg::read::{read_messages, stream_messages};
+
+/// An update from a search which is running in the background.
+#[derive(Debug)]
+pub enum SearchEvent {
+    /// A message was received from `rg`.
+    Message(RgMessage),
+    /// A snapshot of a file, taken just before the message which begins its results (see `Search::set_snapshots`).
+    Snapshot(PathBuf, FileSnapshot),
+    /// The search has ended, either successfully or with an error.
+    /// This is always the last event (and is successful if the search was cancelled).
+    Finished(Result<()>),
+}
+
+/// A running `rg` process.
+struct Ripgrep {
+    child: Arc<Mutex<Child>>,
+    stderr: JoinHandle<String>,
+}
+
+impl Ripgrep {
+    fn kill(&self) {
+        if let Err(e) = self.child.lock().unwrap().kill() {
+            log::warn!("Failed to kill rg: {}", e);
+        }
+    }
+
+    fn wait(self) -> Result<()> {
+        let exit_status = self.child.lock().unwrap().wait()?;
+        let rg_stderr = self.stderr.join().unwrap_or_default();
+        check_exit_status(exit_status, &rg_stderr)
+    }
+}
+
+/// The results of a search, which are read either from `rg` as it runs or from a file of its JSON output.
+pub struct Search {
+    reader: Box<dyn Read + Send>,
+    ripgrep: Option<Ripgrep>,
+    snapshots: bool,
+}
+
+impl Search {
+    /// Starts `rg` with the given arguments.
+    pub fn ripgrep<I, S>(args: I) -> Result<Search>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        let mut child = spawn_ripgrep(args)?;
+        let stdout = child.stdout.take().expect("rg's stdout was not piped");
+        let mut stderr = child.stderr.take().expect("rg's stderr was not piped");
+
+        // Read STDERR on its own thread, so `rg` doesn't block if it writes a lot to it.
+        let stderr = thread::spawn(move || {
+            let mut rg_stderr = String::new();
+            let _ = stderr.read_to_string(&mut rg_stderr);
+            rg_stderr
+        });
+
+        Ok(Search {
+            reader: Box::new(stdout),
+            ripgrep: Some(Ripgrep {
+                child: Arc::new(Mutex::new(child)),
+                stderr,
+            }),
+            snapshots: false,
+        })
+    }
+
+    /// Reads the JSON output of a previous `rg` run from a file.
+    pub fn file(file: File) -> Search {
+        Search {
+            reader: Box::new(file),
+            ripgrep: None,
+            snapshots: false,
+        }
+    }
+
+    /// Reads results which were already found (rather than by `rg`).
+    pub fn messages(rg_messages: &[RgMessage]) -> Result<Search> {
+        let mut json = vec![];
+        for rg_message in rg_messages {
+            serde_json::to_writer(&mut json, rg_message)?;
+            json.push(b'\n');
+        }
+
+        Ok(Search {
+            reader: Box::new(Cursor::new(json)),
+            ripgrep: None,
+            snapshots: false,
+        })
+    }
+
+    /// Sets whether each file is snapshotted as its results arrive, when the results are read on another thread
+    /// (so that files which change before they're written can be detected, without reading them on the caller's
+    /// thread).
+    pub fn set_snapshots(&mut self, snapshots: bool) {
+        self.snapshots = snapshots;
+    }
+
+    /// Waits for the search to end, and returns all of its results.
+    pub fn collect(self) -> Result<Vec<RgMessage>> {
+        // NOTE: progress is only shown on a terminal, so it's never mixed into output which is redirected (such as a
+        // diff written to STDOUT).
+        let mut stderr = io::stderr();
+        let progress = if stderr.is_terminal() {
+            Some(&mut stderr as &mut dyn Write)
+        } else {
+            None
+        };
+        let rg_messages = read_messages(self.reader, progress);
+        if let Some(ripgrep) = self.ripgrep {
+            if rg_messages.is_err() {
+                ripgrep.kill();
+            }
+
+            ripgrep.wait()?;
+        }
+
+        rg_messages
+    }
+
+    /// Reads the results on another thread, passing each to `on_event` as they arrive.
+    /// Returns a handle which cancels the search when it's dropped.
+    pub fn spawn<F>(self, mut on_event: F) -> SearchHandle
+    where
+        F: FnMut(SearchEvent) + Send + 'static,
+    {
+        let handle = SearchHandle {
+            child: self
+                .ripgrep
+                .as_ref()
+                .map(|ripgrep| Arc::clone(&ripgrep.child)),
+            cancelled: Arc::new(AtomicBool::new(false)),
+        };
+
+        let Search {
+            reader,
+            ripgrep,
+            snapshots,
+        } = self;
+        let cancelled = Arc::clone(&handle.cancelled);
+        thread::spawn(move || {
+            let mut saw_match_message = false;
+            let mut result = stream_messages(reader, |rg_message| {
+                saw_match_message |= matches!(rg_message, RgMessage::Match { .. });
+                if let Some((path, snapshot)) = snapshots
+                    .then(|| FileSnapshot::of_message(&rg_message))
+                    .flatten()
+                {
+                    on_event(SearchEvent::Snapshot(path, snapshot));
+                }
+                on_event(SearchEvent::Message(rg_message));
+                !cancelled.load(Ordering::SeqCst)
+            });
+
+            if let Some(ripgrep) = ripgrep {
+                if result.is_err() {
+                    ripgrep.kill();
+                }
+
+                result = result.and(ripgrep.wait());
+            }
+
+            let result = if cancelled.load(Ordering::SeqCst) {
+                log::debug!("Search cancelled");
+                Ok(())
+            } else if result.is_ok() && !saw_match_message {
+                Err(anyhow!("No matches returned from rg!"))
+            } else {
+                result
+            };
+
+            on_event(SearchEvent::Finished(result));
+        });
+
+        handle
+    }
+}
+
+/// A search which is running in the background.
+pub struct SearchHandle {
+    child: Option<Arc<Mutex<Child>>>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl SearchHandle {
+    /// Stops the search, killing `rg` if it's still running.
+    pub fn cancel(&self) {
+        if self.cancelled.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        if let Some(child) = &self.child {
+            log::debug!("Killing rg");
+            // NOTE: this is fine even if `rg` has already exited.
+            if let Err(e) = child.lock().unwrap().kill() {
+                log::warn!("Failed to kill rg: {}", e);
+            }
+        }
+    }
+}
+
+impl Drop for SearchHandle {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::sync::mpsc;
+
+    use tempfile::NamedTempFile;
+
+    use crate::rg::de::test_utilities::*;
+    use crate::rg::de::{RgMessage, RgMessageKind};
+    use crate::rg::search::{Search, SearchEvent};
+
+    #[test]
+    fn it_streams_messages_from_a_file() {
+        let mut file = NamedTempFile::new().unwrap();
+        for json in [RG_JSON_BEGIN, RG_JSON_MATCH, RG_JSON_END, RG_JSON_SUMMARY] {
+            writeln!(file, "{}", json).unwrap();
+        }
+
+        let (tx, rx) = mpsc::channel();
+        let _handle = Search::file(file.reopen().unwrap()).spawn(move |event| {
+            tx.send(event).unwrap();
+        });
+
+        let events = rx.iter().collect::<Vec<_>>();
+        assert_eq!(events.len(), 5);
+        assert!(events[..4]
+            .iter()
+            .all(|event| matches!(event, SearchEvent::Message(_))));
+        assert!(matches!(events[4], SearchEvent::Finished(Ok(()))));
+    }
+
+    #[test]
+    fn it_fails_if_there_are_no_matches() {
+        let mut file = NamedTempFile::new().unwrap();
+        writeln!(file, "{}", RG_JSON_SUMMARY).unwrap();
+
+        let (tx, rx) = mpsc::channel();
+        let _handle = Search::file(file.reopen().unwrap()).spawn(move |event| {
+            tx.send(event).unwrap();
+        });
+
+        let events = rx.iter().collect::<Vec<_>>();
+        assert!(matches!(events[1], SearchEvent::Finished(Err(_))));
+    }
+
+    #[test]
+    fn it_snapshots_each_file_before_its_results() {
+        let file = NamedTempFile::new().unwrap();
+        let begin = RgMessageBuilder::new(RgMessageKind::Begin)
+            .with_path_text(file.path().to_string_lossy())
+            .build();
+        let mut search = Search::messages(&[begin]).unwrap();
+        search.set_snapshots(true);
+
+        let (tx, rx) = mpsc::channel();
+        let _handle = search.spawn(move |event| {
+            tx.send(event).unwrap();
+        });
+
+        let events = rx.iter().collect::<Vec<_>>();
+        assert!(matches!(&events[0], SearchEvent::Snapshot(path, _) if path == file.path()));
+        assert!(matches!(
+            events[1],
+            SearchEvent::Message(RgMessage::Begin { .. })
+        ));
+    }
+}
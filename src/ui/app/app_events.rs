@@ -4,9 +4,10 @@ use crossterm::event::{Event, KeyCode, KeyModifiers};
 use either::Either;
 use tui::layout::Rect;
 
-use crate::model::{Movement, ReplacementCriteria};
-use crate::rg::de::RgMessageKind;
-use crate::ui::app::{App, AppState, AppUiState};
+use crate::model::{Editor, Filter, Movement, Predicate, ReplacementCriteria, ReplacementOutput};
+use crate::rg::de::{ArbitraryData, RgMessageKind};
+use crate::ui::app::{App, AppState, AppUiState, DetailPane, SearchState, PREVIEW_CONTEXT_STEP};
+use crate::ui::line::Item;
 use crate::util::clamp;
 
 impl App {
@@ -24,12 +25,15 @@ impl App {
                 return Ok(());
             }
             Event::Key(key) => {
+                self.notice = None;
+
                 // Common Ctrl+Key scroll keybindings that apply to multiple modes.
                 let control_pressed = key.modifiers.contains(KeyModifiers::CONTROL);
                 if control_pressed {
                     let did_handle_key = match &self.ui_state {
                         AppUiState::SelectMatches
                         | AppUiState::InputReplacement(_)
+                        | AppUiState::InputOverride(_)
                         | AppUiState::ConfirmReplacement(_) => match key.code {
                             // Page movements
                             KeyCode::Char('b') => {
@@ -53,6 +57,18 @@ impl App {
                                 self.update_indicator(term_size);
                                 true
                             }
+
+                            // Toggle the side-by-side view of the selected file
+                            KeyCode::Char('t') => {
+                                self.toggle_pane(DetailPane::Split, term_size);
+                                true
+                            }
+
+                            // Stop searching
+                            KeyCode::Char('c') if self.search_state == SearchState::Searching => {
+                                self.search_state = SearchState::Cancelled;
+                                true
+                            }
                             _ => false,
                         },
                         _ => false,
@@ -67,14 +83,21 @@ impl App {
                 match &self.ui_state {
                     AppUiState::ConfirmReplacement(replacement) => match key.code {
                         KeyCode::Esc | KeyCode::Char('q') => {
-                            self.ui_state = AppUiState::InputReplacement(replacement.to_owned())
+                            self.ui_state =
+                                AppUiState::InputReplacement(Editor::new(replacement.to_owned()))
                         }
                         KeyCode::Enter => {
-                            self.state = AppState::Complete(ReplacementCriteria::new(
-                                replacement,
-                                self.list.clone(),
-                            ));
+                            let mut criteria =
+                                ReplacementCriteria::new(replacement, self.list.clone());
+                            criteria.set_matcher(self.matcher.clone());
+                            criteria.set_pattern_texts(self.pattern_texts.clone());
+                            criteria.set_preserve_case(self.preserve_case);
+                            criteria.set_operation(self.operation);
+                            criteria.set_output(self.output);
+                            criteria.set_snapshots(self.snapshots.clone());
+                            self.state = AppState::Complete(Box::new(criteria));
                         }
+                        KeyCode::Char('d') => self.toggle_output(),
                         _ => {}
                     },
                     AppUiState::Help => match key.code {
@@ -85,6 +108,14 @@ impl App {
                         KeyCode::Char('j') | KeyCode::Down => self.help_text_state.incr(),
                         _ => {}
                     },
+                    // Nothing can be selected until the first results arrive.
+                    AppUiState::SelectMatches if self.list.is_empty() => match key.code {
+                        KeyCode::Esc | KeyCode::Char('q') => self.state = AppState::Cancelled,
+                        KeyCode::Char('?') => self.ui_state = AppUiState::Help,
+                        KeyCode::Char('e') => self.edit_search(),
+                        KeyCode::Char('/') => self.input_filter(),
+                        _ => {}
+                    },
                     AppUiState::SelectMatches => {
                         let shift = key.modifiers.contains(KeyModifiers::SHIFT);
                         match key.code {
@@ -116,43 +147,155 @@ impl App {
                             KeyCode::Char('a') | KeyCode::Char('A') => self.toggle_all_items(),
                             KeyCode::Char('v') => self.invert_selection_current(),
                             KeyCode::Char('V') => self.invert_selection_all(),
+                            // If the list is filtered, then the first escape removes the filter.
+                            KeyCode::Esc if self.filter.is_some() => {
+                                self.set_filter(None);
+                                self.select_visible_item(term_size);
+                            }
                             KeyCode::Esc | KeyCode::Char('q') => self.state = AppState::Cancelled,
                             KeyCode::Char('?') => self.ui_state = AppUiState::Help,
+                            KeyCode::Char('/') => self.input_filter(),
+                            KeyCode::Char('+') => {
+                                self.ui_state = AppUiState::InputPredicate(true, String::new())
+                            }
+                            KeyCode::Char('-') => {
+                                self.ui_state = AppUiState::InputPredicate(false, String::new())
+                            }
+                            KeyCode::Char('o') => self.input_override(),
+                            KeyCode::Char('O') => self.clear_override(),
+                            KeyCode::Char('p') => self.input_pattern_replacement(),
+                            KeyCode::Char('P') => self.clear_pattern_replacement(),
+                            KeyCode::Char('e') => self.edit_search(),
+                            KeyCode::Char('c') => self.toggle_pane(DetailPane::Preview, term_size),
+                            KeyCode::Char('>') => self.resize_preview(true, term_size),
+                            KeyCode::Char('<') => self.resize_preview(false, term_size),
                             KeyCode::Enter | KeyCode::Char('r') | KeyCode::Char('R') => {
-                                self.ui_state = AppUiState::InputReplacement(String::new())
+                                self.ui_state = AppUiState::InputReplacement(Editor::default())
                             }
                             _ => {}
                         }
                     }
-                    AppUiState::InputReplacement(ref input) => match key.code {
-                        KeyCode::Char(ch) => {
-                            if control_pressed && ch == 's' {
-                                self.ui_state = AppUiState::ConfirmReplacement(input.to_owned());
-                            } else {
-                                self.ui_state =
-                                    AppUiState::InputReplacement(format!("{}{}", input, ch));
+                    AppUiState::InputReplacement(ref editor)
+                    | AppUiState::InputOverride(ref editor)
+                    | AppUiState::InputPatternReplacement(ref editor) => {
+                        let alt_pressed = key.modifiers.contains(KeyModifiers::ALT);
+                        let input = editor.text().to_owned();
+                        // Searching the history continues for as long as <control+r> is pressed.
+                        if !(control_pressed && key.code == KeyCode::Char('r')) {
+                            self.replacement_history_query = None;
+                        }
+
+                        match key.code {
+                            KeyCode::Char('s') if control_pressed => self.accept_input(input),
+                            KeyCode::Char('r') if control_pressed => {
+                                self.search_replacement_history(term_size, input)
+                            }
+                            KeyCode::Char('p') if control_pressed => {
+                                self.preserve_case = !self.preserve_case
+                            }
+                            KeyCode::Char('o') if control_pressed => self.cycle_operation(),
+                            KeyCode::Char('w') if control_pressed => {
+                                self.edit_input(term_size, Editor::delete_word)
+                            }
+                            KeyCode::Char('u') if control_pressed => {
+                                self.edit_input(term_size, Editor::delete_line)
+                            }
+                            KeyCode::Char('b') if alt_pressed => {
+                                self.edit_input(term_size, Editor::move_word_left)
                             }
+                            KeyCode::Char('f') if alt_pressed => {
+                                self.edit_input(term_size, Editor::move_word_right)
+                            }
+                            KeyCode::Char(ch) => self.edit_input(term_size, |e| e.insert(ch)),
+                            KeyCode::Enter => self.edit_input(term_size, |e| e.insert('\n')),
+                            KeyCode::Backspace => self.edit_input(term_size, Editor::backspace),
+                            KeyCode::Delete => self.edit_input(term_size, Editor::delete),
+                            KeyCode::Left if control_pressed => {
+                                self.edit_input(term_size, Editor::move_word_left)
+                            }
+                            KeyCode::Right if control_pressed => {
+                                self.edit_input(term_size, Editor::move_word_right)
+                            }
+                            KeyCode::Left => self.edit_input(term_size, Editor::move_left),
+                            KeyCode::Right => self.edit_input(term_size, Editor::move_right),
+                            KeyCode::Home => self.edit_input(term_size, Editor::move_home),
+                            KeyCode::End => self.edit_input(term_size, Editor::move_end),
+                            // Moving past the first or last line recalls the replacement history.
+                            KeyCode::Up | KeyCode::Down => {
+                                let up = key.code == KeyCode::Up;
+                                if !self.edit_input(term_size, |e| e.move_vertically(up)) {
+                                    self.recall_replacement(term_size, input, up);
+                                }
+                            }
+                            KeyCode::Esc => {
+                                self.reset_replacement_history();
+                                self.ui_state = AppUiState::SelectMatches;
+                            }
+                            _ => {}
+                        }
+                    }
+                    AppUiState::InputFilter(ref input) => match key.code {
+                        KeyCode::Char(ch) if !control_pressed => {
+                            self.update_filter(format!("{}{}", input, ch), term_size)
                         }
                         KeyCode::Backspace => {
-                            if !input.is_empty() {
-                                // trim off the last character
-                                let input = input.chars().rev().skip(1).collect::<Vec<_>>();
-                                let input = input.iter().rev().collect::<String>();
-                                self.ui_state = AppUiState::InputReplacement(input);
+                            let mut input = input.to_owned();
+                            input.pop();
+                            self.update_filter(input, term_size);
+                        }
+                        KeyCode::Enter => self.ui_state = AppUiState::SelectMatches,
+                        KeyCode::Esc => {
+                            self.update_filter(String::new(), term_size);
+                            self.ui_state = AppUiState::SelectMatches;
+                        }
+                        _ => {}
+                    },
+                    AppUiState::InputPredicate(select, ref input) => {
+                        let select = *select;
+                        match key.code {
+                            KeyCode::Char(ch) if !control_pressed => {
+                                self.error = None;
+                                self.ui_state =
+                                    AppUiState::InputPredicate(select, format!("{}{}", input, ch));
+                            }
+                            KeyCode::Backspace => {
+                                let mut input = input.to_owned();
+                                input.pop();
+                                self.error = None;
+                                self.ui_state = AppUiState::InputPredicate(select, input);
                             }
+                            KeyCode::Enter => {
+                                let input = input.to_owned();
+                                self.select_by_predicate(select, &input);
+                            }
+                            KeyCode::Esc => {
+                                self.error = None;
+                                self.ui_state = AppUiState::SelectMatches;
+                            }
+                            _ => {}
                         }
-                        KeyCode::Esc => self.ui_state = AppUiState::SelectMatches,
+                    }
+                    AppUiState::EditSearch(ref input) => match key.code {
+                        KeyCode::Char(ch) if !control_pressed => {
+                            self.set_search_input(format!("{}{}", input, ch))
+                        }
+                        KeyCode::Backspace => {
+                            let mut input = input.to_owned();
+                            input.pop();
+                            self.set_search_input(input);
+                        }
+                        KeyCode::Up => self.recall_search(true),
+                        KeyCode::Down => self.recall_search(false),
                         KeyCode::Enter => {
-                            self.ui_state = AppUiState::InputReplacement(format!("{}\n", input));
+                            let input = input.to_owned();
+                            self.search(&input);
+                        }
+                        KeyCode::Esc => {
+                            if self.search_state != SearchState::Failed {
+                                self.error = None;
+                            }
+                            self.ui_state = AppUiState::SelectMatches;
                         }
-
-                        // TODO: use arrow keys to move "cursor" in text
-                        KeyCode::Up => {}
-                        KeyCode::Down => {}
-                        KeyCode::Left => {}
-                        KeyCode::Right => {}
-                        // TODO: use "delete" key to forward delete
-                        KeyCode::Delete => {}
                         _ => {}
                     },
                 }
@@ -163,6 +306,292 @@ impl App {
         Ok(())
     }
 
+    /// Toggles between writing the replacements to the files, and writing a diff of them.
+    pub(crate) fn toggle_output(&mut self) {
+        self.output = match self.output {
+            ReplacementOutput::Files => ReplacementOutput::Diff,
+            ReplacementOutput::Diff => ReplacementOutput::Files,
+        };
+    }
+
+    /// Cycles through the operations which can be done with each line containing selected matches. When renaming
+    /// files, only the matches in their paths can be substituted.
+    pub(crate) fn cycle_operation(&mut self) {
+        if !self.rename {
+            self.operation = self.operation.cycle();
+        }
+    }
+
+    /// Shows the pane below the list, or hides it if it's already shown.
+    pub(crate) fn toggle_pane(&mut self, pane: DetailPane, term_size: Rect) {
+        self.detail_pane = if self.detail_pane == Some(pane) {
+            None
+        } else {
+            Some(pane)
+        };
+        // The list is shorter while the pane is shown, so it may need to scroll to keep the selected match visible.
+        self.update_indicator(term_size);
+    }
+
+    /// Grows (or shrinks) how many lines are shown around the selected match in the preview pane, showing the
+    /// pane if it isn't already.
+    pub(crate) fn resize_preview(&mut self, grow: bool, term_size: Rect) {
+        self.preview_context = if grow {
+            self.preview_context + PREVIEW_CONTEXT_STEP
+        } else {
+            self.preview_context.saturating_sub(PREVIEW_CONTEXT_STEP)
+        };
+        if self.detail_pane != Some(DetailPane::Preview) {
+            self.toggle_pane(DetailPane::Preview, term_size);
+        }
+    }
+
+    /// Prompts for text to filter the list by, starting with the current filter.
+    pub(crate) fn input_filter(&mut self) {
+        let input = self.filter.as_ref().map(|f| f.text().to_owned());
+        self.ui_state = AppUiState::InputFilter(input.unwrap_or_default());
+    }
+
+    /// Filters the list as the text is entered. An empty filter shows every item.
+    fn update_filter(&mut self, input: String, term_size: Rect) {
+        let filter = Some(&input)
+            .filter(|input| !input.is_empty())
+            .map(Filter::new);
+        self.set_filter(filter);
+        self.ui_state = AppUiState::InputFilter(input);
+        self.select_visible_item(term_size);
+    }
+
+    /// After the list is filtered, moves the selection to the first visible item if the selected item is hidden.
+    fn select_visible_item(&mut self, term_size: Rect) {
+        if self.list.is_empty() {
+            return;
+        }
+
+        if self.list[self.list_state.selected_item()].is_hidden() {
+            let first_visible = self
+                .list
+                .iter()
+                .position(|item| !item.is_hidden() && item.is_selectable());
+            self.list_state
+                .set_selected_item(first_visible.unwrap_or_default());
+            self.list_state.set_selected_submatch(0);
+        }
+
+        // The lines before the selected item have changed, so scroll from the top again.
+        self.list_state.set_window_start(0);
+        self.update_indicator(term_size);
+    }
+
+    /// Edits the current search, starting with the pattern and flags passed to `rg`.
+    pub(crate) fn edit_search(&mut self) {
+        self.search_history_pos = None;
+        self.ui_state = AppUiState::EditSearch(self.rg_cmdline.clone());
+    }
+
+    fn set_search_input(&mut self, input: String) {
+        self.error = None;
+        self.ui_state = AppUiState::EditSearch(input);
+    }
+
+    /// Replaces the edited search with an older (or newer) one from this session's history.
+    /// Searches which are the same as the current input are skipped.
+    fn recall_search(&mut self, older: bool) {
+        let input = match &self.ui_state {
+            AppUiState::EditSearch(input) => input.to_owned(),
+            _ => unreachable!("recall_search called outside of the search mode"),
+        };
+
+        let len = self.search_history.len();
+        let mut pos = self.search_history_pos;
+        loop {
+            pos = match (pos, older) {
+                (None, true) => len.checked_sub(1),
+                (Some(pos), true) => pos.checked_sub(1),
+                (None, false) => return,
+                (Some(pos), false) => Some(pos + 1).filter(|pos| *pos < len),
+            };
+
+            match pos {
+                Some(i) if self.search_history[i] == input => continue,
+                Some(i) => {
+                    self.search_history_pos = pos;
+                    self.set_search_input(self.search_history[i].clone());
+                    return;
+                }
+                // There's nothing older, so keep the input as it is.
+                None if older => return,
+                // We've gone past the newest search, so return to the current one.
+                None => {
+                    self.search_history_pos = None;
+                    self.set_search_input(self.rg_cmdline.clone());
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Replaces the text being entered with an older (or newer) replacement from the history, which is shared by
+    /// each of the replacement input modes. Going past the newest returns to the text which was being entered.
+    /// Entries which are the same as the current input are skipped.
+    fn recall_replacement(&mut self, term_size: Rect, input: String, older: bool) {
+        let len = self.history.replacements().len();
+        let mut pos = self.replacement_history_pos;
+        loop {
+            pos = match (pos, older) {
+                (None, true) => len.checked_sub(1),
+                (Some(pos), true) => pos.checked_sub(1),
+                (None, false) => return,
+                (Some(pos), false) => Some(pos + 1).filter(|pos| *pos < len),
+            };
+
+            match pos {
+                Some(i) if self.history.replacements()[i] == input => continue,
+                Some(i) => {
+                    let text = self.history.replacements()[i].clone();
+                    self.recall_input(term_size, input, i, text);
+                    return;
+                }
+                // There's nothing older, so keep the input as it is.
+                None if older => return,
+                // We've gone past the newest replacement, so return to the text being entered.
+                None => {
+                    let draft = self.replacement_draft.take().unwrap_or_default();
+                    self.replacement_history_pos = None;
+                    self.edit_input(term_size, |e| *e = Editor::new(draft));
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Replaces the text being entered with the next older replacement in the history which contains the query.
+    /// The query is the text which was entered when <control+r> was first pressed.
+    fn search_replacement_history(&mut self, term_size: Rect, input: String) {
+        let query = self
+            .replacement_history_query
+            .get_or_insert_with(|| input.clone());
+        let end = self
+            .replacement_history_pos
+            .unwrap_or(self.history.replacements().len());
+        let found = self.history.replacements()[..end]
+            .iter()
+            .rposition(|text| text.contains(query.as_str()) && *text != input);
+
+        if let Some(i) = found {
+            let text = self.history.replacements()[i].clone();
+            self.recall_input(term_size, input, i, text);
+        }
+    }
+
+    /// Replaces the text being entered with the replacement at `pos` in the history.
+    fn recall_input(&mut self, term_size: Rect, input: String, pos: usize, text: String) {
+        if self.replacement_history_pos.is_none() {
+            self.replacement_draft = Some(input);
+        }
+        self.replacement_history_pos = Some(pos);
+        self.edit_input(term_size, |e| *e = Editor::new(text));
+    }
+
+    fn reset_replacement_history(&mut self) {
+        self.replacement_history_pos = None;
+        self.replacement_draft = None;
+        self.replacement_history_query = None;
+    }
+
+    /// Edits the text being entered in any of the replacement input modes.
+    fn edit_input<T>(&mut self, term_size: Rect, edit: impl FnOnce(&mut Editor) -> T) -> T {
+        let editor = match &mut self.ui_state {
+            AppUiState::InputReplacement(editor)
+            | AppUiState::InputOverride(editor)
+            | AppUiState::InputPatternReplacement(editor) => editor,
+            _ => unreachable!("edit_input called outside of an input mode"),
+        };
+
+        let line_count = editor.line_count();
+        let result = edit(editor);
+        // The input line grows with the text, so the list may need to scroll to keep the selected match visible.
+        if editor.line_count() != line_count {
+            self.update_indicator(term_size);
+        }
+        result
+    }
+
+    /// Accepts the text entered in either of the input modes, and adds it to the history.
+    fn accept_input(&mut self, input: String) {
+        self.history.add_replacement(&input);
+        self.reset_replacement_history();
+        match self.ui_state {
+            AppUiState::InputReplacement(_) => {
+                self.ui_state = AppUiState::ConfirmReplacement(input);
+            }
+            AppUiState::InputOverride(_) => {
+                let selected_item = self.list_state.selected_item();
+                let selected_match = self.list_state.selected_submatch();
+                let item = &mut self.list[selected_item];
+                item.set_replacement(selected_match, Some(input));
+                item.set_should_replace(selected_match, true);
+                self.ui_state = AppUiState::SelectMatches;
+            }
+            AppUiState::InputPatternReplacement(_) => {
+                if let Some(pattern) = self.selected_pattern() {
+                    self.pattern_texts.insert(pattern.to_owned(), input);
+                }
+                self.ui_state = AppUiState::SelectMatches;
+            }
+            _ => unreachable!("accept_input called outside of an input mode"),
+        }
+    }
+
+    /// Prompts for replacement text for only the selected match.
+    /// If the match already has its own replacement, then that's used as the initial input.
+    pub(crate) fn input_override(&mut self) {
+        let selected_item = self.list_state.selected_item();
+        let selected_match = self.list_state.selected_submatch();
+
+        let item = &self.list[selected_item];
+        if matches!(item.kind, RgMessageKind::Match) {
+            let input = item.get_replacement(selected_match).unwrap_or_default();
+            self.ui_state = AppUiState::InputOverride(Editor::new(input));
+        }
+    }
+
+    /// Removes the replacement text for only the selected match, if it has one.
+    pub(crate) fn clear_override(&mut self) {
+        let selected_item = self.list_state.selected_item();
+        let selected_match = self.list_state.selected_submatch();
+
+        let item = &mut self.list[selected_item];
+        if matches!(item.kind, RgMessageKind::Match) {
+            item.set_replacement(selected_match, None);
+        }
+    }
+
+    /// Returns the pattern which produced the selected match, if it's known.
+    pub(crate) fn selected_pattern(&self) -> Option<&str> {
+        let item = &self.list[self.list_state.selected_item()];
+        item.sub_items()
+            .get(self.list_state.selected_submatch())
+            .and_then(|sub_item| sub_item.pattern)
+            .and_then(|i| self.matcher.pattern(i))
+    }
+
+    /// Prompts for replacement text for the matches of the pattern which produced the selected match.
+    /// If the pattern already has its own replacement, then that's used as the initial input.
+    pub(crate) fn input_pattern_replacement(&mut self) {
+        if let Some(pattern) = self.selected_pattern() {
+            let input = self.pattern_texts.get(pattern).cloned().unwrap_or_default();
+            self.ui_state = AppUiState::InputPatternReplacement(Editor::new(input));
+        }
+    }
+
+    /// Removes the replacement text for the pattern which produced the selected match, if it has one.
+    pub(crate) fn clear_pattern_replacement(&mut self) {
+        if let Some(pattern) = self.selected_pattern().map(str::to_owned) {
+            self.pattern_texts.remove(&pattern);
+        }
+    }
+
     fn move_horizonally(&mut self, movement: &Movement) -> bool {
         let selected_item = self.list_state.selected_item();
         let selected_match = self.list_state.selected_submatch();
@@ -182,9 +611,21 @@ impl App {
     }
 
     fn move_vertically(&mut self, movement: &Movement) {
+        // Only move between the items which aren't hidden by the filter.
+        let selected_item = self.list_state.selected_item();
+        let visible_len = self.list.iter().filter(|i| !i.is_hidden()).count();
+        let visible_pos = self.list[..selected_item]
+            .iter()
+            .filter(|i| !i.is_hidden())
+            .count();
+
         // Reverse the iterator depending on movement direction.
         let iterator = {
-            let iter = self.list.iter().enumerate();
+            let iter = self
+                .list
+                .iter()
+                .enumerate()
+                .filter(|(_, item)| !item.is_hidden());
             if movement.is_forward() {
                 Either::Right(iter)
             } else {
@@ -193,22 +634,25 @@ impl App {
         };
 
         // Determine how far to skip down the list.
-        let selected_item = self.list_state.selected_item();
+        let first_visible = self.list.iter().position(|i| !i.is_hidden());
+        let last_visible = self.list.iter().rposition(|i| !i.is_hidden());
         let (skip, default_item_idx) = match movement {
-            Movement::Prev | Movement::PrevLine | Movement::PrevFile => {
-                (self.list.len().saturating_sub(selected_item), 0)
-            }
+            Movement::Prev | Movement::PrevLine | Movement::PrevFile => (
+                visible_len.saturating_sub(visible_pos),
+                first_visible.unwrap_or(0),
+            ),
             Movement::Backward(n) => (
-                self.list
-                    .len()
-                    .saturating_sub(selected_item.saturating_sub((*n - 1) as usize)),
-                0,
+                visible_len.saturating_sub(visible_pos.saturating_sub((*n - 1) as usize)),
+                first_visible.unwrap_or(0),
             ),
 
             Movement::Next | Movement::NextLine | Movement::NextFile => {
-                (selected_item, self.list.len() - 1)
+                (visible_pos, last_visible.unwrap_or(self.list.len() - 1))
             }
-            Movement::Forward(n) => (selected_item + (*n as usize), self.list.len() - 1),
+            Movement::Forward(n) => (
+                visible_pos + (*n as usize),
+                last_visible.unwrap_or(self.list.len() - 1),
+            ),
         };
 
         // Find the new position.
@@ -249,12 +693,20 @@ impl App {
     /// a match which spans multiple lines and has multiple submatches, the start of the selected submatch.
     /// Note that this is also the mechanism which scrolls tui-rs' list interface.
     fn update_indicator(&mut self, term_size: Rect) {
+        if self.list.is_empty() {
+            return;
+        }
+
         let item_idx = self.list_state.selected_item();
         let match_idx = self.list_state.selected_submatch();
         let main_view_list_rect = self.main_view_list_rect(term_size);
 
         let mut indicator_idx = 0;
         for item in &mut self.list.as_mut_slice()[0..item_idx] {
+            if item.is_hidden() {
+                continue;
+            }
+
             let item_height = item.line_count(main_view_list_rect.width, self.printable_style);
             indicator_idx += item_height;
         }
@@ -287,6 +739,10 @@ impl App {
     }
 
     pub(crate) fn move_pos(&mut self, movement: Movement, term_size: Rect) {
+        if self.list.is_empty() {
+            return;
+        }
+
         if !self.move_horizonally(&movement) {
             self.move_vertically(&movement);
         }
@@ -322,10 +778,60 @@ impl App {
         }
     }
 
+    /// Selects (or deselects) every match which satisfies the predicate in `input`, and reports how many changed.
+    /// If the list is filtered, then only the visible matches are changed.
+    fn select_by_predicate(&mut self, select: bool, input: &str) {
+        let predicate = match input.parse::<Predicate>() {
+            Ok(predicate) => predicate,
+            Err(e) => {
+                self.error = Some(e.to_string());
+                return;
+            }
+        };
+
+        let mut changed = 0;
+        for item in self
+            .visible_items()
+            .filter(|i| matches!(i.kind, RgMessageKind::Match))
+        {
+            let path = item.path_buf().unwrap_or_default();
+            let lines = item.lines().map(ArbitraryData::to_vec).unwrap_or_default();
+            for i in 0..item.sub_items().len() {
+                let text = item.sub_items()[i].sub_match.text.to_vec();
+                if item.get_should_replace(i) != select && predicate.is_match(&path, &lines, &text)
+                {
+                    item.set_should_replace(i, select);
+                    changed += 1;
+                }
+            }
+        }
+
+        log::debug!(
+            "Changed {} match(es) with predicate: {:?}",
+            changed,
+            predicate
+        );
+        self.notice = Some(format!(
+            "{} {} match(es)",
+            if select { "Selected" } else { "Deselected" },
+            changed
+        ));
+        self.ui_state = AppUiState::SelectMatches;
+    }
+
+    /// Returns the items which aren't hidden by the filter.
+    fn visible_items(&mut self) -> impl Iterator<Item = &mut Item> {
+        self.list.iter_mut().filter(|i| !i.is_hidden())
+    }
+
     pub(crate) fn toggle_all_items(&mut self) {
-        let should_replace = !self.list.iter().all(|i| i.get_should_replace_all());
+        let should_replace = !self
+            .list
+            .iter()
+            .filter(|i| !i.is_hidden())
+            .all(|i| i.get_should_replace_all());
 
-        for item in self.list.iter_mut() {
+        for item in self.visible_items() {
             item.set_should_replace_all(should_replace);
         }
     }
@@ -344,17 +850,17 @@ impl App {
         }
     }
 
-    fn get_all_items_in_file(&mut self, selected_item: usize) -> Vec<&mut crate::ui::line::Item> {
+    fn get_all_items_in_file(&mut self, selected_item: usize) -> Vec<&mut Item> {
         self.list
             .iter_mut()
             .skip(selected_item)
             .take_while(|i| i.kind != RgMessageKind::End)
-            .filter(|i| i.kind == RgMessageKind::Match)
+            .filter(|i| i.kind == RgMessageKind::Match && !i.is_hidden())
             .collect()
     }
 
     fn invert_selection_all(&mut self) {
-        for item in self.list.iter_mut() {
+        for item in self.visible_items() {
             item.invert_selection();
         }
     }
@@ -362,10 +868,15 @@ impl App {
 
 #[cfg(test)]
 mod tests {
+    use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
     use pretty_assertions::assert_eq;
     use tui::layout::Rect;
 
-    use crate::model::Movement;
+    use crate::history::History;
+    use crate::model::{
+        Editor, Filter, Matcher, MatcherOptions, Movement, Operation, PatternTexts,
+        ReplacementOutput,
+    };
     use crate::rg::de::test_utilities::*;
     use crate::rg::de::*;
     use crate::ui::app::*;
@@ -408,7 +919,13 @@ mod tests {
     }
 
     fn new_app() -> App {
-        App::new("TESTS".to_string(), rg_messages())
+        App::new(
+            "TESTS".to_string(),
+            Matcher::empty(),
+            false,
+            ReplacementOutput::Files,
+            rg_messages(),
+        )
     }
 
     fn new_app_multiple_files() -> App {
@@ -424,7 +941,13 @@ mod tests {
         messages_multiple_files.extend(messages_multiple_files.clone());
         messages_multiple_files.push(RgMessage::from_str(RG_JSON_SUMMARY));
 
-        App::new("TESTS".to_string(), messages_multiple_files)
+        App::new(
+            "TESTS".to_string(),
+            Matcher::empty(),
+            false,
+            ReplacementOutput::Files,
+            messages_multiple_files,
+        )
     }
 
     type PosTriple = (usize, usize, usize);
@@ -458,7 +981,13 @@ mod tests {
             RgMessage::from_str(RG_JSON_SUMMARY),
         ];
 
-        App::new("TESTS".to_string(), messages)
+        App::new(
+            "TESTS".to_string(),
+            Matcher::empty(),
+            false,
+            ReplacementOutput::Files,
+            messages,
+        )
     }
 
     // Valid positions for the app returned by `new_app_line_wrapping`.
@@ -566,6 +1095,505 @@ mod tests {
         assert_eq!(app.list, expected_items);
     }
 
+    #[test]
+    fn it_sets_and_clears_an_override() {
+        let mut app = new_app();
+        let mut expected_items = items();
+
+        // Overrides can only be set on matches
+        app.list_state.set_selected_item(0);
+        app.input_override();
+        assert_eq!(app.ui_state, AppUiState::SelectMatches);
+
+        app.list_state.set_selected_item(1);
+        app.list_state.set_selected_submatch(1);
+        app.list[1].set_should_replace(1, false);
+        app.input_override();
+        assert_eq!(app.ui_state, AppUiState::InputOverride(Editor::default()));
+
+        // Accepting the override should also select the match
+        app.accept_input(String::from("override"));
+        assert_eq!(app.ui_state, AppUiState::SelectMatches);
+        expected_items[1].set_replacement(1, Some(String::from("override")));
+        assert_eq!(app.list, expected_items);
+
+        // Editing starts with the existing override
+        app.input_override();
+        assert_eq!(
+            app.ui_state,
+            AppUiState::InputOverride(Editor::new("override"))
+        );
+        app.ui_state = AppUiState::SelectMatches;
+
+        app.clear_override();
+        assert_eq!(app.list, items());
+    }
+
+    #[test]
+    fn it_sets_and_clears_the_replacement_for_a_pattern() {
+        let mut app = App::new(
+            "-e Item -e rg_msg".to_string(),
+            Matcher::new(&["Item", "rg_msg"], MatcherOptions::default()),
+            false,
+            ReplacementOutput::Files,
+            rg_messages(),
+        );
+        let patterns = |app: &App| {
+            app.list[1]
+                .sub_items()
+                .iter()
+                .map(|s| s.pattern)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(patterns(&app), vec![Some(0), Some(1)]);
+
+        app.list_state.set_selected_item(3);
+        app.list_state.set_selected_submatch(1);
+        app.input_pattern_replacement();
+        assert_eq!(
+            app.ui_state,
+            AppUiState::InputPatternReplacement(Editor::default())
+        );
+        app.accept_input(String::from("msg"));
+        assert_eq!(app.ui_state, AppUiState::SelectMatches);
+        assert_eq!(
+            app.pattern_texts,
+            PatternTexts::from([(String::from("rg_msg"), String::from("msg"))])
+        );
+
+        // Editing starts with the existing replacement, and it's used for the matches of that pattern.
+        app.input_pattern_replacement();
+        assert_eq!(
+            app.ui_state,
+            AppUiState::InputPatternReplacement(Editor::new("msg"))
+        );
+        app.ui_state = AppUiState::InputReplacement(Editor::new("foo"));
+        let term_size = Rect::new(0, 0, 80, 24);
+        let ctrl_s = Event::Key(KeyEvent::new(KeyCode::Char('s'), KeyModifiers::CONTROL));
+        let enter = Event::Key(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE));
+        app.on_event(term_size, ctrl_s).unwrap();
+        app.on_event(term_size, enter).unwrap();
+        let criteria = match &app.state {
+            AppState::Complete(criteria) => criteria,
+            _ => panic!("replacements weren't accepted"),
+        };
+        let item = &criteria.items[1];
+        let replacements = item
+            .sub_items()
+            .iter()
+            .map(|s| {
+                item.replacement_text(
+                    s,
+                    &criteria.matcher,
+                    &criteria.text,
+                    &criteria.pattern_texts,
+                    false,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(replacements, vec!["foo", "msg"]);
+
+        app.clear_pattern_replacement();
+        assert!(app.pattern_texts.is_empty());
+    }
+
+    #[test]
+    fn it_toggles_the_output() {
+        let mut app = new_app();
+        assert_eq!(app.output, ReplacementOutput::Files);
+        app.toggle_output();
+        assert_eq!(app.output, ReplacementOutput::Diff);
+        app.toggle_output();
+        assert_eq!(app.output, ReplacementOutput::Files);
+    }
+
+    #[test]
+    fn it_edits_the_replacement_at_the_cursor() {
+        let term_size = Rect::new(0, 0, 80, 24);
+        let key = |code, modifiers| Event::Key(KeyEvent::new(code, modifiers));
+        let mut app = new_app();
+        app.ui_state = AppUiState::InputReplacement(Editor::new("foo bar"));
+
+        for event in [
+            key(KeyCode::Left, KeyModifiers::CONTROL),
+            key(KeyCode::Char('w'), KeyModifiers::CONTROL),
+            key(KeyCode::Char('x'), KeyModifiers::NONE),
+            key(KeyCode::Enter, KeyModifiers::NONE),
+            key(KeyCode::End, KeyModifiers::NONE),
+            key(KeyCode::Up, KeyModifiers::NONE),
+            key(KeyCode::Home, KeyModifiers::NONE),
+            key(KeyCode::Delete, KeyModifiers::NONE),
+        ] {
+            app.on_event(term_size, event).unwrap();
+        }
+        assert_eq!(app.ui_state.get_replacement_text(), Some("\nbar"));
+
+        app.on_event(term_size, key(KeyCode::Down, KeyModifiers::NONE))
+            .unwrap();
+        app.on_event(term_size, key(KeyCode::Char('u'), KeyModifiers::CONTROL))
+            .unwrap();
+        app.on_event(term_size, key(KeyCode::Backspace, KeyModifiers::NONE))
+            .unwrap();
+        assert_eq!(app.ui_state.get_replacement_text(), Some("bar"));
+    }
+
+    #[test]
+    fn it_recalls_previous_replacements() {
+        let term_size = Rect::new(0, 0, 80, 24);
+        let key = |code, modifiers| Event::Key(KeyEvent::new(code, modifiers));
+        let up = key(KeyCode::Up, KeyModifiers::NONE);
+        let down = key(KeyCode::Down, KeyModifiers::NONE);
+        let ctrl_r = key(KeyCode::Char('r'), KeyModifiers::CONTROL);
+        let mut app = new_app();
+        for replacement in ["foo", "bar", "foo baz", "qux"] {
+            app.ui_state = AppUiState::InputPatternReplacement(Editor::new(replacement));
+            app.on_event(term_size, key(KeyCode::Char('s'), KeyModifiers::CONTROL))
+                .unwrap();
+        }
+        assert_eq!(app.history.replacements(), ["foo", "bar", "foo baz", "qux"]);
+
+        // Entries which are the same as the input are skipped, and going past the newest restores the input.
+        app.ui_state = AppUiState::InputReplacement(Editor::new("qux"));
+        app.on_event(term_size, up.clone()).unwrap();
+        assert_eq!(app.ui_state.get_replacement_text(), Some("foo baz"));
+        app.on_event(term_size, up.clone()).unwrap();
+        app.on_event(term_size, up.clone()).unwrap();
+        app.on_event(term_size, up.clone()).unwrap();
+        assert_eq!(app.ui_state.get_replacement_text(), Some("foo"));
+        app.on_event(term_size, down.clone()).unwrap();
+        assert_eq!(app.ui_state.get_replacement_text(), Some("bar"));
+        app.on_event(term_size, down.clone()).unwrap();
+        app.on_event(term_size, down.clone()).unwrap();
+        app.on_event(term_size, down.clone()).unwrap();
+        assert_eq!(app.ui_state.get_replacement_text(), Some("qux"));
+
+        // Searching recalls older entries containing the text that was entered.
+        app.ui_state = AppUiState::InputReplacement(Editor::new("fo"));
+        app.on_event(term_size, ctrl_r.clone()).unwrap();
+        assert_eq!(app.ui_state.get_replacement_text(), Some("foo baz"));
+        app.on_event(term_size, ctrl_r.clone()).unwrap();
+        assert_eq!(app.ui_state.get_replacement_text(), Some("foo"));
+        app.on_event(term_size, ctrl_r.clone()).unwrap();
+        assert_eq!(app.ui_state.get_replacement_text(), Some("foo"));
+        app.on_event(term_size, down).unwrap();
+        assert_eq!(app.replacement_history_query, None);
+        assert_eq!(app.ui_state.get_replacement_text(), Some("bar"));
+    }
+
+    #[test]
+    fn it_shows_and_resizes_the_panes() {
+        let term_size = Rect::new(0, 0, 80, 24);
+        let key = |code, modifiers| Event::Key(KeyEvent::new(code, modifiers));
+        let mut app = new_app();
+        assert_eq!(app.detail_pane, None);
+
+        app.on_event(term_size, key(KeyCode::Char('c'), KeyModifiers::NONE))
+            .unwrap();
+        assert_eq!(app.detail_pane, Some(DetailPane::Preview));
+        app.on_event(term_size, key(KeyCode::Char('t'), KeyModifiers::CONTROL))
+            .unwrap();
+        assert_eq!(app.detail_pane, Some(DetailPane::Split));
+        app.on_event(term_size, key(KeyCode::Char('t'), KeyModifiers::CONTROL))
+            .unwrap();
+        assert_eq!(app.detail_pane, None);
+
+        // Changing the context shows the preview.
+        app.on_event(term_size, key(KeyCode::Char('>'), KeyModifiers::NONE))
+            .unwrap();
+        assert_eq!(app.detail_pane, Some(DetailPane::Preview));
+        assert_eq!(app.preview_context, 10);
+        for _ in 0..3 {
+            app.on_event(term_size, key(KeyCode::Char('<'), KeyModifiers::NONE))
+                .unwrap();
+        }
+        assert_eq!(app.detail_pane, Some(DetailPane::Preview));
+        assert_eq!(app.preview_context, 0);
+    }
+
+    #[test]
+    fn it_cycles_the_operation() {
+        let term_size = Rect::new(0, 0, 80, 24);
+        let ctrl_o = Event::Key(KeyEvent::new(KeyCode::Char('o'), KeyModifiers::CONTROL));
+        let mut app = new_app();
+        app.ui_state = AppUiState::InputReplacement(Editor::new("foo"));
+
+        app.on_event(term_size, ctrl_o.clone()).unwrap();
+        assert_eq!(app.operation, Operation::DeleteLine);
+        assert_eq!(
+            app.ui_state,
+            AppUiState::InputReplacement(Editor::new("foo"))
+        );
+        app.on_event(term_size, ctrl_o.clone()).unwrap();
+        app.on_event(term_size, ctrl_o.clone()).unwrap();
+        app.on_event(term_size, ctrl_o.clone()).unwrap();
+        assert_eq!(app.operation, Operation::Substitute);
+
+        // Only the paths of files can be substituted when renaming them.
+        app.set_rename(true);
+        app.on_event(term_size, ctrl_o).unwrap();
+        assert_eq!(app.operation, Operation::Substitute);
+    }
+
+    // Searching
+
+    #[test]
+    fn it_adds_results_as_they_arrive() {
+        let term_size = Rect::new(0, 0, 80, 24);
+        let mut app = App::new(
+            "TESTS".to_string(),
+            Matcher::empty(),
+            false,
+            ReplacementOutput::Files,
+            vec![],
+        );
+        assert_eq!(app.search_state(), SearchState::Searching);
+
+        // Nothing can be selected before any results arrive.
+        let key = |code| Event::Key(KeyEvent::new(code, KeyModifiers::NONE));
+        app.on_event(term_size, key(KeyCode::Char(' '))).unwrap();
+        app.on_event(term_size, key(KeyCode::Down)).unwrap();
+
+        let mut rg_messages = rg_messages().into_iter();
+        for rg_message in rg_messages.by_ref().take(3) {
+            app.add_rg_message(rg_message);
+        }
+        assert_eq!(app.list.len(), 3);
+        assert_eq!(app.stats, None);
+        app.on_event(term_size, key(KeyCode::Down)).unwrap();
+        assert_eq!(app.list_state.selected_item(), 1);
+
+        for rg_message in rg_messages {
+            app.add_rg_message(rg_message);
+        }
+        assert_eq!(app.list, items());
+        assert_eq!(app.search_state(), SearchState::Complete);
+    }
+
+    #[test]
+    fn it_cancels_the_search() {
+        let term_size = Rect::new(0, 0, 80, 24);
+        let mut app = App::new(
+            "TESTS".to_string(),
+            Matcher::empty(),
+            false,
+            ReplacementOutput::Files,
+            vec![],
+        );
+
+        let ctrl_c = Event::Key(KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL));
+        app.on_event(term_size, ctrl_c).unwrap();
+        assert_eq!(app.search_state(), SearchState::Cancelled);
+
+        app.on_search_finished();
+        assert_eq!(app.search_state(), SearchState::Cancelled);
+        assert!(matches!(app.state, AppState::Running));
+    }
+
+    #[test]
+    fn it_searches_again_keeping_selections() {
+        let term_size = Rect::new(0, 0, 80, 24);
+        let key = |code| Event::Key(KeyEvent::new(code, KeyModifiers::NONE));
+        let mut app = new_app();
+
+        app.on_event(term_size, key(KeyCode::Char('a'))).unwrap();
+        assert_eq!(app_list_to_match_replace(&app), vec![false, false]);
+
+        // Add a flag to the search.
+        app.on_event(term_size, key(KeyCode::Char('e'))).unwrap();
+        assert_eq!(app.ui_state, AppUiState::EditSearch("TESTS".to_string()));
+        for ch in " -w".chars() {
+            app.on_event(term_size, key(KeyCode::Char(ch))).unwrap();
+        }
+        app.on_event(term_size, key(KeyCode::Enter)).unwrap();
+        assert_eq!(app.ui_state, AppUiState::SelectMatches);
+        assert_eq!(app.search_state(), SearchState::Searching);
+        assert_eq!(app.rg_cmdline, "TESTS -w");
+        assert!(app.list.is_empty());
+
+        let args = app.take_search_request().unwrap();
+        assert!(args.word_regexp);
+        assert_eq!(app.take_search_request().map(|_| ()), None);
+
+        // The matches which are found again are still deselected.
+        for rg_message in rg_messages() {
+            app.add_rg_message(rg_message);
+        }
+        assert_eq!(app_list_to_match_replace(&app), vec![false, false]);
+        assert_eq!(app.search_history, vec!["TESTS", "TESTS -w"]);
+    }
+
+    #[test]
+    fn it_keeps_renaming_when_searching_again() {
+        let mut app = new_app();
+        app.set_rename(true);
+
+        app.search("TESTS -w");
+        assert!(app.take_search_request().unwrap().rename);
+    }
+
+    #[test]
+    fn it_recalls_searches_from_earlier_runs() {
+        let mut history = History::new(10);
+        history.add_search("TESTS");
+        history.add_search("TESTS -i");
+        let mut app = new_app();
+        app.set_history(history);
+        assert_eq!(app.search_history, vec!["TESTS -i", "TESTS"]);
+
+        app.search("TESTS -w");
+        assert_eq!(app.history.searches(), ["TESTS -i", "TESTS", "TESTS -w"]);
+    }
+
+    #[test]
+    fn it_recalls_previous_searches() {
+        let term_size = Rect::new(0, 0, 80, 24);
+        let key = |code| Event::Key(KeyEvent::new(code, KeyModifiers::NONE));
+        let mut app = new_app();
+        let search = |app: &mut App, text: &str| {
+            app.on_event(term_size, key(KeyCode::Char('e'))).unwrap();
+            for ch in text.chars() {
+                app.on_event(term_size, key(KeyCode::Char(ch))).unwrap();
+            }
+            app.on_event(term_size, key(KeyCode::Enter)).unwrap();
+        };
+        let edited = |app: &App| match &app.ui_state {
+            AppUiState::EditSearch(input) => input.to_owned(),
+            _ => panic!("not editing the search"),
+        };
+
+        search(&mut app, " -i");
+        assert!(app.take_search_request().is_some());
+
+        // Searches the same as the input are skipped.
+        app.on_event(term_size, key(KeyCode::Char('e'))).unwrap();
+        assert_eq!(edited(&app), "TESTS -i");
+        app.on_event(term_size, key(KeyCode::Up)).unwrap();
+        assert_eq!(edited(&app), "TESTS");
+        app.on_event(term_size, key(KeyCode::Up)).unwrap();
+        assert_eq!(edited(&app), "TESTS");
+        app.on_event(term_size, key(KeyCode::Down)).unwrap();
+        assert_eq!(edited(&app), "TESTS -i");
+        app.on_event(term_size, key(KeyCode::Down)).unwrap();
+        assert_eq!(edited(&app), "TESTS -i");
+
+        // Invalid searches are reported, and not searched.
+        app.on_event(term_size, key(KeyCode::Esc)).unwrap();
+        search(&mut app, " --not-a-flag");
+        assert_eq!(edited(&app), "TESTS -i --not-a-flag");
+        assert!(app.error.is_some());
+        assert!(app.take_search_request().is_none());
+        assert_eq!(app.search_history, vec!["TESTS", "TESTS -i"]);
+    }
+
+    #[test]
+    fn it_filters_the_list() {
+        let term_size = Rect::new(0, 0, 80, 24);
+        let key = |code| Event::Key(KeyEvent::new(code, KeyModifiers::NONE));
+        let mut app = new_app_multiple_files();
+
+        for code in [KeyCode::Char('/'), KeyCode::Char('b'), KeyCode::Char('a')] {
+            app.on_event(term_size, key(code)).unwrap();
+        }
+        app.on_event(term_size, key(KeyCode::Char('z'))).unwrap();
+        assert_eq!(app.ui_state, AppUiState::InputFilter("baz".to_string()));
+        let visible = app
+            .list
+            .iter()
+            .enumerate()
+            .filter(|(_, i)| !i.is_hidden())
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+        assert_eq!(visible, vec![6, 7, 8, 15, 16, 17]);
+
+        // The selection moves to the first visible item, and movement skips hidden items.
+        assert_eq!(app.list_state.selected_item(), 6);
+        app.on_event(term_size, key(KeyCode::Enter)).unwrap();
+        assert_eq!(app.ui_state, AppUiState::SelectMatches);
+        app.on_event(term_size, key(KeyCode::Down)).unwrap();
+        assert_eq!(app.list_state.selected_item(), 7);
+        app.on_event(term_size, key(KeyCode::Down)).unwrap();
+        assert_eq!(app.list_state.selected_item(), 15);
+        app.on_event(term_size, key(KeyCode::Up)).unwrap();
+        assert_eq!(app.list_state.selected_item(), 7);
+
+        // Toggling only affects the visible matches.
+        app.on_event(term_size, key(KeyCode::Char('a'))).unwrap();
+        assert_eq!(
+            app_list_to_match_replace(&app),
+            vec![true, true, false, true, true, false]
+        );
+        app.on_event(term_size, key(KeyCode::Char('V'))).unwrap();
+        assert_eq!(
+            app_list_to_match_replace(&app),
+            vec![true, true, true, true, true, true]
+        );
+
+        // Escape removes the filter, rather than quitting.
+        app.on_event(term_size, key(KeyCode::Esc)).unwrap();
+        assert!(matches!(app.state, AppState::Running));
+        assert!(app.list.iter().all(|i| !i.is_hidden()));
+        assert_eq!(app.list_state.selected_item(), 7);
+    }
+
+    #[test]
+    fn it_filters_results_as_they_arrive() {
+        let mut app = App::new(
+            "TESTS".to_string(),
+            Matcher::empty(),
+            false,
+            ReplacementOutput::Files,
+            vec![],
+        );
+        app.set_filter(Some(Filter::new("rg_msg")));
+        for rg_message in rg_messages() {
+            app.add_rg_message(rg_message);
+        }
+
+        let hidden = app.list.iter().map(|i| i.is_hidden()).collect::<Vec<_>>();
+        assert_eq!(hidden, vec![false, false, true, false, true, false]);
+    }
+
+    #[test]
+    fn it_selects_matches_by_predicate() {
+        let term_size = Rect::new(0, 0, 80, 24);
+        let key = |code| Event::Key(KeyEvent::new(code, KeyModifiers::NONE));
+        let mut app = new_app_multiple_files();
+        let enter = |app: &mut App, text: &str| {
+            for ch in text.chars() {
+                app.on_event(term_size, key(KeyCode::Char(ch))).unwrap();
+            }
+            app.on_event(term_size, key(KeyCode::Enter)).unwrap();
+        };
+
+        enter(&mut app, "-line:rg_msg");
+        assert_eq!(app.ui_state, AppUiState::SelectMatches);
+        assert_eq!(app.notice.as_deref(), Some("Deselected 8 match(es)"));
+        assert_eq!(
+            app_list_to_match_replace(&app),
+            vec![false, false, true, false, false, true]
+        );
+
+        enter(&mut app, "+Item");
+        assert_eq!(app.notice.as_deref(), Some("Selected 4 match(es)"));
+        let selected = app
+            .list
+            .iter()
+            .flat_map(|i| i.sub_items().iter().map(|s| s.should_replace))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            selected,
+            vec![true, false, true, false, true, true, true, false, true, false, true, true]
+        );
+
+        // Invalid predicates are reported.
+        enter(&mut app, "-line:(");
+        assert_eq!(
+            app.ui_state,
+            AppUiState::InputPredicate(false, "line:(".to_string())
+        );
+        assert!(app.error.is_some());
+    }
+
     // Inverting selection
 
     #[test]
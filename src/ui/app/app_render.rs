@@ -7,15 +7,20 @@ use tui::style::{Color, Modifier, Style};
 use tui::text::{Span, Spans, Text};
 use tui::widgets::{Block, Borders, List, ListItem, Paragraph, Row, Table, Wrap};
 use tui::Frame;
+use unicode_width::UnicodeWidthStr;
 
-use crate::model::Printable;
+use crate::model::{Editor, FilePreview, Printable, ReplacementOutput};
 use crate::rg::de::RgMessageKind;
-use crate::ui::app::{App, AppUiState};
+use crate::rg::RgEncoding;
+use crate::ui::app::{App, AppUiState, DetailPane, SearchState};
+use crate::ui::line::{number_rows, to_rows, Item};
 use crate::ui::render::UiItemContext;
 
 const LIST_HIGHLIGHT_SYMBOL: &str = "-> ";
 const MINIMUM_WIDTH: u16 = 70;
 const MINIMUM_HEIGHT: u16 = 20;
+/// The most lines of replacement text which are shown at once while it's being edited.
+const MAXIMUM_INPUT_HEIGHT: u16 = 8;
 const TOO_SMALL_MESSAGE: &str = formatcp!(
     "Terminal window is too small!
 Minimum dimensions are: {}x{}.
@@ -24,6 +29,16 @@ Resize your terminal window or press 'esc' or 'q' to quit.",
     MINIMUM_HEIGHT
 );
 
+/// Joins the lines of an error message, so it fits on the input line.
+fn one_line(error: &str) -> String {
+    error
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
 impl App {
     // The UI is:
     // _
@@ -52,17 +67,35 @@ impl App {
     fn get_layouts(&self, r: Rect) -> (Vec<Rect>, Vec<Rect>) {
         let root_split = Layout::default()
             .direction(Direction::Vertical)
-            .constraints([Constraint::Min(1), Constraint::Length(2)].as_ref())
+            .constraints(
+                [
+                    Constraint::Min(1),
+                    Constraint::Length(1 + self.input_height()),
+                ]
+                .as_ref(),
+            )
             .split(r);
 
         let stats_and_input_split = Layout::default()
             .direction(Direction::Vertical)
-            .constraints([Constraint::Length(1), Constraint::Length(1)].as_ref())
+            .constraints([Constraint::Length(1), Constraint::Min(1)].as_ref())
             .split(root_split[1]);
 
         (root_split, stats_and_input_split)
     }
 
+    /// How many lines the input line needs, which grows with the replacement text while it's being edited.
+    fn input_height(&self) -> u16 {
+        match &self.ui_state {
+            AppUiState::InputReplacement(editor)
+            | AppUiState::InputOverride(editor)
+            | AppUiState::InputPatternReplacement(editor) => {
+                (editor.line_count() as u16).clamp(1, MAXIMUM_INPUT_HEIGHT)
+            }
+            _ => 1,
+        }
+    }
+
     pub(crate) fn is_frame_too_small(&self, frame: Rect) -> bool {
         frame.width < MINIMUM_WIDTH || frame.height < MINIMUM_HEIGHT
     }
@@ -73,40 +106,93 @@ impl App {
     }
 
     fn draw_input_line<B: Backend>(&mut self, f: &mut Frame<B>, r: Rect) {
-        let prefix = "Replacement: ";
+        let prefix = match &self.ui_state {
+            AppUiState::InputOverride(_) => String::from("Replacement (this match only): "),
+            AppUiState::InputPatternReplacement(_) => format!(
+                "Replacement (matches of {}): ",
+                self.selected_pattern()
+                    .unwrap_or_default()
+                    .to_printable(self.printable_style.as_one_line())
+            ),
+            AppUiState::EditSearch(_) => String::from("Search: "),
+            AppUiState::InputFilter(_) => String::from("Filter: "),
+            AppUiState::InputPredicate(true, _) => String::from("Select matches: "),
+            AppUiState::InputPredicate(false, _) => String::from("Deselect matches: "),
+            _ => String::from("Replacement: "),
+        };
+        if let AppUiState::InputReplacement(editor)
+        | AppUiState::InputOverride(editor)
+        | AppUiState::InputPatternReplacement(editor) = &self.ui_state
+        {
+            return self.draw_editor(f, r, &prefix, editor);
+        }
+
+        let error_style = Style::default().fg(Color::Red);
         let mut spans = match &self.ui_state {
             AppUiState::Help => vec![Span::from("Viewing Help. Press <esc> or <q> to return...")],
-            AppUiState::SelectMatches => vec![Span::from(
-                "Select (or deselect) Matches with <space> then press <Enter>. Press <?> for help.",
-            )],
-            AppUiState::InputReplacement(input) => vec![
-                Span::from(prefix),
+            AppUiState::SelectMatches => match (&self.error, &self.notice) {
+                (Some(error), _) => vec![Span::styled(one_line(error), error_style)],
+                (None, Some(notice)) => {
+                    vec![Span::styled(notice, Style::default().fg(Color::Green))]
+                }
+                (None, None) => vec![Span::from(
+                    "Select (or deselect) Matches with <space> then press <Enter>. Press <?> for help.",
+                )],
+            },
+            AppUiState::EditSearch(input)
+            | AppUiState::InputFilter(input)
+            | AppUiState::InputPredicate(_, input) => vec![
+                Span::from(prefix.as_str()),
                 if input.is_empty() {
                     Span::styled("<empty>", Style::default().fg(Color::DarkGray))
                 } else {
                     Span::from(input.to_printable(self.printable_style.as_one_line()))
                 },
             ],
-            AppUiState::ConfirmReplacement(_) => vec![Span::from(
-                "Press <enter> to write changes, <esc> to cancel.",
-            )],
+            AppUiState::InputReplacement(_)
+            | AppUiState::InputOverride(_)
+            | AppUiState::InputPatternReplacement(_) => unreachable!("drawn by draw_editor"),
+            AppUiState::ConfirmReplacement(_) => vec![Span::from(match self.output {
+                ReplacementOutput::Files => {
+                    "Press <enter> to write changes, <d> to write a diff instead, <esc> to cancel."
+                }
+                ReplacementOutput::Diff => {
+                    "Press <enter> to write a diff of the changes, <d> to write changes instead, <esc> to cancel."
+                }
+            })],
         };
 
         let mut render_input = |spans| f.render_widget(Paragraph::new(Spans::from(spans)), r);
 
         // Draw input cursor after rendering input
-        if let AppUiState::InputReplacement(input) = &self.ui_state {
-            let x_start = r.x + (prefix.len() as u16);
+        if let AppUiState::EditSearch(input)
+        | AppUiState::InputFilter(input)
+        | AppUiState::InputPredicate(_, input) = &self.ui_state
+        {
+            let x_start = r.x + (prefix.width() as u16);
             let x_pos = if input.is_empty() {
                 0
             } else {
                 spans.last().map(|span| span.width()).unwrap() as u16
             };
 
-            spans.push(Span::styled(
-                "    (press <control+s> to accept replacement)",
-                Style::default().fg(Color::DarkGray),
-            ));
+            spans.push(match (&self.ui_state, &self.error) {
+                (AppUiState::EditSearch(_) | AppUiState::InputPredicate(..), Some(error)) => {
+                    Span::styled(format!("    {}", one_line(error)), error_style)
+                }
+                (AppUiState::EditSearch(_), None) => Span::styled(
+                    "    (press <enter> to search, <up>/<down> for previous searches)",
+                    Style::default().fg(Color::DarkGray),
+                ),
+                (AppUiState::InputPredicate(..), None) => Span::styled(
+                    "    (glob:GLOB, path:PATH, line:REGEX or REGEX, then press <enter>)",
+                    Style::default().fg(Color::DarkGray),
+                ),
+                _ => Span::styled(
+                    "    (press <enter> to accept filter, <esc> to clear it)",
+                    Style::default().fg(Color::DarkGray),
+                ),
+            });
 
             render_input(spans);
             f.set_cursor(x_start + x_pos, r.y);
@@ -115,6 +201,52 @@ impl App {
         }
     }
 
+    /// Draws the replacement text being edited over as many lines as it has (scrolling to keep the cursor's line
+    /// visible), with the cursor where it's being edited.
+    fn draw_editor<B: Backend>(&self, f: &mut Frame<B>, r: Rect, prefix: &str, editor: &Editor) {
+        let style = self.printable_style.as_one_line();
+        let hint_style = Style::default().fg(Color::DarkGray);
+        let (cursor_line, before_cursor) = editor.cursor_line();
+        let first_line = (cursor_line + 1).saturating_sub(r.height as usize);
+
+        let lines = editor
+            .text()
+            .split('\n')
+            .enumerate()
+            .skip(first_line)
+            .take(r.height as usize)
+            .map(|(i, line)| {
+                // Every line after the first is lined up with the first.
+                let mut spans = vec![if i == 0 {
+                    Span::from(prefix)
+                } else {
+                    Span::from(" ".repeat(prefix.width()))
+                }];
+                if editor.is_empty() {
+                    spans.push(Span::styled("<empty>", hint_style));
+                } else {
+                    spans.push(Span::from(line.to_printable(style)));
+                }
+                if i == 0 {
+                    let hint = match &self.replacement_history_query {
+                        Some(query) => format!(
+                            "    (history containing {:?}, press <control+r> for older)",
+                            query
+                        ),
+                        None => "    (press <control+s> to accept replacement)".to_owned(),
+                    };
+                    spans.push(Span::styled(hint, hint_style));
+                }
+
+                Spans::from(spans)
+            })
+            .collect::<Vec<_>>();
+        f.render_widget(Paragraph::new(lines), r);
+
+        let x_pos = prefix.width() + before_cursor.to_printable(style).width();
+        f.set_cursor(r.x + x_pos as u16, r.y + (cursor_line - first_line) as u16);
+    }
+
     fn draw_stats_line<B: Backend>(&mut self, f: &mut Frame<B>, r: Rect) {
         let replacement_count = self
             .list
@@ -127,6 +259,19 @@ impl App {
                 }
             })
             .sum::<usize>();
+        let match_count = match &self.stats {
+            Some(stats) => stats.matches,
+            // Until the search is complete, count the matches found so far.
+            None => self.list.iter().map(|i| i.sub_items().len()).sum::<usize>(),
+        };
+
+        let filtered_count = self.filter.as_ref().map(|_| {
+            self.list
+                .iter()
+                .filter(|i| !i.is_hidden())
+                .map(|i| i.sub_items().len())
+                .sum::<usize>()
+        });
 
         // Split the stats line into halves, so we can render left and right aligned portions.
         let hsplit = Layout::default()
@@ -136,20 +281,72 @@ impl App {
             .split(r);
 
         let left_side_items = vec![Spans::from(self.ui_state.to_span())];
-        let right_side_items = vec![Spans::from(vec![
-            Span::styled(
-                format!(" {} ", self.rg_cmdline),
-                Style::default().bg(Color::Blue).fg(Color::Black),
-            ),
+        let mut right_side_items = vec![Span::styled(
+            format!(" {} ", self.rg_cmdline),
+            Style::default().bg(Color::Blue).fg(Color::Black),
+        )];
+        if let Some(config_path) = &self.config_path {
+            right_side_items.push(Span::styled(
+                format!(" Config: {} ", config_path.display()),
+                Style::default().bg(Color::LightGreen).fg(Color::Black),
+            ));
+        }
+        match self.search_state {
+            SearchState::Searching => right_side_items.push(Span::styled(
+                " Searching… ",
+                Style::default().bg(Color::Yellow).fg(Color::Black),
+            )),
+            SearchState::Cancelled => right_side_items.push(Span::styled(
+                " Search cancelled ",
+                Style::default().bg(Color::Red).fg(Color::Black),
+            )),
+            SearchState::Failed => right_side_items.push(Span::styled(
+                " Search failed ",
+                Style::default().bg(Color::Red).fg(Color::Black),
+            )),
+            SearchState::Complete => {}
+        }
+        if let Some(filtered_count) = filtered_count {
+            right_side_items.push(Span::styled(
+                format!(" Filtered: {}/{} ", filtered_count, match_count),
+                Style::default().bg(Color::LightBlue).fg(Color::Black),
+            ));
+        }
+        if self.preserve_case {
+            right_side_items.push(Span::styled(
+                " PreserveCase ",
+                Style::default().bg(Color::Yellow).fg(Color::Black),
+            ));
+        }
+        if self.operation.is_line_operation() {
+            right_side_items.push(Span::styled(
+                format!(" {} ", self.operation),
+                Style::default().bg(Color::LightRed).fg(Color::Black),
+            ));
+        }
+        if self.rename {
+            right_side_items.push(Span::styled(
+                " Rename ",
+                Style::default().bg(Color::LightMagenta).fg(Color::Black),
+            ));
+        }
+        if self.output == ReplacementOutput::Diff {
+            right_side_items.push(Span::styled(
+                " Diff ",
+                Style::default().bg(Color::Green).fg(Color::Black),
+            ));
+        }
+        right_side_items.extend(vec![
             Span::styled(
                 format!(" CtrlChars: {} ", self.printable_style),
                 Style::default().bg(Color::Cyan).fg(Color::Black),
             ),
             Span::styled(
-                format!(" {}/{} ", replacement_count, self.stats.matches),
+                format!(" {}/{} ", replacement_count, match_count),
                 Style::default().bg(Color::Magenta).fg(Color::Black),
             ),
-        ])];
+        ]);
+        let right_side_items = vec![Spans::from(right_side_items)];
 
         let stats_line_style = Style::default().bg(Color::DarkGray).fg(Color::White);
         f.render_widget(
@@ -173,38 +370,73 @@ impl App {
             .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
             .split(r);
 
-        let help_table = Table::new(
-            vec![
-                Row::new(vec!["MODE: ALL"]).style(title_style),
-                Row::new(vec!["control + b", "move backward one page"]),
-                Row::new(vec!["control + f", "move forward one page"]),
-                Row::new(vec![
-                    "control + v",
-                    "toggle how control characters are rendered",
-                ])
-                .bottom_margin(1),
-                Row::new(vec!["MODE: SELECT"]).style(title_style),
-                Row::new(vec!["k, up", "move to previous match"]),
-                Row::new(vec!["j, down", "move to next match"]),
-                Row::new(vec!["K, shift + up", "move to previous file"]),
-                Row::new(vec!["J, shift + down", "move to next file"]),
-                Row::new(vec!["space", "toggle selection"]),
-                Row::new(vec!["a, A", "toggle selection for all matches"]),
-                Row::new(vec!["s, S", "toggle selection for whole line"]),
-                Row::new(vec!["v", "invert section for the current item"]),
-                Row::new(vec!["V", "invert section for all items"]),
-                Row::new(vec!["enter, r, R", "accept selection"]),
-                Row::new(vec!["q, esc", "quit"]),
-                Row::new(vec!["?", "show help and keybindings"]).bottom_margin(1),
-                Row::new(vec!["MODE: REPLACE"]).style(title_style),
-                Row::new(vec!["control + s", "accept replacement text"]),
-                Row::new(vec!["esc", "previous mode"]).bottom_margin(1),
-                Row::new(vec!["MODE: CONFIRM"]).style(title_style),
-                Row::new(vec!["enter", "write replacements to disk"]),
-                Row::new(vec!["q, esc", "previous mode"]),
-            ]
-            .into_iter(),
-        )
+        let help_table = Table::new(vec![
+            Row::new(vec!["MODE: ALL"]).style(title_style),
+            Row::new(vec!["control + b", "move backward one page"]),
+            Row::new(vec!["control + f", "move forward one page"]),
+            Row::new(vec![
+                "control + v",
+                "toggle how control characters are rendered",
+            ]),
+            Row::new(vec![
+                "control + t",
+                "toggle the side-by-side view of the file",
+            ]),
+            Row::new(vec!["control + c", "stop searching"]).bottom_margin(1),
+            Row::new(vec!["MODE: SELECT"]).style(title_style),
+            Row::new(vec!["k, up", "move to previous match"]),
+            Row::new(vec!["j, down", "move to next match"]),
+            Row::new(vec!["K, shift + up", "move to previous file"]),
+            Row::new(vec!["J, shift + down", "move to next file"]),
+            Row::new(vec!["space", "toggle selection"]),
+            Row::new(vec!["a, A", "toggle selection for all matches"]),
+            Row::new(vec!["s, S", "toggle selection for whole line"]),
+            Row::new(vec!["v", "invert section for the current item"]),
+            Row::new(vec!["V", "invert section for all items"]),
+            Row::new(vec!["enter, r, R", "accept selection"]),
+            Row::new(vec!["q, esc", "quit"]),
+            Row::new(vec!["o", "enter replacement for the current match only"]),
+            Row::new(vec!["O", "clear replacement for the current match only"]),
+            Row::new(vec!["e", "edit the search pattern and flags"]),
+            Row::new(vec!["c", "toggle a preview of the lines around the match"]),
+            Row::new(vec!["<, >", "show less or more of the lines around it"]),
+            Row::new(vec!["/", "filter the matches by path or line"]),
+            Row::new(vec!["esc", "clear the filter"]),
+            Row::new(vec!["+", "select all matches which match a predicate"]),
+            Row::new(vec!["-", "deselect all matches which match a predicate"]),
+            Row::new(vec!["?", "show help and keybindings"]).bottom_margin(1),
+            Row::new(vec!["MODE: REPLACE"]).style(title_style),
+            Row::new(vec!["control + s", "accept replacement text"]),
+            Row::new(vec!["left, right", "move the cursor"]),
+            Row::new(vec!["control + left/right", "move the cursor by words"]),
+            Row::new(vec!["home, end", "move to the start or end of the line"]),
+            Row::new(vec!["up, down", "previous or next line (or replacement)"]),
+            Row::new(vec!["control + r", "search previous replacements"]),
+            Row::new(vec!["enter", "start a new line"]),
+            Row::new(vec!["control + w", "delete the word before the cursor"]),
+            Row::new(vec!["control + u", "delete the line before the cursor"]),
+            Row::new(vec!["esc", "previous mode"]).bottom_margin(1),
+            Row::new(vec!["MODE: OVERRIDE"]).style(title_style),
+            Row::new(vec![
+                "control + s",
+                "accept replacement for the current match",
+            ]),
+            Row::new(vec!["esc", "previous mode"]).bottom_margin(1),
+            Row::new(vec!["MODE: SEARCH"]).style(title_style),
+            Row::new(vec!["enter", "search again"]),
+            Row::new(vec!["up, down", "previous and next searches"]),
+            Row::new(vec!["esc", "previous mode"]).bottom_margin(1),
+            Row::new(vec!["MODE: FILTER"]).style(title_style),
+            Row::new(vec!["enter", "accept filter"]),
+            Row::new(vec!["esc", "clear filter and return"]).bottom_margin(1),
+            Row::new(vec!["MODE: INCLUDE, EXCLUDE"]).style(title_style),
+            Row::new(vec!["enter", "select or deselect the matches"]),
+            Row::new(vec!["esc", "previous mode"]).bottom_margin(1),
+            Row::new(vec!["MODE: CONFIRM"]).style(title_style),
+            Row::new(vec!["enter", "write replacements to disk"]),
+            Row::new(vec!["d", "toggle writing a diff instead"]),
+            Row::new(vec!["q, esc", "previous mode"]),
+        ])
         .header(
             Row::new(vec!["[Key]", "[Action]"])
                 .style(
@@ -246,8 +478,25 @@ impl App {
         Span::from(self.list_indicator().as_str()).width() as u16
     }
 
+    /// Splits the main view between the list and the pane below it, if one is shown.
+    fn split_main_view(&self, r: Rect) -> (Rect, Option<Rect>) {
+        if self.detail_pane.is_none() {
+            return (r, None);
+        }
+
+        let vsplit = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
+            .split(r);
+        (vsplit[0], Some(vsplit[1]))
+    }
+
     fn draw_main_view<B: Backend>(&mut self, f: &mut Frame<B>, r: Rect) {
+        let (r, pane_rect) = self.split_main_view(r);
         let list_rect = self.main_view_list_rect(f.size());
+        if self.detail_pane == Some(DetailPane::Preview) {
+            self.read_preview_file();
+        }
         let indicator_symbol = self.list_indicator();
 
         // For performance with large match sets, we only send a single "window"'s
@@ -261,23 +510,39 @@ impl App {
 
         let ctx = &UiItemContext {
             replacement_text: self.ui_state.get_replacement_text(),
+            pattern_texts: &self.pattern_texts,
+            matcher: &self.matcher,
+            preserve_case: self.preserve_case,
+            operation: self.operation,
             printable_style: self.printable_style,
             app_list_state: &self.list_state,
             app_ui_state: &self.ui_state,
             list_rect,
         };
 
+        match (self.detail_pane, pane_rect) {
+            (Some(DetailPane::Split), Some(r)) => self.draw_split_view(f, r, ctx),
+            (Some(DetailPane::Preview), Some(r)) => self.draw_preview_view(f, r, ctx),
+            _ => {}
+        }
+
         // iterate over all our items and collect only those that will be in the visible
         // window region of the list (skipping all the others)
         let mut match_items = vec![];
         let mut curr_height = 0;
-        for item in self.list.iter_mut() {
+        for item in self.list.iter_mut().filter(|item| !item.is_hidden()) {
             // we've passed the visible region
             if curr_height > window_end {
                 break;
             }
 
             let line_count = item.line_count(list_rect.width, self.printable_style);
+            // NOTE: only the items which are drawn are highlighted, since it can be slow.
+            if curr_height + line_count > window_start {
+                if let Some(highlighter) = &self.highlighter {
+                    item.highlight(highlighter);
+                }
+            }
 
             // items that fall in the visible window, but don't start in the visible window
             if curr_height < window_start {
@@ -308,7 +573,214 @@ impl App {
             .style(Style::default().fg(Color::White))
             .highlight_symbol(&indicator_symbol);
 
-        f.render_stateful_widget(match_list, r, &mut self.list_state.indicator_mut());
+        f.render_stateful_widget(match_list, r, self.list_state.indicator_mut());
+    }
+
+    /// Draws the lines of the selected file as they are before the replacements on the left, and after them on the
+    /// right. The lines are scrolled so the selected item is level with it in the list, where there's room.
+    fn draw_split_view<B: Backend>(&self, f: &mut Frame<B>, r: Rect, ctx: &UiItemContext) {
+        let selected = self.list_state.selected_item();
+        let file_start = match self.list.get(..=selected).and_then(|items| {
+            items
+                .iter()
+                .rposition(|item| item.kind == RgMessageKind::Begin)
+        }) {
+            Some(file_start) => file_start,
+            None => return,
+        };
+
+        let hsplit = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
+            .split(r);
+        let path = self.list[file_start]
+            .path()
+            .map(|path| {
+                path.lossy_utf8()
+                    .to_printable(self.printable_style.as_one_line())
+            })
+            .unwrap_or_default();
+        let blocks = [format!("Before: {}", path), String::from("After")].map(|title| {
+            Block::default()
+                .borders(Borders::TOP)
+                .title(Span::styled(title, Style::default().fg(Color::Magenta)))
+        });
+        let width = hsplit[0].width.min(hsplit[1].width) as usize;
+
+        // Each item takes as many rows on both sides, so the lines before and after the replacements stay level.
+        let mut before_rows = vec![];
+        let mut after_rows = vec![];
+        let mut selected_row = 0;
+        // How many more lines there are after the replacements, for the line numbers of the lines after them.
+        let mut line_offset = 0isize;
+        let items = self.list[file_start + 1..]
+            .iter()
+            .take_while(|item| item.kind != RgMessageKind::End)
+            .filter(|item| !item.is_hidden());
+        for item in items {
+            if item.index == selected {
+                selected_row = before_rows.len();
+            }
+
+            let line_number_style = Style::default().fg(if item.index == selected {
+                Color::Yellow
+            } else {
+                Color::DarkGray
+            });
+            let line_number = item.line_number().copied();
+            let (before, after) = item.to_split_rows(ctx);
+            let after_line_number = line_number.map(|n| n.saturating_add_signed(line_offset));
+            line_offset += after.len() as isize - before.len() as isize;
+
+            let mut before =
+                Item::wrap_span_lines(number_rows(before, line_number, line_number_style), width);
+            let mut after = Item::wrap_span_lines(
+                number_rows(after, after_line_number, line_number_style),
+                width,
+            );
+            let height = before.len().max(after.len());
+            before.resize(height, Spans::default());
+            after.resize(height, Spans::default());
+            before_rows.extend(before);
+            after_rows.extend(after);
+        }
+
+        // Scroll so the selected item is on the same line as the indicator in the list, or at least visible.
+        let height = r.height.saturating_sub(1) as usize;
+        let indicator_pos = ctx.app_list_state.indicator_pos();
+        let scroll = selected_row
+            .saturating_sub(indicator_pos)
+            .max((selected_row + 1).saturating_sub(height));
+
+        for ((rows, block), r) in vec![before_rows, after_rows]
+            .into_iter()
+            .zip(blocks)
+            .zip(hsplit)
+        {
+            let rows = rows
+                .into_iter()
+                .skip(scroll)
+                .take(height)
+                .collect::<Vec<_>>();
+            f.render_widget(Paragraph::new(rows).block(block), r);
+        }
+    }
+
+    /// Reads the file of the selected item for the preview pane, unless it's the file which was read last.
+    fn read_preview_file(&mut self) {
+        let path = self
+            .list
+            .get(self.list_state.selected_item())
+            .and_then(Item::path_buf);
+        let path = match path {
+            Some(path) => path,
+            None => return,
+        };
+
+        if !matches!(&self.preview_file, Some((read_path, _)) if *read_path == path) {
+            let preview = FilePreview::read(&path, &RgEncoding::from(&self.encoding))
+                .map_err(|e| format!("{:#}", e));
+            self.preview_file = Some((path, preview));
+        }
+    }
+
+    /// Draws the lines of the selected file around the selected match, which is highlighted as it is in the list.
+    /// The lines of the match are the ones `rg` found, and the lines around it are read from the file. If a file
+    /// (rather than a match) is selected, then the lines at the start of the file are shown.
+    fn draw_preview_view<B: Backend>(&self, f: &mut Frame<B>, r: Rect, ctx: &UiItemContext) {
+        let item = match self.list.get(self.list_state.selected_item()) {
+            Some(item) => item,
+            None => return,
+        };
+        let path = item.path_buf().unwrap_or_default();
+        let title = format!(
+            "Preview: {} ({} lines of context, press <, > to change)",
+            path.display()
+                .to_string()
+                .to_printable(self.printable_style.as_one_line()),
+            self.preview_context
+        );
+        let block = Block::default()
+            .borders(Borders::TOP)
+            .title(Span::styled(title, Style::default().fg(Color::Magenta)));
+
+        let preview = match &self.preview_file {
+            Some((read_path, preview)) if *read_path == path => preview,
+            _ => return f.render_widget(block, r),
+        };
+        let preview = match preview {
+            Ok(preview) => preview,
+            Err(e) => {
+                let error = Span::styled(one_line(e), Style::default().fg(Color::Red));
+                return f.render_widget(Paragraph::new(Spans::from(error)).block(block), r);
+            }
+        };
+
+        let context = self.preview_context;
+        let line_number_style = Style::default().fg(Color::DarkGray);
+        let line_rows = |first: usize, lines: &[String]| {
+            lines
+                .iter()
+                .enumerate()
+                .flat_map(|(i, line)| {
+                    let text = format!("{}\n", line).to_printable(ctx.printable_style);
+                    number_rows(
+                        to_rows(&text, Style::default()),
+                        Some(first + i),
+                        line_number_style,
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let (before, matched, after) = match item.line_number().copied() {
+            Some(n) => {
+                let lines = item.lines().map(|lines| lines.to_vec()).unwrap_or_default();
+                let line_count =
+                    lines.split(|b| *b == b'\n').count() - usize::from(lines.ends_with(b"\n"));
+                let after_start = n + line_count;
+                let before_start = n.saturating_sub(context).max(1);
+                (
+                    line_rows(before_start, preview.lines(before_start..=n - 1)),
+                    number_rows(
+                        item.to_split_rows(ctx).0,
+                        Some(n),
+                        line_number_style.fg(Color::Yellow),
+                    ),
+                    line_rows(
+                        after_start,
+                        preview.lines(after_start..=after_start + context - 1),
+                    ),
+                )
+            }
+            None => (
+                vec![],
+                vec![],
+                line_rows(1, preview.lines(1..=context * 2 + 1)),
+            ),
+        };
+
+        let width = r.width as usize;
+        let before = Item::wrap_span_lines(before, width);
+        let matched = Item::wrap_span_lines(matched, width);
+        let after = Item::wrap_span_lines(after, width);
+
+        // Scroll so the match is in the middle of the pane, if there's more context than fits.
+        let height = r.height.saturating_sub(1) as usize;
+        let total = before.len() + matched.len() + after.len();
+        let middle = before.len() + matched.len() / 2;
+        let scroll = middle
+            .saturating_sub(height / 2)
+            .min(total.saturating_sub(height));
+
+        let rows = before
+            .into_iter()
+            .chain(matched)
+            .chain(after)
+            .skip(scroll)
+            .take(height)
+            .collect::<Vec<_>>();
+        f.render_widget(Paragraph::new(rows).block(block), r);
     }
 
     pub(crate) fn main_view_list_rect(&self, term_size: Rect) -> Rect {
@@ -317,7 +789,7 @@ impl App {
             y,
             width,
             height,
-        } = self.get_layouts(term_size).0[0];
+        } = self.split_main_view(self.get_layouts(term_size).0[0]).0;
         let indicator_width = self.list_indicator_width();
         Rect::new(
             x + indicator_width,
@@ -2,53 +2,332 @@ mod app_events;
 mod app_render;
 mod state;
 
-use crate::model::PrintableStyle;
-use crate::rg::de::{RgMessage, Stats};
-use crate::ui::line::Item;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use crate::cli::{self, Args};
+use crate::history::{History, DEFAULT_HISTORY_SIZE};
+use crate::model::{
+    FilePreview, FileSnapshot, Filter, Matcher, Operation, PatternTexts, PrintableStyle,
+    ReplacementOutput, Snapshots,
+};
+use crate::rg::de::{ArbitraryData, RgMessage, RgMessageKind, Stats};
+use crate::ui::line::{Highlighter, Item};
 use state::HelpTextState;
-pub use state::{AppListState, AppState, AppUiState};
+pub use state::{AppListState, AppState, AppUiState, DetailPane, SearchState};
 
 const HELP_TEXT: &str = include_str!("../../../doc/rgr.1.template");
+/// How many lines are shown before and after the selected match in the preview pane, until it's changed.
+const DEFAULT_PREVIEW_CONTEXT: usize = 5;
+/// How many lines the context in the preview pane grows or shrinks by at once.
+const PREVIEW_CONTEXT_STEP: usize = 5;
+
+/// Identifies a match across searches: its path, the offset of the match in the file, and its text.
+type MatchKey = (Option<PathBuf>, usize, Vec<u8>);
+
+/// Whether a match was selected, and its own replacement text (if any).
+type Selection = (bool, Option<String>);
+
+/// The file which was last read for the preview pane, or why it couldn't be read.
+type PreviewFile = (PathBuf, Result<FilePreview, String>);
+
+fn match_keys(item: &Item) -> impl Iterator<Item = (usize, MatchKey)> + '_ {
+    let path = item.path_buf();
+    let offset = item.offset().unwrap_or_default();
+    item.sub_items().iter().map(move |sub_item| {
+        let sub_match = &sub_item.sub_match;
+        (
+            sub_item.index,
+            (
+                path.clone(),
+                offset + sub_match.range.start,
+                sub_match.text.to_vec(),
+            ),
+        )
+    })
+}
 
 pub struct App {
     pub state: AppState,
 
     rg_cmdline: String,
-    stats: Stats,
+    /// The ripgrep config file which was used in the search, if any.
+    config_path: Option<PathBuf>,
+    /// The encoding passed to `rg`, if any (see `--encoding`).
+    encoding: Option<String>,
+    matcher: Matcher,
+    /// The replacement text entered for the matches of particular patterns (kept when searching again).
+    pattern_texts: PatternTexts,
+    stats: Option<Stats>,
+    search_state: SearchState,
+    /// An error to show on the input line, either from the search or from what was entered.
+    error: Option<String>,
+    /// The outcome of the last command, shown on the input line until the next key is pressed.
+    notice: Option<String>,
+    /// A search which should be started in place of the current one, see `App::take_search_request`.
+    search_request: Option<Args>,
+    /// The searches made this session (after those from the history of earlier runs), most recent last.
+    search_history: Vec<String>,
+    search_history_pos: Option<usize>,
+    /// The replacements and searches entered in this and earlier runs.
+    history: History,
+    /// The position in the replacement history of the text being entered, if it was recalled from there.
+    replacement_history_pos: Option<usize>,
+    /// The text which was being entered before an entry was recalled from the replacement history.
+    replacement_draft: Option<String>,
+    /// The text searched for in the replacement history with <control+r>, while searching it.
+    replacement_history_query: Option<String>,
+    /// The selections made before searching again, which are kept for the matches that are found again.
+    selections: HashMap<MatchKey, Selection>,
+    snapshots: Snapshots,
+    /// Whether the matches are in the paths of files which are being renamed (see `--rename`).
+    rename: bool,
+    filter: Option<Filter>,
+    /// The pane shown below the list, if any.
+    detail_pane: Option<DetailPane>,
+    /// How many lines are shown before and after the selected match in the preview pane.
+    preview_context: usize,
+    preview_file: Option<PreviewFile>,
+    /// Highlights the syntax of the lines in the list, unless that's turned off (see `--no-highlight`).
+    highlighter: Option<Highlighter>,
     list: Vec<Item>,
     list_state: AppListState,
     ui_state: AppUiState,
     help_text_state: HelpTextState,
 
     printable_style: PrintableStyle,
+    preserve_case: bool,
+    operation: Operation,
+    output: ReplacementOutput,
 }
 
 impl App {
-    pub fn new(rg_cmdline: String, rg_messages: Vec<RgMessage>) -> App {
-        let mut list = vec![];
-        let mut maybe_stats = None;
-
-        for (i, rg_message) in rg_messages.into_iter().enumerate() {
-            match rg_message {
-                RgMessage::Summary { stats, .. } => {
-                    maybe_stats = Some(stats);
-                    // NOTE: there should only be one RgMessage::Summary, and it should be the last item.
-                    break;
-                }
-                other => list.push(Item::new(i, other)),
-            }
-        }
-
-        App {
+    pub fn new(
+        rg_cmdline: String,
+        matcher: Matcher,
+        preserve_case: bool,
+        output: ReplacementOutput,
+        rg_messages: Vec<RgMessage>,
+    ) -> App {
+        let mut app = App {
             state: AppState::Running,
 
+            search_history: vec![rg_cmdline.clone()],
             rg_cmdline,
-            stats: maybe_stats.expect("failed to find RgMessage::Summary from rg!"),
+            config_path: None,
+            encoding: None,
+            matcher,
+            pattern_texts: PatternTexts::new(),
+            stats: None,
+            search_state: SearchState::Searching,
+            error: None,
+            notice: None,
+            search_request: None,
+            search_history_pos: None,
+            history: History::new(DEFAULT_HISTORY_SIZE),
+            replacement_history_pos: None,
+            replacement_draft: None,
+            replacement_history_query: None,
+            selections: HashMap::new(),
+            snapshots: Snapshots::default(),
+            rename: false,
+            filter: None,
+            detail_pane: None,
+            preview_context: DEFAULT_PREVIEW_CONTEXT,
+            preview_file: None,
+            highlighter: None,
             list_state: AppListState::new(),
-            list,
+            list: vec![],
             ui_state: AppUiState::SelectMatches,
             help_text_state: HelpTextState::new(HELP_TEXT),
             printable_style: PrintableStyle::default(),
+            preserve_case,
+            operation: Operation::default(),
+            output,
+        };
+
+        for rg_message in rg_messages {
+            app.add_rg_message(rg_message);
+        }
+
+        app
+    }
+
+    pub fn set_config_path(&mut self, config_path: Option<PathBuf>) {
+        self.config_path = config_path;
+    }
+
+    pub fn set_highlighter(&mut self, highlighter: Option<Highlighter>) {
+        self.highlighter = highlighter;
+    }
+
+    /// Sets the history of earlier runs, and adds the current search to it.
+    pub fn set_history(&mut self, mut history: History) {
+        history.add_search(&self.rg_cmdline);
+        self.search_history = history
+            .searches()
+            .iter()
+            .filter(|search| *search != &self.rg_cmdline)
+            .cloned()
+            .chain(Some(self.rg_cmdline.clone()))
+            .collect();
+        self.history = history;
+    }
+
+    pub fn set_encoding(&mut self, encoding: Option<String>) {
+        self.encoding = encoding;
+    }
+
+    pub fn set_rename(&mut self, rename: bool) {
+        self.rename = rename;
+    }
+
+    pub fn set_operation(&mut self, operation: Operation) {
+        self.operation = operation;
+    }
+
+    /// Adds a snapshot of a file that was searched, which was taken as its results arrived.
+    pub fn add_snapshot(&mut self, path: PathBuf, snapshot: FileSnapshot) {
+        self.snapshots.insert(path, snapshot);
+    }
+
+    /// Adds a message received from `rg` to the list.
+    pub fn add_rg_message(&mut self, rg_message: RgMessage) {
+        // NOTE: there should only be one RgMessage::Summary, and it should be the last item.
+        if self.stats.is_some() {
+            return;
+        }
+
+        match rg_message {
+            RgMessage::Summary { stats, .. } => {
+                self.stats = Some(stats);
+                self.search_state = SearchState::Complete;
+            }
+            other => {
+                let mut item = Item::new(self.list.len(), other);
+                item.attribute_patterns(&self.matcher);
+                if matches!(item.kind, RgMessageKind::Match) && !self.selections.is_empty() {
+                    let selections = match_keys(&item)
+                        .filter_map(|(i, key)| self.selections.get(&key).map(|s| (i, s.clone())))
+                        .collect::<Vec<_>>();
+                    for (i, (should_replace, replacement)) in selections {
+                        item.set_should_replace(i, should_replace);
+                        item.set_replacement(i, replacement);
+                    }
+                }
+
+                self.list.push(item);
+
+                if self.filter.is_some() {
+                    let idx = self.list.len() - 1;
+                    let file_start = self
+                        .list
+                        .iter()
+                        .rposition(|item| item.kind == RgMessageKind::Begin)
+                        .unwrap_or_default();
+                    self.filter_item(idx, file_start);
+                }
+            }
+        }
+    }
+
+    /// Hides the items which don't match `filter`, or shows all items if there's no filter.
+    pub(crate) fn set_filter(&mut self, filter: Option<Filter>) {
+        self.filter = filter;
+
+        let mut file_start = 0;
+        for idx in 0..self.list.len() {
+            if self.list[idx].kind == RgMessageKind::Begin {
+                file_start = idx;
+            }
+            self.filter_item(idx, file_start);
+        }
+    }
+
+    /// Hides the item at `idx` if its path and lines don't match the filter. A file's `Begin` and `End` items
+    /// are shown if any of the items in the file (which starts at `file_start`) are shown.
+    fn filter_item(&mut self, idx: usize, file_start: usize) {
+        let hidden = match (&self.filter, &self.list[idx].kind) {
+            (None, _) => false,
+            (Some(_), RgMessageKind::Begin) => true,
+            (Some(_), RgMessageKind::End) => self.list[file_start].is_hidden(),
+            (Some(filter), _) => {
+                let item = &self.list[idx];
+                let is_match = |data: Option<&ArbitraryData>| {
+                    data.is_some_and(|data| filter.is_match(&data.lossy_utf8()))
+                };
+                !is_match(item.path()) && !is_match(item.lines())
+            }
+        };
+
+        self.list[idx].set_hidden(hidden);
+        if !hidden {
+            self.list[file_start].set_hidden(false);
+        }
+    }
+
+    /// Called once no more messages will be received from `rg`.
+    pub fn on_search_finished(&mut self) {
+        if self.search_state == SearchState::Searching {
+            self.search_state = SearchState::Complete;
+        }
+    }
+
+    /// Called if the search couldn't be run, or `rg` reported an error.
+    pub fn on_search_failed(&mut self, error: anyhow::Error) {
+        log::warn!("Search failed: {}", error);
+        self.search_state = SearchState::Failed;
+        self.error = Some(error.to_string());
+    }
+
+    pub fn search_state(&self) -> SearchState {
+        self.search_state
+    }
+
+    /// Parses the edited search, and if it's valid clears the list so its results can be received.
+    /// The selections made so far are kept for any matches which are found again.
+    pub(crate) fn search(&mut self, cmdline: &str) {
+        let mut args = match cli::parse_search(cmdline) {
+            Ok(args) => args,
+            Err(e) => {
+                self.error = Some(e.to_string());
+                return;
+            }
+        };
+
+        args.rename = self.rename;
+        let cmdline = cli::search_cmdline(&args);
+        self.search_history.retain(|query| query != &cmdline);
+        self.search_history.push(cmdline.clone());
+        self.history.add_search(&cmdline);
+
+        for item in &self.list {
+            for (i, key) in match_keys(item) {
+                let selection = (
+                    item.get_should_replace(i),
+                    item.get_replacement(i).map(str::to_owned),
+                );
+                self.selections.insert(key, selection);
+            }
         }
+
+        self.rg_cmdline = cmdline;
+        self.config_path = args.config_path.clone();
+        self.encoding = args.encoding.clone();
+        self.preview_file = None;
+        self.matcher = args.matcher();
+        self.stats = None;
+        self.search_state = SearchState::Searching;
+        self.error = None;
+        self.snapshots = Snapshots::default();
+        self.list.clear();
+        self.list_state = AppListState::new();
+        self.ui_state = AppUiState::SelectMatches;
+        self.search_request = Some(args);
+    }
+
+    /// Returns the search which should replace the current one, if one was entered.
+    pub fn take_search_request(&mut self) -> Option<Args> {
+        self.search_request.take()
     }
 }
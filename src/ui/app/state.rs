@@ -2,7 +2,7 @@ use tui::style::{Color, Style};
 use tui::text::Span;
 use tui::widgets::ListState;
 
-use crate::model::ReplacementCriteria;
+use crate::model::{Editor, ReplacementCriteria};
 
 #[derive(Debug)]
 pub struct AppListState {
@@ -34,6 +34,11 @@ impl AppListState {
         &mut self.indicator
     }
 
+    /// The line of the visible window of the main list view which the indicator is on.
+    pub fn indicator_pos(&self) -> usize {
+        self.indicator.selected().unwrap_or_default()
+    }
+
     pub fn set_indicator_pos(&mut self, idx: usize) {
         self.indicator.select(Some(idx));
     }
@@ -63,11 +68,31 @@ impl AppListState {
     }
 }
 
+/// A pane which is shown below the list of matches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DetailPane {
+    /// The lines of the selected file as they are before and after the replacements, side by side.
+    Split,
+    /// The lines of the selected file around the selected match.
+    Preview,
+}
+
+/// Whether results are still being received from `rg`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchState {
+    Searching,
+    /// The search was stopped by the user before it finished.
+    Cancelled,
+    /// The search couldn't be run, or `rg` reported an error.
+    Failed,
+    Complete,
+}
+
 #[derive(Debug)]
 pub enum AppState {
     Running,
     Cancelled,
-    Complete(ReplacementCriteria),
+    Complete(Box<ReplacementCriteria>),
 }
 
 /// Describes the various states that `App` can be in.
@@ -78,9 +103,19 @@ pub enum AppUiState {
     /// The main matches list: select or deselect the found matches.
     SelectMatches,
     /// Prompt the user for the replacement text.
-    InputReplacement(String),
+    InputReplacement(Editor),
+    /// Prompt the user for replacement text for only the selected match.
+    InputOverride(Editor),
+    /// Prompt the user for replacement text for the matches of the pattern which produced the selected match.
+    InputPatternReplacement(Editor),
     /// Ask the user to confirm the replacement.
     ConfirmReplacement(String),
+    /// Edit the pattern and flags passed to `rg`, and search again.
+    EditSearch(String),
+    /// Prompt the user for text to filter the matches list by.
+    InputFilter(String),
+    /// Prompt the user for a `Predicate`, and then select (if `true`) or deselect every match which satisfies it.
+    InputPredicate(bool, String),
 }
 
 impl AppUiState {
@@ -93,21 +128,33 @@ impl AppUiState {
 
     pub fn get_replacement_text(&self) -> Option<&str> {
         match &self {
-            AppUiState::InputReplacement(replacement)
-            | AppUiState::ConfirmReplacement(replacement) => Some(replacement.as_str()),
+            AppUiState::InputReplacement(editor) => Some(editor.text()),
+            AppUiState::ConfirmReplacement(replacement) => Some(replacement.as_str()),
             _ => None,
         }
     }
 
     /// Represent the `AppUiState` as a `Text`.
     /// This is displayed as the "mode" in the stats line.
-    pub fn to_span(&self) -> Span {
+    pub fn to_span(&self) -> Span<'_> {
         let style = Style::default().fg(Color::Black);
         match self {
             AppUiState::Help => Span::styled(" HELP ", style.bg(Color::Green)),
             AppUiState::SelectMatches => Span::styled(" SELECT ", style.bg(Color::Cyan)),
             AppUiState::InputReplacement(_) => Span::styled(" REPLACE ", style.bg(Color::White)),
+            AppUiState::InputOverride(_) => Span::styled(" OVERRIDE ", style.bg(Color::Cyan)),
+            AppUiState::InputPatternReplacement(_) => {
+                Span::styled(" PATTERN ", style.bg(Color::LightMagenta))
+            }
             AppUiState::ConfirmReplacement(_) => Span::styled(" CONFIRM ", style.bg(Color::Red)),
+            AppUiState::EditSearch(_) => Span::styled(" SEARCH ", style.bg(Color::Yellow)),
+            AppUiState::InputFilter(_) => Span::styled(" FILTER ", style.bg(Color::Blue)),
+            AppUiState::InputPredicate(true, _) => {
+                Span::styled(" INCLUDE ", style.bg(Color::Green))
+            }
+            AppUiState::InputPredicate(false, _) => {
+                Span::styled(" EXCLUDE ", style.bg(Color::Magenta))
+            }
         }
     }
 }
@@ -0,0 +1,109 @@
+/// Highlighting of the syntax of the lines in the list, by the extension of the file they're from.
+use std::ops::Range;
+use std::path::Path;
+
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{Theme, ThemeSet};
+use syntect::parsing::SyntaxSet;
+use syntect::util::LinesWithEndings;
+use tui::style::Color;
+
+/// The theme (of those bundled with syntect) which the syntax is coloured with.
+const THEME: &str = "base16-ocean.dark";
+
+/// The colour of each part of an item's lines, as byte ranges of the lines. Text which isn't in any of them is left
+/// in the default colour.
+pub type Highlights = Vec<(Range<usize>, Color)>;
+
+pub struct Highlighter {
+    syntax_set: SyntaxSet,
+    theme: Theme,
+}
+
+impl Highlighter {
+    pub fn new() -> Highlighter {
+        let mut themes = ThemeSet::load_defaults().themes;
+        Highlighter {
+            syntax_set: SyntaxSet::load_defaults_newlines(),
+            theme: themes.remove(THEME).expect("bundled theme is missing"),
+        }
+    }
+
+    /// Returns the colours of `text`, which is some of the lines of the file at `path`. Nothing is coloured if the
+    /// syntax isn't known for the file's extension.
+    /// NOTE: each item's lines are highlighted on their own, so any text which is within a construct which started
+    /// on an earlier line (such as a block comment) isn't coloured as such.
+    pub fn highlight(&self, path: &Path, text: &str) -> Highlights {
+        let syntax = match path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(|ext| self.syntax_set.find_syntax_by_extension(ext))
+        {
+            Some(syntax) => syntax,
+            None => return vec![],
+        };
+
+        let mut highlight_lines = HighlightLines::new(syntax, &self.theme);
+        let mut highlights: Highlights = vec![];
+        let mut offset = 0;
+        for line in LinesWithEndings::from(text) {
+            let regions = match highlight_lines.highlight_line(line, &self.syntax_set) {
+                Ok(regions) => regions,
+                Err(e) => {
+                    log::warn!("Failed to highlight {}: {}", path.display(), e);
+                    return vec![];
+                }
+            };
+
+            for (style, part) in regions {
+                let range = offset..offset + part.len();
+                offset = range.end;
+                if Some(style.foreground) == self.theme.settings.foreground {
+                    continue;
+                }
+
+                let fg = style.foreground;
+                let color = Color::Rgb(fg.r, fg.g, fg.b);
+                match highlights.last_mut() {
+                    // Adjacent parts of the same colour are joined, so there are fewer spans to draw.
+                    Some((last, last_color)) if last.end == range.start && *last_color == color => {
+                        last.end = range.end;
+                    }
+                    _ => highlights.push((range, color)),
+                }
+            }
+        }
+
+        highlights
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::ui::line::Highlighter;
+
+    #[test]
+    fn it_highlights_by_extension() {
+        let highlighter = Highlighter::new();
+        let text = "fn main() {\n    let s = \"foo\";\n}\n";
+
+        let highlights = highlighter.highlight(Path::new("src/main.rs"), text);
+        let highlighted = |part: &str| {
+            let start = text.find(part).unwrap();
+            highlights
+                .iter()
+                .any(|(range, _)| range.start <= start && start + part.len() <= range.end)
+        };
+        assert!(highlighted("fn"));
+        assert!(highlighted("let"));
+        assert!(highlighted("foo"));
+        assert!(!highlighted("s ="));
+
+        assert!(highlighter
+            .highlight(Path::new("README.unknown"), text)
+            .is_empty());
+        assert!(highlighter.highlight(Path::new("main"), text).is_empty());
+    }
+}
@@ -1,15 +1,17 @@
 use std::ops::Range;
 use std::path::PathBuf;
 
-use tui::style::{Color, Style};
+use tui::style::{Color, Modifier, Style};
 use tui::text::{Span, Spans};
 use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
 use crate::format_line_number;
-use crate::model::{Printable, PrintableStyle};
+use crate::model::{
+    pattern_text, preserve_case, Matcher, Operation, PatternTexts, Printable, PrintableStyle,
+};
 use crate::rg::de::{ArbitraryData, RgMessage, RgMessageKind};
 use crate::ui::app::AppUiState;
-use crate::ui::line::SubItem;
+use crate::ui::line::{push_text, Highlighter, Highlights, SubItem};
 use crate::ui::render::UiItemContext;
 
 #[derive(Debug, Clone)]
@@ -26,11 +28,15 @@ pub struct Item {
 
     sub_items: Vec<SubItem>,
 
+    /// Whether the item is hidden by the filter in the main list view.
+    hidden: bool,
     cached_line_count: Option<CachedLineCount>,
+    /// The colours of the syntax of the lines, once they've been highlighted (see `Item::highlight`).
+    highlights: Option<Highlights>,
 }
 
-// This is implemented manually, so the `cached_line_count` field isn't used in
-// equality checks. All other fields should be included.
+// This is implemented manually, so the `hidden`, `cached_line_count` and `highlights` fields (which
+// only affect the view) aren't used in equality checks. All other fields should be included.
 impl PartialEq for Item {
     fn eq(&self, other: &Self) -> bool {
         self.index == other.index
@@ -65,10 +71,20 @@ impl Item {
             kind,
             rg_message,
             sub_items,
+            hidden: false,
             cached_line_count: None,
+            highlights: None,
         }
     }
 
+    pub fn is_hidden(&self) -> bool {
+        self.hidden
+    }
+
+    pub fn set_hidden(&mut self, hidden: bool) {
+        self.hidden = hidden;
+    }
+
     pub fn get_should_replace(&self, idx: usize) -> bool {
         self.sub_items[idx].should_replace
     }
@@ -77,6 +93,25 @@ impl Item {
         self.sub_items[idx].should_replace = should_replace
     }
 
+    pub fn get_replacement(&self, idx: usize) -> Option<&str> {
+        self.sub_items[idx].replacement.as_deref()
+    }
+
+    pub fn set_replacement(&mut self, idx: usize, replacement: Option<String>) {
+        self.sub_items[idx].replacement = replacement
+    }
+
+    /// Finds which of the patterns in `matcher` produced each of the matches in this item, so they can be replaced
+    /// with the replacement text for that pattern (since `rg` doesn't report it).
+    pub fn attribute_patterns(&mut self, matcher: &Matcher) {
+        if let RgMessage::Match { lines, .. } = &self.rg_message {
+            let lines = lines.to_vec();
+            for sub_item in &mut self.sub_items {
+                sub_item.pattern = matcher.pattern_at(&lines, &sub_item.sub_match.range);
+            }
+        }
+    }
+
     pub fn get_should_replace_all(&self) -> bool {
         self.sub_items.iter().all(|s| s.should_replace)
     }
@@ -115,6 +150,14 @@ impl Item {
         }
     }
 
+    pub fn lines(&self) -> Option<&ArbitraryData> {
+        match &self.rg_message {
+            RgMessage::Context { lines, .. } => Some(lines),
+            RgMessage::Match { lines, .. } => Some(lines),
+            _ => None,
+        }
+    }
+
     pub fn replace_count(&self) -> usize {
         self.sub_items.iter().filter(|s| s.should_replace).count()
     }
@@ -137,6 +180,97 @@ impl Item {
         self.path().and_then(|data| data.to_path_buf().ok())
     }
 
+    /// Returns the text which will replace the given `SubItem`, with any capture groups expanded.
+    /// If the `SubItem` has its own replacement then that is used instead of `text`, and otherwise if the pattern
+    /// which produced it has replacement text in `pattern_texts` then that is used.
+    /// If `should_preserve_case` is set, then the replacement is transformed to match the case of the match
+    /// (this isn't done for a `SubItem`'s own replacement, since that was entered for that match specifically).
+    pub fn replacement_text(
+        &self,
+        sub_item: &SubItem,
+        matcher: &Matcher,
+        text: &str,
+        pattern_texts: &PatternTexts,
+        should_preserve_case: bool,
+    ) -> String {
+        let (text, should_preserve_case) = match &sub_item.replacement {
+            Some(replacement) => (replacement.as_str(), false),
+            None => (
+                pattern_text(sub_item, matcher, pattern_texts).unwrap_or(text),
+                should_preserve_case,
+            ),
+        };
+
+        let text = match &self.rg_message {
+            RgMessage::Match { lines, .. } => {
+                matcher.expand(text, &lines.to_vec(), sub_item.sub_match.range.clone())
+            }
+            _ => text.to_owned(),
+        };
+
+        if should_preserve_case {
+            preserve_case(&sub_item.sub_match.text.lossy_utf8(), &text)
+        } else {
+            text
+        }
+    }
+
+    /// Highlights the syntax of the item's lines if it's a match or context, by the extension of its file (which is
+    /// the path of the `Begin` item before it). This is only done once, since it can be slow for long lines.
+    pub fn highlight(&mut self, highlighter: &Highlighter) {
+        if self.highlights.is_some() {
+            return;
+        }
+
+        // NOTE: the highlights are byte ranges of the lines, so lines which aren't valid UTF-8 aren't highlighted.
+        let highlights = match (self.lines(), self.path_buf()) {
+            (Some(lines), Some(path)) => match String::from_utf8(lines.to_vec()) {
+                Ok(text) => highlighter.highlight(&path, &text),
+                Err(_) => vec![],
+            },
+            _ => vec![],
+        };
+        self.highlights = Some(highlights);
+    }
+
+    /// Returns the spans of the text in `range` of `lines` (the item's lines), each coloured by its syntax if it has
+    /// been highlighted. The colours are beneath `style`, so any colour it has takes precedence.
+    fn highlighted_spans(
+        &self,
+        lines: &[u8],
+        range: Range<usize>,
+        printable_style: PrintableStyle,
+        style: Style,
+    ) -> Vec<Span<'static>> {
+        let span = |range: Range<usize>, style: Style| {
+            let content = String::from_utf8_lossy(&lines[range]).to_printable(printable_style);
+            Span::styled(content, style)
+        };
+
+        let mut spans = vec![];
+        let mut offset = range.start;
+        let highlights = self.highlights.as_deref().unwrap_or_default();
+        for (highlight, color) in highlights
+            .iter()
+            .filter(|(highlight, _)| highlight.end > range.start && highlight.start < range.end)
+        {
+            let start = highlight.start.max(offset);
+            if start > offset {
+                spans.push(span(offset..start, style));
+            }
+
+            let end = highlight.end.min(range.end);
+            spans.push(span(start..end, Style::default().fg(*color).patch(style)));
+            offset = end;
+        }
+
+        if offset < range.end || spans.is_empty() {
+            spans.push(span(offset..range.end, style));
+        }
+
+        spans
+    }
+
     pub fn line_count_at(
         &mut self,
         match_idx: usize,
@@ -147,7 +281,7 @@ impl Item {
             RgMessage::Begin { .. } | RgMessage::End { .. } => 0,
             RgMessage::Match { lines, .. } | RgMessage::Context { lines, .. } => {
                 let list_width = list_width as usize;
-                let line_number = self.line_number().unwrap();
+                let line_number = self.line_number().copied();
                 let line_bytes = lines.to_vec();
 
                 let line_bytes = if let Some(start_of_match) = self
@@ -166,8 +300,10 @@ impl Item {
                     .iter()
                     .enumerate()
                     .map(|(i, line)| {
-                        let available_width =
-                            list_width.saturating_sub(format_line_number!(line_number + i).width());
+                        // NOTE: there's no line number for the paths listed when renaming files.
+                        let line_number_width =
+                            line_number.map_or(0, |n| format_line_number!(n + i).width());
+                        let available_width = list_width.saturating_sub(line_number_width);
                         let line_width = line.width();
                         let height = line_width / available_width;
                         if line_width % available_width == 0 && i == lines.len() + 1 {
@@ -193,15 +329,17 @@ impl Item {
             RgMessage::Begin { .. } | RgMessage::End { .. } => 1,
             RgMessage::Match { lines, .. } | RgMessage::Context { lines, .. } => {
                 let list_width = list_width as usize;
-                let line_number = self.line_number().unwrap();
+                let line_number = self.line_number().copied();
                 let lines = lines.to_printable(style);
                 let lines = lines.lines().collect::<Vec<_>>();
                 lines
                     .iter()
                     .enumerate()
                     .map(|(i, line)| {
-                        let available_width =
-                            list_width.saturating_sub(format_line_number!(line_number + i).width());
+                        // NOTE: there's no line number for the paths listed when renaming files.
+                        let line_number_width =
+                            line_number.map_or(0, |n| format_line_number!(n + i).width());
+                        let available_width = list_width.saturating_sub(line_number_width);
                         let line_width = line.width();
                         let height = line_width / available_width;
                         if line_width > 0 && line_width % available_width == 0 {
@@ -222,7 +360,7 @@ impl Item {
         count
     }
 
-    pub fn to_span_lines(&self, ctx: &UiItemContext) -> Vec<Spans> {
+    pub fn to_span_lines(&self, ctx: &UiItemContext) -> Vec<Spans<'_>> {
         let is_replacing = ctx.app_ui_state.is_replacing();
         let is_selected = ctx.app_list_state.selected_item() == self.index;
 
@@ -259,17 +397,25 @@ impl Item {
             RgMessage::Context {
                 lines, line_number, ..
             } => {
-                let mut span_lines = vec![];
-                for (i, line) in lines.to_printable(ctx.printable_style).lines().enumerate() {
-                    let mut spans = vec![];
-                    if i == 0 {
-                        if let Some(n) = line_number {
-                            push_line_number_span!(spans, n);
-                        }
-                    }
+                let lines_bytes = lines.to_vec();
+                let mut span_lines = vec![vec![]];
+                for span in self.highlighted_spans(
+                    &lines_bytes,
+                    0..lines_bytes.len(),
+                    ctx.printable_style,
+                    base_style,
+                ) {
+                    push_text(&mut span_lines, &span.content, span.style);
+                }
+                // The newline at the end of the lines doesn't start another line.
+                if span_lines.len() > 1 && span_lines.last().is_some_and(Vec::is_empty) {
+                    span_lines.pop();
+                }
 
-                    spans.push(Span::styled(line.to_string(), base_style));
-                    span_lines.push(spans);
+                if let Some(n) = line_number {
+                    let mut spans = vec![];
+                    push_line_number_span!(spans, n);
+                    span_lines[0].splice(0..0, spans);
                 }
 
                 span_lines
@@ -280,24 +426,52 @@ impl Item {
             } => {
                 let mut line_number = *line_number;
 
+                // Lines are only deleted or inserted if any of their matches are selected, and then the matches
+                // aren't replaced themselves.
+                let operation = if is_replacing && self.replace_count() > 0 {
+                    ctx.operation
+                } else {
+                    Operation::Substitute
+                };
+                let text_style = if operation == Operation::DeleteLine {
+                    base_style
+                        .fg(Color::Red)
+                        .add_modifier(Modifier::CROSSED_OUT)
+                } else {
+                    base_style
+                };
+
                 // Read the lines as bytes since we split it at the byte ranges that ripgrep gives us in each of the submatches.
                 let lines_bytes = lines.to_vec();
-                let replacement_spans = ctx.replacement_text.map(|text| {
-                    let replacement_style = base_style.fg(Color::Green);
-                    let mut spans = text
-                        .to_printable(ctx.printable_style)
-                        .lines()
-                        .map(|line| Span::styled(line.to_owned(), replacement_style))
-                        .collect::<Vec<_>>();
-
-                    // NOTE: since `"foo\n".lines().collect()` == `vec!["foo"]` we need to make sure the
-                    // last newline isn't trimmed.
-                    if !ctx.printable_style.is_one_line() && text.ends_with('\n') {
-                        spans.push(Span::from(""));
-                    }
+                let replacement_spans = |sub_item: &SubItem| {
+                    ctx.replacement_text.map(|text| {
+                        let text = self.replacement_text(
+                            sub_item,
+                            ctx.matcher,
+                            text,
+                            ctx.pattern_texts,
+                            ctx.preserve_case,
+                        );
+                        let replacement_style = if sub_item.replacement.is_some() {
+                            base_style.fg(Color::Cyan)
+                        } else {
+                            base_style.fg(Color::Green)
+                        };
+                        let mut spans = text
+                            .to_printable(ctx.printable_style)
+                            .lines()
+                            .map(|line| Span::styled(line.to_owned(), replacement_style))
+                            .collect::<Vec<_>>();
+
+                        // NOTE: since `"foo\n".lines().collect()` == `vec!["foo"]` we need to make sure the
+                        // last newline isn't trimmed.
+                        if !ctx.printable_style.is_one_line() && text.ends_with('\n') {
+                            spans.push(Span::from(""));
+                        }
 
-                    spans
-                });
+                        spans
+                    })
+                };
 
                 let mut span_lines = vec![];
                 let mut spans = vec![]; // filled and emptied for each line
@@ -305,14 +479,16 @@ impl Item {
                 macro_rules! push_utf8_slice {
                     ($range:ident) => {
                         {
-                            let mut content = String::from_utf8_lossy(&lines_bytes[$range]).to_printable(ctx.printable_style);
+                            let mut slice_spans = self.highlighted_spans(&lines_bytes, $range, ctx.printable_style, text_style);
                             // remove trailing new line if one exists since lines are already handled
-                            if content.ends_with("\n") {
-                                content.pop();
+                            if let Some(span) = slice_spans.last_mut() {
+                                if span.content.ends_with("\n") {
+                                    span.content.to_mut().pop();
+                                }
                             }
                             // NOTE: don't handle multiple lines in the match because AFAICT ripgrep doesn't return multiline
                             // text in between submatches in a "match" item.
-                            spans.push(Span::styled(content, base_style));
+                            spans.extend(slice_spans);
                         }
                     }
                 }
@@ -348,10 +524,13 @@ impl Item {
                     // Match text, also may contain any leading line numbers and text from before.
                     let confirm_replacement =
                         matches!(ctx.app_ui_state, AppUiState::ConfirmReplacement(_));
-                    if !confirm_replacement || !sub_item.should_replace {
+                    if !confirm_replacement
+                        || !sub_item.should_replace
+                        || operation.is_line_operation()
+                    {
                         let sub_span_lines = sub_item.to_span_lines(ctx, is_selected);
                         let sub_span_lines_len = sub_span_lines.len();
-                        for (i, span) in sub_span_lines.into_iter().enumerate() {
+                        for (i, mut span) in sub_span_lines.into_iter().enumerate() {
                             if i > 0 {
                                 if is_replacing {
                                     push_line_number_span!(spans, "-");
@@ -361,15 +540,19 @@ impl Item {
                                 }
                             }
 
+                            if operation == Operation::DeleteLine {
+                                span.style = span.style.patch(text_style);
+                            }
                             spans.push(span);
                             new_line_if_needed!(sub_span_lines_len, i);
                         }
                     }
 
                     // Replacement text.
-                    if sub_item.should_replace {
-                        if let Some(replacement_span_lines) = replacement_spans.as_ref() {
-                            for (i, span) in replacement_span_lines.iter().enumerate() {
+                    if sub_item.should_replace && !operation.is_line_operation() {
+                        if let Some(replacement_span_lines) = replacement_spans(sub_item) {
+                            let replacement_span_lines_len = replacement_span_lines.len();
+                            for (i, span) in replacement_span_lines.into_iter().enumerate() {
                                 if i == 0 {
                                     // reset the line number
                                     line_number = self.line_number().cloned();
@@ -377,8 +560,8 @@ impl Item {
                                     push_line_number_span!(spans, "+");
                                 }
 
-                                spans.push(span.clone());
-                                new_line_if_needed!(replacement_span_lines.len(), i);
+                                spans.push(span);
+                                new_line_if_needed!(replacement_span_lines_len, i);
                             }
                         }
                     }
@@ -394,6 +577,39 @@ impl Item {
                 }
 
                 span_lines.push(spans);
+
+                // Lines inserted above or below, using the replacement text of the first selected match.
+                if matches!(operation, Operation::InsertAbove | Operation::InsertBelow) {
+                    let sub_item = self.sub_items.iter().find(|s| s.should_replace).unwrap();
+                    let text = self.replacement_text(
+                        sub_item,
+                        ctx.matcher,
+                        ctx.replacement_text.unwrap_or_default(),
+                        ctx.pattern_texts,
+                        ctx.preserve_case,
+                    );
+                    let printable = text.to_printable(ctx.printable_style);
+                    let mut lines = printable.lines().collect::<Vec<_>>();
+                    // NOTE: an empty line is inserted for empty text, and as above the last newline isn't trimmed.
+                    if lines.is_empty()
+                        || (!ctx.printable_style.is_one_line() && text.ends_with('\n'))
+                    {
+                        lines.push("");
+                    }
+
+                    let inserted_span_lines = lines.into_iter().map(|line| {
+                        let mut spans = vec![];
+                        push_line_number_span!(spans, "+");
+                        spans.push(Span::styled(line.to_owned(), base_style.fg(Color::Green)));
+                        spans
+                    });
+                    if operation == Operation::InsertAbove {
+                        span_lines.splice(0..0, inserted_span_lines);
+                    } else {
+                        span_lines.extend(inserted_span_lines);
+                    }
+                }
+
                 span_lines
             }
             RgMessage::End { .. } => vec![vec![Span::from("")]],
@@ -405,7 +621,7 @@ impl Item {
         Self::wrap_span_lines(span_lines, max_width)
     }
 
-    fn wrap_span_lines(span_lines: Vec<Vec<Span>>, max_width: usize) -> Vec<Spans> {
+    pub fn wrap_span_lines(span_lines: Vec<Vec<Span>>, max_width: usize) -> Vec<Spans> {
         span_lines
             .into_iter()
             .flat_map(|spans| {
@@ -427,7 +643,7 @@ impl Item {
                                     chars.drain(..).collect::<String>(),
                                     span.style,
                                 ));
-                                wrapped_spans.push(Spans::from(tmp.drain(..).collect::<Vec<_>>()));
+                                wrapped_spans.push(Spans::from(std::mem::take(&mut tmp)));
                                 len = 0;
                             }
 
@@ -444,7 +660,7 @@ impl Item {
                     }
                 }
 
-                wrapped_spans.push(Spans::from(tmp.drain(..).collect::<Vec<_>>()));
+                wrapped_spans.push(Spans::from(std::mem::take(&mut tmp)));
                 wrapped_spans
             })
             .collect()
@@ -454,6 +670,7 @@ impl Item {
 #[cfg(test)]
 mod tests {
     use std::path::PathBuf;
+    use std::sync::OnceLock;
 
     use base64_simd::STANDARD as base64;
     use insta::assert_debug_snapshot;
@@ -559,7 +776,7 @@ mod tests {
             Item::new(
                 0,
                 RgMessageBuilder::new(kind)
-                    .with_path_base64(base64.encode_to_string(&invalid_utf8_name_bytes))
+                    .with_path_base64(base64.encode_to_string(invalid_utf8_name_bytes))
                     .with_lines_text("foo bar baz")
                     .with_submatches(vec![SubMatch::new_text("foo", 0..3)])
                     .with_stats(Stats::new())
@@ -599,6 +816,9 @@ mod tests {
         );
     }
 
+    static EMPTY_MATCHER: Matcher = Matcher::empty();
+    static EMPTY_PATTERN_TEXTS: OnceLock<PatternTexts> = OnceLock::new();
+
     fn new_ui_item_ctx<'a>(
         replacement_text: Option<&'a str>,
         app_list_state: &'a AppListState,
@@ -612,6 +832,10 @@ mod tests {
         UiItemContext {
             printable_style: PrintableStyle::Hidden,
             replacement_text,
+            pattern_texts: EMPTY_PATTERN_TEXTS.get_or_init(PatternTexts::new),
+            matcher: &EMPTY_MATCHER,
+            preserve_case: false,
+            operation: Operation::default(),
             app_list_state,
             app_ui_state,
             list_rect: Rect::new(0, 0, 80, 24),
@@ -642,7 +866,7 @@ mod tests {
     fn to_span_lines_with_text_input_replacement() {
         let replacement = "foobar";
         let app_list_state = new_app_list_state();
-        let app_ui_state = AppUiState::InputReplacement(String::from(replacement));
+        let app_ui_state = AppUiState::InputReplacement(Editor::new(replacement));
         let ctx = new_ui_item_ctx(Some(replacement), &app_list_state, &app_ui_state);
 
         assert_debug_snapshot!(new_item(RG_JSON_BEGIN).to_span_lines(&ctx));
@@ -678,6 +902,36 @@ mod tests {
         assert_debug_snapshot!(new_item(RG_JSON_END).to_span_lines(&ctx));
     }
 
+    #[test]
+    fn to_span_lines_with_line_operations() {
+        let replacement = "foobar";
+        let app_list_state = new_app_list_state();
+        let app_ui_state = AppUiState::ConfirmReplacement(String::from(replacement));
+        let mut ctx = new_ui_item_ctx(Some(replacement), &app_list_state, &app_ui_state);
+
+        ctx.operation = Operation::DeleteLine;
+        assert_debug_snapshot!(new_item(RG_JSON_MATCH).to_span_lines(&ctx));
+        ctx.operation = Operation::InsertAbove;
+        assert_debug_snapshot!(new_item(RG_JSON_MATCH).to_span_lines(&ctx));
+        ctx.operation = Operation::InsertBelow;
+        assert_debug_snapshot!(new_item(RG_JSON_MATCH).to_span_lines(&ctx));
+    }
+
+    #[test]
+    fn to_span_lines_coloured_by_pattern() {
+        let app_list_state = new_app_list_state();
+        let app_ui_state = AppUiState::SelectMatches;
+        let ctx = new_ui_item_ctx(None, &app_list_state, &app_ui_state);
+
+        let mut item = new_item(RG_JSON_MATCH);
+        item.attribute_patterns(&Matcher::new(
+            &["Item", "rg_msg"],
+            MatcherOptions::default(),
+        ));
+        item.set_should_replace(1, false);
+        assert_debug_snapshot!(item.to_span_lines(&ctx));
+    }
+
     #[test]
     fn to_span_lines_with_deselected_submatch() {
         let mut app_list_state = new_app_list_state();
@@ -698,7 +952,7 @@ mod tests {
         app_list_state.set_selected_item(0);
         app_list_state.set_selected_submatch(0);
         let replacement = "foobar";
-        let app_ui_state = AppUiState::InputReplacement(String::from(replacement));
+        let app_ui_state = AppUiState::InputReplacement(Editor::new(replacement));
         let ctx = new_ui_item_ctx(Some(replacement), &app_list_state, &app_ui_state);
 
         let mut item = new_item(RG_JSON_MATCH);
@@ -741,7 +995,7 @@ mod tests {
     fn to_span_lines_with_base64_lossy_input_replacement() {
         let replacement = "foobar";
         let app_list_state = new_app_list_state();
-        let app_ui_state = AppUiState::InputReplacement(String::from(replacement));
+        let app_ui_state = AppUiState::InputReplacement(Editor::new(replacement));
         let ctx = new_ui_item_ctx(Some(replacement), &app_list_state, &app_ui_state);
 
         assert_debug_snapshot!(new_item(RG_B64_JSON_BEGIN).to_span_lines(&ctx));
@@ -780,11 +1034,111 @@ mod tests {
         assert_debug_snapshot!(new_item(RG_B64_JSON_CONTEXT).to_span_lines(&ctx));
     }
 
+    #[test]
+    fn to_span_lines_with_capture_groups() {
+        let replacement = "<$1|${rest}|$$>";
+        let app_list_state = new_app_list_state();
+        let app_ui_state = AppUiState::InputReplacement(Editor::new(replacement));
+        let matcher = Matcher::new(&["(I)tem", r"rg_(?P<rest>\w+)"], MatcherOptions::default());
+        let mut ctx = new_ui_item_ctx(Some(replacement), &app_list_state, &app_ui_state);
+        ctx.matcher = &matcher;
+
+        assert_debug_snapshot!(new_item(RG_JSON_MATCH).to_span_lines(&ctx));
+    }
+
+    #[test]
+    fn replacement_text_expands_capture_groups() {
+        let item = new_item(RG_JSON_MATCH);
+        let matcher = Matcher::new(&["(I)tem", r"rg_(?P<rest>\w+)"], MatcherOptions::default());
+        let replacement = "<$1|${rest}|$$>";
+
+        assert_eq!(
+            item.replacement_text(
+                &item.sub_items()[0],
+                &matcher,
+                replacement,
+                &PatternTexts::new(),
+                false
+            ),
+            "<I||$>"
+        );
+        assert_eq!(
+            item.replacement_text(
+                &item.sub_items()[1],
+                &matcher,
+                replacement,
+                &PatternTexts::new(),
+                false
+            ),
+            "<msg|msg|$>"
+        );
+        assert_eq!(
+            item.replacement_text(
+                &item.sub_items()[1],
+                &EMPTY_MATCHER,
+                "[$0]",
+                &PatternTexts::new(),
+                false
+            ),
+            "[rg_msg]"
+        );
+    }
+
+    #[test]
+    fn to_span_lines_with_replacement_override() {
+        let replacement = "foobar";
+        let app_list_state = new_app_list_state();
+        let app_ui_state = AppUiState::InputReplacement(Editor::new(replacement));
+        let ctx = new_ui_item_ctx(Some(replacement), &app_list_state, &app_ui_state);
+
+        let mut item = new_item(RG_JSON_MATCH);
+        item.set_replacement(1, Some(String::from("override")));
+        assert_debug_snapshot!(item.to_span_lines(&ctx));
+    }
+
+    #[test]
+    fn to_span_lines_with_replacement_override_select() {
+        let app_list_state = new_app_list_state();
+        let app_ui_state = AppUiState::SelectMatches;
+        let ctx = new_ui_item_ctx(None, &app_list_state, &app_ui_state);
+
+        let mut item = new_item(RG_JSON_MATCH);
+        item.set_replacement(1, Some(String::from("override")));
+        assert_debug_snapshot!(item.to_span_lines(&ctx));
+    }
+
+    #[test]
+    fn replacement_text_uses_override() {
+        let mut item = new_item(RG_JSON_MATCH);
+        item.set_replacement(1, Some(String::from("[$0]")));
+
+        assert_eq!(
+            item.replacement_text(
+                &item.sub_items()[0],
+                &EMPTY_MATCHER,
+                "foo",
+                &PatternTexts::new(),
+                true
+            ),
+            "Foo"
+        );
+        assert_eq!(
+            item.replacement_text(
+                &item.sub_items()[1],
+                &EMPTY_MATCHER,
+                "foo",
+                &PatternTexts::new(),
+                true
+            ),
+            "[rg_msg]"
+        );
+    }
+
     #[test]
     fn to_span_lines_with_multiline_replacement() {
         let replacement = "foobar\nbaz\nasdf";
         let app_list_state = new_app_list_state();
-        let app_ui_state = AppUiState::InputReplacement(String::from(replacement));
+        let app_ui_state = AppUiState::InputReplacement(Editor::new(replacement));
         let ctx = new_ui_item_ctx(Some(replacement), &app_list_state, &app_ui_state);
 
         assert_debug_snapshot!(new_item(RG_JSON_MATCH).to_span_lines(&ctx));
@@ -803,7 +1157,7 @@ mod tests {
     fn to_span_lines_multiline_input_replacement_with_multiline_matches() {
         let replacement = "foobar\nbaz\nasdf";
         let app_list_state = new_app_list_state();
-        let app_ui_state = AppUiState::InputReplacement(String::from(replacement));
+        let app_ui_state = AppUiState::InputReplacement(Editor::new(replacement));
         let ctx = new_ui_item_ctx(Some(replacement), &app_list_state, &app_ui_state);
 
         assert_debug_snapshot!(new_item(RG_JSON_MATCH_MULTILINE).to_span_lines(&ctx));
@@ -837,7 +1191,7 @@ mod tests {
         let mut app_list_state = new_app_list_state();
         app_list_state.set_selected_item(0);
         app_list_state.set_selected_submatch(0);
-        let app_ui_state = AppUiState::InputReplacement(String::from(replacement));
+        let app_ui_state = AppUiState::InputReplacement(Editor::new(replacement));
         let ctx = new_ui_item_ctx(Some(replacement), &app_list_state, &app_ui_state);
 
         assert_debug_snapshot!(new_item(RG_JSON_MATCH_LINE_WRAP).to_span_lines(&ctx));
@@ -857,6 +1211,39 @@ mod tests {
         assert_debug_snapshot!(new_item(RG_JSON_CONTEXT_LINE_WRAP).to_span_lines(&ctx));
     }
 
+    #[test]
+    fn to_span_lines_with_syntax_highlighting() {
+        let replacement = "foobar";
+        let app_list_state = new_app_list_state();
+        let app_ui_state = AppUiState::InputReplacement(Editor::new(replacement));
+        let ctx = new_ui_item_ctx(Some(replacement), &app_list_state, &app_ui_state);
+
+        let new_item = |kind, lines: &str, submatches| {
+            let mut item = Item::new(
+                0,
+                RgMessageBuilder::new(kind)
+                    .with_path_text("src/main.rs")
+                    .with_lines_text(lines)
+                    .with_line_number(1)
+                    .with_offset(0)
+                    .with_submatches(submatches)
+                    .build(),
+            );
+            item.highlight(&Highlighter::new());
+            item
+        };
+
+        // The matches and replacements are drawn as usual, and only the text around them is coloured.
+        let item = new_item(
+            RgMessageKind::Match,
+            "    let item = Item::new(1);\n",
+            vec![SubMatch::new_text("Item", 15..19)],
+        );
+        assert_debug_snapshot!(item.to_span_lines(&ctx));
+        let item = new_item(RgMessageKind::Context, "// one\nlet x = 2;\n", vec![]);
+        assert_debug_snapshot!(item.to_span_lines(&ctx));
+    }
+
     #[test]
     fn line_count_hidden() {
         let w = 80_u16;
@@ -961,7 +1348,7 @@ mod tests {
         let mut app_list_state = new_app_list_state();
         app_list_state.set_selected_item(0);
         app_list_state.set_selected_submatch(0);
-        let app_ui_state = AppUiState::InputReplacement(String::from(replacement));
+        let app_ui_state = AppUiState::InputReplacement(Editor::new(replacement));
         let ctx = new_ui_item_ctx(Some(replacement), &app_list_state, &app_ui_state);
 
         assert_debug_snapshot!(new_item(RG_JSON_MATCH_LINE_WRAP_MULTI).to_span_lines(&ctx));
@@ -971,7 +1358,7 @@ mod tests {
     fn to_span_lines_input_replacement_trailing_line_feed() {
         let replacement = "foobar\n";
         let app_list_state = new_app_list_state();
-        let app_ui_state = AppUiState::InputReplacement(String::from(replacement));
+        let app_ui_state = AppUiState::InputReplacement(Editor::new(replacement));
         let mut ctx = new_ui_item_ctx(Some(replacement), &app_list_state, &app_ui_state);
 
         // Should add a line
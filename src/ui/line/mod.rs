@@ -1,7 +1,11 @@
+pub mod highlight;
 pub mod item;
+pub mod split;
 pub mod sub_item;
 
+pub use highlight::*;
 pub use item::*;
+pub use split::*;
 pub use sub_item::*;
 
 #[macro_export]
@@ -0,0 +1,273 @@
+/// The lines of an item as they are before and after the replacements, for the side-by-side view.
+use std::ops::Range;
+
+use tui::style::{Color, Modifier, Style};
+use tui::text::Span;
+
+use crate::format_line_number;
+use crate::model::{pattern_text, Operation, Printable};
+use crate::ui::line::{Item, SubItem};
+use crate::ui::render::UiItemContext;
+
+/// A line in one side of the side-by-side view.
+pub type SplitRow = Vec<Span<'static>>;
+
+/// Appends `text` to the last row, starting a new row after each newline.
+pub fn push_text(rows: &mut Vec<SplitRow>, text: &str, style: Style) {
+    for (i, part) in text.split('\n').enumerate() {
+        if i > 0 {
+            rows.push(vec![]);
+        }
+        if !part.is_empty() {
+            rows.last_mut()
+                .unwrap()
+                .push(Span::styled(part.to_owned(), style));
+        }
+    }
+}
+
+/// Splits `text` into rows, without the empty row after its last newline.
+pub fn to_rows(text: &str, style: Style) -> Vec<SplitRow> {
+    let mut rows = vec![vec![]];
+    push_text(&mut rows, text, style);
+    if rows.len() > 1 && rows.last().is_some_and(Vec::is_empty) {
+        rows.pop();
+    }
+    rows
+}
+
+/// Adds the line number (counting up from `first`) to the start of each row, if there is one.
+pub fn number_rows(rows: Vec<SplitRow>, first: Option<usize>, style: Style) -> Vec<SplitRow> {
+    rows.into_iter()
+        .enumerate()
+        .map(|(i, mut row)| {
+            if let Some(n) = first {
+                row.insert(0, Span::styled(format_line_number!(n + i), style));
+            }
+            row
+        })
+        .collect()
+}
+
+fn match_style(sub_item: &SubItem) -> Style {
+    if sub_item.should_replace {
+        Style::default().fg(Color::Black).bg(sub_item.color())
+    } else {
+        Style::default().fg(sub_item.color()).bg(Color::DarkGray)
+    }
+}
+
+fn replacement_style(sub_item: &SubItem) -> Style {
+    if sub_item.replacement.is_some() {
+        Style::default().fg(Color::Cyan)
+    } else {
+        Style::default().fg(Color::Green)
+    }
+}
+
+impl Item {
+    /// Returns the rows of a match or context item as they are before and after the replacements (each of which
+    /// may be more or fewer rows than the item has lines). Matches which aren't selected are left as they are,
+    /// and the rest of the text of their lines is dimmed so that they stand out.
+    pub fn to_split_rows(&self, ctx: &UiItemContext) -> (Vec<SplitRow>, Vec<SplitRow>) {
+        let lines = match self.lines() {
+            Some(lines) => lines.to_vec(),
+            None => return (vec![], vec![]),
+        };
+        let printable = |range: Range<usize>| {
+            String::from_utf8_lossy(&lines[range]).to_printable(ctx.printable_style)
+        };
+
+        // Lines are only deleted or inserted if any of their matches are selected (as in the list).
+        let operation = if ctx.app_ui_state.is_replacing() && self.replace_count() > 0 {
+            ctx.operation
+        } else {
+            Operation::Substitute
+        };
+        let text_style = if self.sub_items().iter().all(|s| s.should_replace) {
+            Style::default()
+        } else {
+            Style::default().fg(Color::DarkGray)
+        };
+
+        let mut before_rows = vec![vec![]];
+        let mut after_rows = vec![vec![]];
+        let mut offset = 0;
+        for sub_item in self.sub_items() {
+            let Range { start, end } = sub_item.sub_match.range;
+            let leading = printable(offset..start);
+            push_text(&mut before_rows, &leading, text_style);
+            push_text(&mut after_rows, &leading, text_style);
+
+            let matched = printable(start..end);
+            push_text(&mut before_rows, &matched, match_style(sub_item));
+            match self.split_replacement(sub_item, ctx) {
+                Some(text) if operation == Operation::Substitute => push_text(
+                    &mut after_rows,
+                    &text.to_printable(ctx.printable_style),
+                    replacement_style(sub_item),
+                ),
+                _ => push_text(&mut after_rows, &matched, match_style(sub_item)),
+            }
+
+            offset = end;
+        }
+
+        let trailing = printable(offset..lines.len());
+        push_text(&mut before_rows, &trailing, text_style);
+        push_text(&mut after_rows, &trailing, text_style);
+        // Both sides end with the newline at the end of the lines, which doesn't start another row.
+        for rows in [&mut before_rows, &mut after_rows] {
+            if lines.ends_with(b"\n") && rows.last().is_some_and(Vec::is_empty) {
+                rows.pop();
+            }
+        }
+
+        match operation {
+            Operation::Substitute => {}
+            Operation::DeleteLine => {
+                let deleted_style = Style::default()
+                    .fg(Color::Red)
+                    .add_modifier(Modifier::CROSSED_OUT);
+                for span in before_rows.iter_mut().flatten() {
+                    span.style = span.style.patch(deleted_style);
+                }
+                after_rows.clear();
+            }
+            Operation::InsertAbove | Operation::InsertBelow => {
+                // The lines are inserted using the replacement text of the first selected match.
+                let sub_item = self.sub_items().iter().find(|s| s.should_replace).unwrap();
+                let text = self.replacement_text(
+                    sub_item,
+                    ctx.matcher,
+                    ctx.replacement_text.unwrap_or_default(),
+                    ctx.pattern_texts,
+                    ctx.preserve_case,
+                );
+                let inserted_rows = to_rows(
+                    &text.to_printable(ctx.printable_style),
+                    Style::default().fg(Color::Green),
+                );
+                if operation == Operation::InsertAbove {
+                    after_rows.splice(0..0, inserted_rows);
+                } else {
+                    after_rows.extend(inserted_rows);
+                }
+            }
+        }
+
+        (before_rows, after_rows)
+    }
+
+    /// Returns the text which will replace a match, or `None` if it isn't selected or there's no replacement text
+    /// for it yet (which is the case for most matches until replacement text is entered).
+    fn split_replacement(&self, sub_item: &SubItem, ctx: &UiItemContext) -> Option<String> {
+        if !sub_item.should_replace {
+            return None;
+        }
+
+        let has_own_text = sub_item.replacement.is_some()
+            || pattern_text(sub_item, ctx.matcher, ctx.pattern_texts).is_some();
+        let text = match ctx.replacement_text {
+            Some(text) => text,
+            None if has_own_text => "",
+            None => return None,
+        };
+
+        Some(self.replacement_text(
+            sub_item,
+            ctx.matcher,
+            text,
+            ctx.pattern_texts,
+            ctx.preserve_case,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use tui::layout::Rect;
+    use tui::style::Color;
+
+    use crate::model::*;
+    use crate::rg::de::test_utilities::*;
+    use crate::rg::de::*;
+    use crate::ui::app::{AppListState, AppUiState};
+    use crate::ui::line::*;
+    use crate::ui::render::UiItemContext;
+
+    fn split_rows(
+        item: &Item,
+        app_ui_state: AppUiState,
+        operation: Operation,
+    ) -> (Vec<SplitRow>, Vec<SplitRow>) {
+        let ctx = UiItemContext {
+            replacement_text: app_ui_state.get_replacement_text(),
+            pattern_texts: &PatternTexts::new(),
+            matcher: &Matcher::empty(),
+            preserve_case: false,
+            operation,
+            printable_style: PrintableStyle::Hidden,
+            app_list_state: &AppListState::new(),
+            app_ui_state: &app_ui_state,
+            list_rect: Rect::new(0, 0, 80, 24),
+        };
+        item.to_split_rows(&ctx)
+    }
+
+    /// Returns the text of each row, before and after the replacement "X\nY".
+    fn split_text(item: &Item, operation: Operation) -> (Vec<String>, Vec<String>) {
+        let text = |rows: Vec<SplitRow>| {
+            rows.into_iter()
+                .map(|row| row.into_iter().map(|span| span.content).collect())
+                .collect()
+        };
+        let ui_state = AppUiState::InputReplacement(Editor::new("X\nY"));
+        let (before, after) = split_rows(item, ui_state, operation);
+        (text(before), text(after))
+    }
+
+    #[test]
+    fn it_splits_lines_before_and_after_replacement() {
+        let mut item = Item::new(0, RgMessage::from_str(RG_JSON_MATCH));
+        item.set_should_replace(1, false);
+        assert_eq!(
+            split_text(&item, Operation::Substitute),
+            (
+                vec!["    Item::new(rg_msg)".to_owned()],
+                vec!["    X".to_owned(), "Y::new(rg_msg)".to_owned()]
+            )
+        );
+
+        // The matches' lines are removed (or added to) instead when doing line operations.
+        assert_eq!(
+            split_text(&item, Operation::DeleteLine),
+            (vec!["    Item::new(rg_msg)".to_owned()], vec![])
+        );
+        assert_eq!(
+            split_text(&item, Operation::InsertAbove).1,
+            vec!["X", "Y", "    Item::new(rg_msg)"]
+        );
+
+        // Context is the same before and after.
+        let item = Item::new(1, RgMessage::from_str(RG_JSON_CONTEXT));
+        assert_eq!(
+            split_text(&item, Operation::Substitute),
+            (vec!["  }".to_owned()], vec!["  }".to_owned()])
+        );
+    }
+
+    #[test]
+    fn it_dims_lines_with_deselected_matches() {
+        let mut item = Item::new(0, RgMessage::from_str(RG_JSON_MATCH));
+        let text_color = |item: &Item| {
+            let (before, _) = split_rows(item, AppUiState::SelectMatches, Operation::Substitute);
+            before[0][0].style.fg
+        };
+
+        assert_eq!(text_color(&item), None);
+        item.set_should_replace(0, false);
+        assert_eq!(text_color(&item), Some(Color::DarkGray));
+    }
+}
@@ -1,7 +1,7 @@
 use tui::style::{Color, Modifier, Style};
 use tui::text::Span;
 
-use crate::model::Printable;
+use crate::model::{Operation, Printable};
 use crate::rg::de::SubMatch;
 use crate::ui::render::UiItemContext;
 
@@ -10,26 +10,54 @@ pub struct SubItem {
     pub index: usize,
     pub sub_match: SubMatch,
     pub should_replace: bool,
+    /// Replacement text for only this match, which is used instead of the replacement text for all matches.
+    pub replacement: Option<String>,
+    /// The index of the pattern which produced this match, if it's known (see `Item::attribute_patterns`).
+    pub pattern: Option<usize>,
 }
 
+/// The colours used for matches, so the matches of each pattern are shown in a different colour (matches of an
+/// unknown pattern use the first one).
+const PATTERN_COLORS: &[Color] = &[
+    Color::Red,
+    Color::Magenta,
+    Color::Blue,
+    Color::LightRed,
+    Color::LightMagenta,
+    Color::LightBlue,
+];
+
 impl SubItem {
     pub fn new(index: usize, sub_match: SubMatch) -> SubItem {
         SubItem {
             index,
             sub_match,
             should_replace: true,
+            replacement: None,
+            pattern: None,
         }
     }
 }
 
 impl SubItem {
+    /// The colour of the match, depending on the pattern which produced it.
+    pub fn color(&self) -> Color {
+        let i = self.pattern.unwrap_or_default();
+        PATTERN_COLORS[i % PATTERN_COLORS.len()]
+    }
+
     /// A SubItem contains the "match". A match _may_ be over multiple lines, but there will only ever
     /// be a single span on each line. So this returns a list of "lines": one span for each line.
-    pub fn to_span_lines(&self, ctx: &UiItemContext, is_item_selected: bool) -> Vec<Span> {
+    pub fn to_span_lines(&self, ctx: &UiItemContext, is_item_selected: bool) -> Vec<Span<'_>> {
+        let color = self.color();
         let mut s = Style::default();
         if ctx.app_ui_state.is_replacing() {
             if self.should_replace {
-                s = s.fg(Color::Red).add_modifier(Modifier::CROSSED_OUT);
+                s = s.fg(color);
+                // When inserting lines the matches themselves are kept.
+                if matches!(ctx.operation, Operation::Substitute | Operation::DeleteLine) {
+                    s = s.add_modifier(Modifier::CROSSED_OUT);
+                }
             }
         } else if is_item_selected && ctx.app_list_state.selected_submatch() == self.index {
             if self.should_replace {
@@ -37,10 +65,12 @@ impl SubItem {
             } else {
                 s = s.fg(Color::Yellow).bg(Color::DarkGray);
             }
+        } else if self.should_replace && self.replacement.is_some() {
+            s = s.fg(Color::Black).bg(Color::Cyan);
         } else if self.should_replace {
-            s = s.fg(Color::Black).bg(Color::Red);
+            s = s.fg(Color::Black).bg(color);
         } else {
-            s = s.fg(Color::Red).bg(Color::DarkGray);
+            s = s.fg(color).bg(Color::DarkGray);
         }
 
         self.sub_match
@@ -1,12 +1,20 @@
 use tui::layout::Rect;
 
-use crate::model::PrintableStyle;
+use crate::model::{Matcher, Operation, PatternTexts, PrintableStyle};
 use crate::ui::app::{AppListState, AppUiState};
 
 /// Used when building the UI from the App's state.
 pub struct UiItemContext<'a> {
     /// The replacement text the user has entered.
     pub replacement_text: Option<&'a str>,
+    /// The replacement text the user has entered for the matches of particular patterns.
+    pub pattern_texts: &'a PatternTexts,
+    /// Used to expand capture groups in the replacement text.
+    pub matcher: &'a Matcher,
+    /// Whether the replacement should preserve the case of each match.
+    pub preserve_case: bool,
+    /// What is done with each line containing selected matches.
+    pub operation: Operation,
     /// The current state of the matches list.
     pub app_list_state: &'a AppListState,
     /// The current UI state of the App.
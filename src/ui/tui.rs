@@ -4,27 +4,74 @@ use std::thread;
 use std::time::{Duration, Instant};
 
 use anyhow::Result;
-use crossterm::event::{self, DisableMouseCapture, EnableMouseCapture};
+use crossterm::event::{self, DisableMouseCapture, EnableMouseCapture, Event};
 use crossterm::execute;
 use crossterm::terminal::{self, EnterAlternateScreen, LeaveAlternateScreen};
 use tui::{backend::CrosstermBackend, Terminal};
 
+use crate::cli::{self, Args};
+use crate::history::{self, History};
 use crate::model::ReplacementCriteria;
-use crate::rg::de::RgMessage;
-use crate::ui::app::{App, AppState};
+use crate::rg::search::{Search, SearchEvent, SearchHandle};
+use crate::ui::app::{App, AppState, SearchState};
+use crate::ui::line::Highlighter;
+use crate::util;
+
+/// The events handled by the main loop.
+enum TuiEvent {
+    Input(Event),
+    /// An event from a search, along with which search it came from (each time the search is edited, a new one
+    /// is started).
+    Search(usize, SearchEvent),
+}
+
+/// Receives the results of a search as they arrive (the search is cancelled when the handle is dropped).
+fn spawn_search(search: Search, generation: usize, tx: mpsc::Sender<TuiEvent>) -> SearchHandle {
+    search.spawn(move |event| {
+        // NOTE: this only fails once the interface has closed.
+        let _ = tx.send(TuiEvent::Search(generation, event));
+    })
+}
 
 pub struct Tui {
     app: App,
+    search: Search,
 }
 
 impl Tui {
-    pub fn new(rg_cmdline: String, rg_messages: Vec<RgMessage>) -> Tui {
-        Tui {
-            app: App::new(rg_cmdline, rg_messages),
+    pub fn new(args: &Args, mut search: Search) -> Tui {
+        let mut app = App::new(
+            cli::search_cmdline(args),
+            args.matcher(),
+            args.preserve_case,
+            args.replacement_output(),
+            vec![],
+        );
+        app.set_config_path(args.config_path.clone());
+        app.set_encoding(args.encoding.clone());
+        app.set_rename(args.rename);
+        app.set_operation(args.operation());
+        // NOTE: when renaming, the lines are paths rather than the contents of the files.
+        if !args.no_highlight && !args.rename {
+            app.set_highlighter(Some(Highlighter::new()));
         }
+
+        let history_size = history::configured_size();
+        app.set_history(match util::data_dir() {
+            Some(data_dir) => History::load(data_dir, history_size),
+            None => History::new(history_size),
+        });
+
+        // NOTE: when renaming files their contents don't matter, so there's no need to read them.
+        search.set_snapshots(!args.rename);
+
+        Tui { app, search }
     }
 
-    pub fn start(mut self) -> Result<Option<ReplacementCriteria>> {
+    /// Runs the interface until it's closed. If the replacements were accepted, then they're returned along with
+    /// the arguments of the search if it was edited.
+    pub fn start(self) -> Result<Option<(ReplacementCriteria, Option<Args>)>> {
+        let Tui { mut app, search } = self;
         terminal::enable_raw_mode()?;
 
         let mut stdout = io::stdout();
@@ -37,34 +84,91 @@ impl Tui {
         // Setup input handling
         let (tx, rx) = mpsc::channel();
 
+        let input_tx = tx.clone();
         thread::spawn(move || loop {
-            match tx.send(event::read().expect("failed to read event from terminal")) {
+            let event = event::read().expect("failed to read event from terminal");
+            match input_tx.send(TuiEvent::Input(event)) {
                 Ok(_) => {}
                 Err(e) => log::warn!("failed to send event to the main thread: {}", e),
             }
         });
 
+        let mut generation = 0;
+        let mut search = spawn_search(search, generation, tx.clone());
+        let mut edited_args = None;
+
         term.clear()?;
 
         loop {
             let before_draw = Instant::now();
-            term.draw(|mut f| self.app.draw(&mut f))?;
+            term.draw(|f| app.draw(f))?;
 
             // If drawing to the terminal is slow, flush all keyboard events so they're not buffered.
             // (Otherwise with very slow updates, the user has to wait for all keyboard events to be processed
             // before being able to quit the app, etc).
-            if before_draw.elapsed() > Duration::from_millis(20) {
-                while let Ok(_) = rx.try_recv() {}
+            // NOTE: this isn't done while searching, since drawing is often slow then and the user must be able to
+            // stop the search.
+            let mut events = vec![];
+            if before_draw.elapsed() > Duration::from_millis(20)
+                && app.search_state() != SearchState::Searching
+            {
+                events.extend(rx.try_iter().filter(|e| matches!(e, TuiEvent::Search(..))));
             }
 
-            let event = rx.recv()?;
-            let term_size = term.get_frame().size();
-            self.app.on_event(term_size, event)?;
+            // Handle everything which has arrived since the last draw, so we don't draw for every search result.
+            if events.is_empty() {
+                events.push(rx.recv()?);
+            }
+            events.extend(rx.try_iter());
 
-            match self.app.state {
-                AppState::Running => continue,
-                AppState::Cancelled => return Ok(None),
-                AppState::Complete(replacement_criteria) => return Ok(Some(replacement_criteria)),
+            let term_size = term.get_frame().size();
+            for event in events {
+                match event {
+                    TuiEvent::Input(event) => app.on_event(term_size, event)?,
+                    // Ignore anything from searches which have since been replaced.
+                    TuiEvent::Search(g, _) if g != generation => {}
+                    TuiEvent::Search(_, SearchEvent::Snapshot(path, snapshot)) => {
+                        app.add_snapshot(path, snapshot)
+                    }
+                    TuiEvent::Search(_, SearchEvent::Message(rg_message)) => {
+                        app.add_rg_message(rg_message)
+                    }
+                    TuiEvent::Search(_, SearchEvent::Finished(result)) => match result {
+                        Ok(()) => app.on_search_finished(),
+                        // If the first search fails there's nothing to show, but edited searches can be fixed.
+                        Err(e) if generation == 0 => return Err(e),
+                        Err(e) => app.on_search_failed(e),
+                    },
+                }
+
+                if app.search_state() == SearchState::Cancelled {
+                    search.cancel();
+                }
+
+                if let Some(args) = app.take_search_request() {
+                    search.cancel();
+                    generation += 1;
+                    log::debug!(
+                        "Searching again with args: {:?}",
+                        args.rg_args().collect::<Vec<_>>()
+                    );
+                    match args.search() {
+                        Ok(mut new_search) => {
+                            new_search.set_snapshots(!args.rename);
+                            search = spawn_search(new_search, generation, tx.clone());
+                        }
+                        Err(e) => app.on_search_failed(e),
+                    }
+                    edited_args = Some(args);
+                }
+
+                match app.state {
+                    AppState::Running => continue,
+                    AppState::Cancelled => return Ok(None),
+                    AppState::Complete(replacement_criteria) => {
+                        return Ok(Some((*replacement_criteria, edited_args)))
+                    }
+                }
             }
         }
     }
@@ -1,3 +1,50 @@
+use std::env;
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+
+use clap::crate_name;
+use sha2::{Digest, Sha256};
+
+/// The directory where repgrep keeps its logs.
+pub fn state_dir() -> PathBuf {
+    env::temp_dir().join(format!(".{}", crate_name!()))
+}
+
+/// The directory where repgrep keeps what it remembers between runs, such as the history of replacements.
+/// This is within the user's data directory (e.g. `~/.local/share` on Linux), if there is one.
+pub fn data_dir() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join(crate_name!()))
+}
+
+/// The directory where repgrep keeps the journal of the last replacement run (see `--undo`).
+/// This is within the user's state directory (e.g. `~/.local/state` on Linux), or their data directory if there
+/// isn't one, so that it's private to the user and survives a reboot.
+pub fn journal_dir() -> Option<PathBuf> {
+    dirs::state_dir()
+        .or_else(dirs::data_dir)
+        .map(|dir| dir.join(crate_name!()))
+}
+
+/// Returns the SHA-256 hash of `bytes`, as a hex string.
+pub fn hash(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+/// Asks the user a yes or no question on STDERR, and returns whether they answered yes.
+pub fn confirm(question: &str) -> bool {
+    eprint!("{} [y/N] ", question);
+    let _ = io::stderr().flush();
+
+    let mut answer = String::new();
+    match io::stdin().lock().read_line(&mut answer) {
+        Ok(_) => matches!(answer.trim(), "y" | "Y" | "yes"),
+        Err(e) => {
+            log::warn!("Failed to read answer: {}", e);
+            false
+        }
+    }
+}
+
 pub fn clamp(val: usize, min: usize, max: usize) -> usize {
     if val <= min {
         min